@@ -0,0 +1,88 @@
+//! Audio output of the console. The samples are produced at the CPU clock rate, the mixer averages them down to
+//! [`SAMPLE_RATE`] so a frontend (or the WAV writer) can consume them directly.
+//!
+//! The APU itself isn't emulated yet, so for now the only source is the expansion audio of the cartridge which
+//! the Famicom mixes into the APU output on the cartridge connector.
+
+pub mod wav;
+
+use std::collections::VecDeque;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+pub const NTSC_CPU_CLOCK_RATE: f64 = 1_789_773.0;
+pub const PAL_CPU_CLOCK_RATE: f64 = 1_662_607.0;
+pub const DENDY_CPU_CLOCK_RATE: f64 = 1_773_448.0;
+
+// nobody consumes the samples when running the nestest trace, so keep at most one second around
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize;
+
+pub struct Mixer {
+    cycles_per_sample: f64,
+    cycles_until_sample: f64,
+    sum: f32,
+    count: u32,
+    samples: VecDeque<f32>,
+}
+
+impl Mixer {
+    pub fn new(cpu_clock_rate: f64) -> Self {
+        let cycles_per_sample = cpu_clock_rate / SAMPLE_RATE as f64;
+        Mixer {
+            cycles_per_sample,
+            cycles_until_sample: cycles_per_sample,
+            sum: 0.0,
+            count: 0,
+            samples: VecDeque::with_capacity(MAX_BUFFERED_SAMPLES),
+        }
+    }
+
+    /// Called once per CPU cycle with the current output of every source.
+    pub fn tick(&mut self, expansion: f32) {
+        self.sum += expansion;
+        self.count += 1;
+
+        self.cycles_until_sample -= 1.0;
+        if self.cycles_until_sample <= 0.0 {
+            self.cycles_until_sample += self.cycles_per_sample;
+
+            if self.samples.len() >= MAX_BUFFERED_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(self.sum / self.count as f32);
+            self.sum = 0.0;
+            self.count = 0;
+        }
+    }
+
+    /// Takes all samples produced since the last call, in the range 0.0 - 1.0.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mixer_averages_down_to_sample_rate() {
+        let mut mixer = Mixer::new(NTSC_CPU_CLOCK_RATE);
+        for cycle in 0..NTSC_CPU_CLOCK_RATE as usize / 10 {
+            mixer.tick(if cycle % 2 == 0 { 1.0 } else { 0.0 });
+        }
+
+        let samples = mixer.take_samples();
+        assert!(samples.len().abs_diff(SAMPLE_RATE as usize / 10) <= 1);
+        assert!(samples.iter().all(|sample| (sample - 0.5).abs() < 0.05));
+        assert!(mixer.take_samples().is_empty());
+    }
+
+    #[test]
+    fn test_mixer_buffer_is_capped() {
+        let mut mixer = Mixer::new(SAMPLE_RATE as f64);
+        for _ in 0..MAX_BUFFERED_SAMPLES + 10 {
+            mixer.tick(0.0);
+        }
+        assert_eq!(mixer.take_samples().len(), MAX_BUFFERED_SAMPLES);
+    }
+}
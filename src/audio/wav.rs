@@ -0,0 +1,63 @@
+//! Writes the mixer output as 16 bit mono PCM WAV file.
+//! http://soundfile.sapp.org/doc/WaveFormat/
+
+use super::SAMPLE_RATE;
+
+// the console removes the DC offset of its output with a ~90 Hz high-pass filter
+const HIGH_PASS_HZ: f32 = 90.0;
+
+pub fn encode(samples: &[f32]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_size).to_le_bytes());
+    wav.extend(b"WAVE");
+    wav.extend(b"fmt ");
+    wav.extend(16u32.to_le_bytes()); // size of the format chunk
+    wav.extend(1u16.to_le_bytes()); // PCM
+    wav.extend(1u16.to_le_bytes()); // mono
+    wav.extend(SAMPLE_RATE.to_le_bytes());
+    wav.extend((SAMPLE_RATE * 2).to_le_bytes()); // bytes per second
+    wav.extend(2u16.to_le_bytes()); // bytes per sample
+    wav.extend(16u16.to_le_bytes()); // bits per sample
+    wav.extend(b"data");
+    wav.extend(data_size.to_le_bytes());
+
+    let rc = 1.0 / (2.0 * std::f32::consts::PI * HIGH_PASS_HZ);
+    let alpha = rc / (rc + 1.0 / SAMPLE_RATE as f32);
+    let mut previous_input = samples.first().copied().unwrap_or(0.0);
+    let mut previous_output = 0.0;
+    for &sample in samples {
+        let output = alpha * (previous_output + sample - previous_input);
+        previous_input = sample;
+        previous_output = output;
+
+        let pcm = (output * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        wav.extend(pcm.to_le_bytes());
+    }
+
+    wav
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let wav = encode(&[0.0; 10]);
+        assert_eq!(wav.len(), 44 + 20);
+        assert_eq!(wav[0..4], *b"RIFF");
+        assert_eq!(wav[4..8], 56u32.to_le_bytes());
+        assert_eq!(wav[24..28], SAMPLE_RATE.to_le_bytes());
+        assert_eq!(wav[40..44], 20u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_dc_offset_is_removed() {
+        let wav = encode(&[0.5; 44100]);
+        let last = i16::from_le_bytes([wav[wav.len() - 2], wav[wav.len() - 1]]);
+        assert_eq!(last, 0);
+    }
+}
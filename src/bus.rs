@@ -28,7 +28,15 @@
 //! | Zero Page     |       |               |
 //! |_______________| $0000 |_______________|
 //! ```
-use crate::{cartridge::Rom, cpu::Mem, ppu::NesPPU};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    audio::Mixer,
+    cartridge::{Cartridge, Region, Rom},
+    cpu::Mem,
+    ppu::NesPPU,
+    render::frame::Frame,
+};
 
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
@@ -44,47 +52,122 @@ const PPU_DATA_REGISTER: u16 = 0x2007;
 const PPU_REGISTERS_MIRROR_START: u16 = 0x2008;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
 const PPU_DIRECT_MEMORY_ACCESS_REGISTER: u16 = 0x4014;
+const APU_AND_IO_REGISTERS: u16 = 0x4000;
+const APU_AND_IO_REGISTERS_END: u16 = 0x401F;
+
+// one dummy cycle and a read and write cycle for each of the 256 bytes
+const OAM_DMA_CYCLES: usize = 513;
 
-const PRG_ROM: u16 = 0x8000;
-const PRG_ROM_END: u16 = 0xFFFF;
+const CARTRIDGE_SPACE: u16 = 0x4020;
+const CARTRIDGE_SPACE_END: u16 = 0xFFFF;
 
 pub struct Bus {
     cpu_vram: [u8; 2048],
-    prg_rom: Vec<u8>,
+    cartridge: Rc<RefCell<Cartridge>>,
     ppu: NesPPU,
+    mixer: Mixer,
+    region: Region,
+    // PAL consoles don't run a whole number of PPU dots per CPU cycle, the rest is carried over to the next tick
+    ppu_dots_remainder: u16,
     cycles: usize,
+    frames: usize,
 }
 
 impl Bus {
     pub fn new(rom: Rom) -> Self {
-        let ppu = NesPPU::new(rom.chr_rom, rom.screen_mirroring);
+        Bus::with_cartridge(Cartridge::new(rom))
+    }
+
+    /// For everything that isn't loaded from a ROM file, like the Famicom Disk System.
+    pub fn with_cartridge(cartridge: Cartridge) -> Self {
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let ppu = NesPPU::new(Rc::clone(&cartridge));
 
         Bus {
             cpu_vram: [0; 2048],
-            prg_rom: rom.prg_rom,
+            cartridge,
             ppu,
+            mixer: Mixer::new(Region::Ntsc.cpu_clock_rate()),
+            region: Region::Ntsc,
+            ppu_dots_remainder: 0,
             cycles: 0,
+            frames: 0,
         }
     }
 
     pub fn tick(&mut self, cycles: u8) {
         self.cycles += cycles as usize;
-        self.ppu.tick(cycles * 3); // times 3 since the PPU clock ticks 3 times faster
+        // the PPU clock ticks 3 times faster (3.2 times on PAL consoles)
+        let (dots, per_cycles) = self.region.ppu_dots_per_cpu_cycle();
+        let dots = cycles as u16 * dots + self.ppu_dots_remainder;
+        self.ppu_dots_remainder = dots % per_cycles;
+        if self.ppu.tick((dots / per_cycles) as u8) {
+            self.frames += 1;
+        }
+
+        let mut cartridge = self.cartridge.borrow_mut();
+        for _ in 0..cycles {
+            cartridge.cpu_tick();
+            self.mixer.tick(cartridge.audio_output());
+        }
+    }
+
+    /// Switches the PPU and CPU timing to the one of the region, the audio samples follow the CPU clock rate.
+    /// Has to be called before running the game.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_dots_remainder = 0;
+        self.ppu.set_region(region);
+        self.mixer = Mixer::new(region.cpu_clock_rate());
+    }
+
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.ppu.set_sprite_limit(enabled);
+    }
+
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.mixer.take_samples()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames
+    }
+
+    /// The picture the PPU rendered so far, see [`NesPPU::frame`].
+    pub fn frame(&self) -> &Frame {
+        self.ppu.frame()
+    }
+
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.cartridge.borrow().save_data()
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.cartridge.borrow().disk_side_count()
+    }
+
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.cartridge.borrow_mut().insert_disk(side);
     }
 
     pub fn poll_nmi_status(&mut self) -> Option<u8> {
         self.ppu.take_nmi_interrupt()
     }
 
-    /// PRG Rom Size might be 16 KiB or 32 KiB.
-    /// Because [0x8000 … 0x10000] mapped region is 32 KiB of addressable space, the upper 16 KiB needs to be mapped to the lower 16 KiB (if a game has only 16 KiB of PRG ROM)
-    fn read_prg_rom(&self, mut addr: u16) -> u8 {
-        addr -= 0x8000;
-        if self.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-            // mirror if needed
-            addr %= 0x4000;
+    pub fn poll_irq_status(&self) -> bool {
+        self.cartridge.borrow().irq_pending()
+    }
+
+    /// Copies the CPU page `page` into the OAM, the CPU is stalled for 513 cycles meanwhile.
+    fn oam_dma(&mut self, page: u8) {
+        let mut data = [0; 256];
+        for (offset, value) in data.iter_mut().enumerate() {
+            *value = self.mem_read(u16::from_be_bytes([page, offset as u8]));
+        }
+        self.ppu.write_to_oam_dma_register(&data);
+        for _ in 0..OAM_DMA_CYCLES {
+            self.tick(1);
         }
-        self.prg_rom[addr as usize]
     }
 }
 
@@ -114,16 +197,20 @@ impl Mem for Bus {
                 let mirror_down_addr = addr & 0b0010_0000_0000_0111;
                 self.mem_read(mirror_down_addr)
             }
-            PRG_ROM..=PRG_ROM_END => self.read_prg_rom(addr),
-
-            _ => {
-                println!("Ignoring mem access at {}", addr);
-                0
-            }
+            // the APU and the controllers aren't emulated yet, but games access them all the time
+            APU_AND_IO_REGISTERS..=APU_AND_IO_REGISTERS_END => 0,
+            // everything from here on is handled by the mapper on the cartridge
+            CARTRIDGE_SPACE..=CARTRIDGE_SPACE_END => self.cartridge.borrow_mut().read_prg(addr),
         }
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
+        if let PPU_CTRL_REGISTER..=PPU_DATA_REGISTER = addr {
+            self.cartridge
+                .borrow_mut()
+                .notify_ppu_register_write(addr, data);
+        }
+
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b111_1111_1111;
@@ -137,17 +224,57 @@ impl Mem for Bus {
             PPU_SCROLL_REGISTER => self.ppu.write_to_scroll_register(data),
             PPU_ADDR_REGISTER => self.ppu.write_to_addr_register(data),
             PPU_DATA_REGISTER => self.ppu.write_to_data_register(data),
-            // TODO:
-            PPU_DIRECT_MEMORY_ACCESS_REGISTER => {
-                println!("Ignoring mem write-access for OAM_DMA register")
-            }
+            PPU_DIRECT_MEMORY_ACCESS_REGISTER => self.oam_dma(data),
             PPU_REGISTERS_MIRROR_START..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b0010_0000_0000_0111;
                 self.mem_write(mirror_down_addr, data);
             }
-            PRG_ROM..=PRG_ROM_END => panic!("Attempt to write to Cartridge ROM space"),
+            APU_AND_IO_REGISTERS..=APU_AND_IO_REGISTERS_END => {}
+            CARTRIDGE_SPACE..=CARTRIDGE_SPACE_END => {
+                self.cartridge.borrow_mut().write_prg(addr, data)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::test_mapper_rom;
+
+    fn cycles_per_frame(region: Region) -> usize {
+        let mut bus = Bus::new(test_mapper_rom(0, vec![0; 0x4000], vec![0; 0x2000]));
+        bus.set_region(region);
+        while bus.frame_count() == 0 {
+            bus.tick(1);
+        }
+        bus.cycles
+    }
 
-            _ => println!("Ignoring mem write-access at {}", addr),
+    #[test]
+    fn test_region_timing() {
+        // 341 * 262 / 3 rounded up, rendering is off so there is no skipped dot
+        assert_eq!(cycles_per_frame(Region::Ntsc), 29781);
+        // 341 * 312 / 3.2
+        assert_eq!(cycles_per_frame(Region::Pal), 33248);
+        // 341 * 312 / 3
+        assert_eq!(cycles_per_frame(Region::Dendy), 35464);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = Bus::new(test_mapper_rom(0, vec![0; 0x4000], vec![0; 0x2000]));
+        for offset in 0..256 {
+            bus.mem_write(0x0300 + offset, offset as u8);
         }
+        bus.mem_write(PPU_OAM_ADDRESS_REGISTER, 0x10);
+        bus.mem_write(PPU_DIRECT_MEMORY_ACCESS_REGISTER, 0x03);
+        assert_eq!(bus.cycles, OAM_DMA_CYCLES);
+
+        // the copy starts at the OAM address and wraps around
+        bus.mem_write(PPU_OAM_ADDRESS_REGISTER, 0x10);
+        assert_eq!(bus.mem_read(PPU_OAM_DATA_REGISTER), 0x00);
+        bus.mem_write(PPU_OAM_ADDRESS_REGISTER, 0x0f);
+        assert_eq!(bus.mem_read(PPU_OAM_DATA_REGISTER), 0xff);
     }
 }
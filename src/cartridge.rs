@@ -1,191 +0,0 @@
-const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A]; // part of the header
-const PRG_ROM_PAGE_SIZE: usize = 16384; // 16 kB page size of PRG ROM
-const CHR_ROM_PAGE_SIZE: usize = 8192; // 8 kB page size CHR ROM
-
-#[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, PartialEq)]
-pub enum Mirroring {
-    VERTICAL,
-    HORIZONTAl,
-    FOURSCREEN,
-}
-
-/// For ROMS in the iNES format.
-pub struct Rom {
-    pub prg_rom: Vec<u8>,
-    pub chr_rom: Vec<u8>,
-    pub mapper: u8,
-    pub screen_mirroring: Mirroring,
-}
-
-impl Rom {
-    pub fn new(raw: &[u8]) -> Result<Rom, String> {
-        if raw[0..4] != NES_TAG {
-            return Err("File is not in iNES file format".to_string());
-        }
-
-        // raw 6 and 7 represent the control bytes 1 and 2 respectively
-        // control byte 1 bits 7-4 contain the 4 lower bits of the mapper type
-        // control byte 2 bits 7-4 contain the 4 upper bits of the mapper type
-        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
-
-        // control byte 2 bits 3-2 contain iNES version information
-        // if bit(3, 2) == 10 => iNES 2.0
-        // if bit(3, 2) == 00 => iNES 1.0
-        let ines_ver = (raw[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err("iNES 2.0 format is not supported".to_string());
-        }
-
-        // control byte 1 bit 3 means four-screen VRAM layout
-        let four_screen = raw[6] & 0b1000 != 0;
-        // if control byte 1 bit 0 is equal to 0 it means horizontal mirroring, otherwise vertical
-        let vertical_mirroring = raw[6] & 0b1 != 0;
-        let screen_mirroring = match (four_screen, vertical_mirroring) {
-            (true, _) => Mirroring::FOURSCREEN,
-            (false, true) => Mirroring::VERTICAL,
-            (false, false) => Mirroring::HORIZONTAl,
-        };
-
-        // raw 4 and 5 represent number of PRG and CHR ROM pages respectively
-        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
-
-        // control byte 1 bit 2 represents the need for a 512-byte trainer at memory section 0x7000 - 0x71FF
-        // it's a data section created by Famicom to keep their own mapping, can be skipped if present
-        let skip_trainer = raw[6] & 0b100 != 0;
-
-        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
-        let chr_rom_start = prg_rom_start + prg_rom_size;
-
-        Ok(Rom {
-            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
-            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
-            mapper,
-            screen_mirroring,
-        })
-    }
-}
-
-#[cfg(test)]
-pub mod test {
-
-    use super::*;
-
-    struct TestRom {
-        header: Vec<u8>,
-        trainer: Option<Vec<u8>>,
-        prg_rom: Vec<u8>,
-        chr_rom: Vec<u8>,
-    }
-
-    fn create_rom(rom: TestRom) -> Vec<u8> {
-        let mut result = Vec::with_capacity(
-            rom.header.len()
-                + rom.trainer.as_ref().map_or(0, |t| t.len())
-                + rom.prg_rom.len()
-                + rom.chr_rom.len(),
-        );
-
-        result.extend(&rom.header);
-        if let Some(t) = rom.trainer {
-            result.extend(t);
-        }
-        result.extend(&rom.prg_rom);
-        result.extend(&rom.chr_rom);
-
-        result
-    }
-
-    /// This is a helper function for the CPU Tests to create test roms.
-    pub fn test_rom(program: Option<Vec<u8>>) -> Rom {
-        let prg_rom: Vec<u8> = match program {
-            Some(mut program_bytes) => {
-                program_bytes.resize(2 * PRG_ROM_PAGE_SIZE, 0);
-                program_bytes
-            }
-            None => vec![1; 2 * PRG_ROM_PAGE_SIZE],
-        };
-
-        let test_rom = create_rom(TestRom {
-            header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
-            ],
-            trainer: None,
-            prg_rom,
-            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
-        });
-
-        Rom::new(&test_rom).unwrap()
-    }
-
-    #[test]
-    fn test_rom_works_as_expected() {
-        let test_rom = create_rom(TestRom {
-            header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
-            ],
-            trainer: None,
-            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
-            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
-        });
-
-        let rom = Rom::new(&test_rom).unwrap();
-
-        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
-        assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
-        assert_eq!(rom.mapper, 3);
-        assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
-    }
-
-    #[test]
-    fn test_with_trainer() {
-        let test_rom = create_rom(TestRom {
-            header: vec![
-                0x4E,
-                0x45,
-                0x53,
-                0x1A,
-                0x02,
-                0x01,
-                0x31 | 0b100,
-                00,
-                00,
-                00,
-                00,
-                00,
-                00,
-                00,
-                00,
-                00,
-            ],
-            trainer: Some(vec![0; 512]),
-            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
-            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
-        });
-
-        let rom: Rom = Rom::new(&test_rom).unwrap();
-
-        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
-        assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
-        assert_eq!(rom.mapper, 3);
-        assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
-    }
-
-    #[test]
-    fn test_nes2_is_not_supported() {
-        let test_rom = create_rom(TestRom {
-            header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x8, 00, 00, 00, 00, 00, 00, 00, 00,
-            ],
-            trainer: None,
-            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
-            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
-        });
-        let rom = Rom::new(&test_rom);
-        match rom {
-            Result::Ok(_) => panic!("should not load rom"),
-            Result::Err(str) => assert_eq!(str, "iNES 2.0 format is not supported"),
-        }
-    }
-}
@@ -0,0 +1,244 @@
+//! Local game database to fix bad iNES headers. Lots of older dumps come with a wrong mapper number, mirroring or
+//! battery flag (or region), so known dumps are identified by their hashes and the header values are replaced with the ones
+//! from a database file in the format of `gamedb.tsv` (described at the top of that file).
+//!
+//! The bundled `gamedb.tsv` only knows nestest and serves as the reference for the format, the corrections for real
+//! games come from a database file the user passes with `--gamedb`.
+
+use super::{Mirroring, Region, Rom};
+
+pub struct GameDatabase {
+    games: Vec<GameEntry>,
+}
+
+struct GameEntry {
+    crc32: u32,
+    sha1: Option<[u8; 20]>,
+    mapper: Option<u16>,
+    submapper: Option<u8>,
+    mirroring: Option<Mirroring>,
+    battery: Option<bool>,
+    region: Option<Region>,
+    name: String,
+}
+
+/// A database entry matched the ROM.
+pub struct DatabaseMatch {
+    pub name: String,
+    /// The header values that were replaced, e.g. "mapper 1 -> 4".
+    pub corrections: Vec<String>,
+}
+
+fn parse(text: &str) -> Result<Vec<GameEntry>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            parse_entry(line).map_err(|e| format!("Game database line {}: {}", index + 1, e))
+        })
+        .collect()
+}
+
+fn parse_entry(line: &str) -> Result<GameEntry, String> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    let [crc32, sha1, mapper, submapper, mirroring, battery, region, name] = columns[..] else {
+        return Err(format!("expected 8 columns, found {}", columns.len()));
+    };
+
+    // "-" keeps the value of the header
+    fn optional<T>(column: &str, parse: impl Fn(&str) -> Option<T>) -> Result<Option<T>, String> {
+        match column {
+            "-" => Ok(None),
+            _ => parse(column)
+                .map(Some)
+                .ok_or(format!("invalid value {}", column)),
+        }
+    }
+
+    Ok(GameEntry {
+        crc32: u32::from_str_radix(crc32, 16).map_err(|_| format!("invalid CRC-32 {}", crc32))?,
+        sha1: optional(sha1, parse_sha1)?,
+        mapper: optional(mapper, |column| column.parse().ok())?,
+        submapper: optional(submapper, |column| column.parse().ok())?,
+        mirroring: optional(mirroring, Mirroring::from_name)?,
+        battery: optional(battery, |column| match column {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
+        })?,
+        region: optional(region, Region::from_name)?,
+        name: name.to_string(),
+    })
+}
+
+fn parse_sha1(column: &str) -> Option<[u8; 20]> {
+    if column.len() != 40 || !column.is_ascii() {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (i, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&column[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(sha1)
+}
+
+fn lookup<'a>(games: &'a [GameEntry], rom: &Rom) -> Option<&'a GameEntry> {
+    let crc32 = rom.crc32();
+    let mut candidates = games.iter().filter(|game| game.crc32 == crc32).peekable();
+    candidates.peek()?;
+
+    // the SHA-1 is only computed when there is a CRC-32 match to tell CRC collisions apart
+    let sha1 = rom.sha1();
+    candidates.find(|game| game.sha1.is_none_or(|game_sha1| game_sha1 == sha1))
+}
+
+fn apply(game: &GameEntry, rom: &mut Rom) -> DatabaseMatch {
+    let mut corrections = Vec::new();
+
+    if let Some(mapper) = game.mapper.filter(|&mapper| mapper != rom.mapper) {
+        corrections.push(format!("mapper {} -> {}", rom.mapper, mapper));
+        rom.mapper = mapper;
+    }
+    if let Some(submapper) = game
+        .submapper
+        .filter(|&submapper| submapper != rom.submapper)
+    {
+        corrections.push(format!("submapper {} -> {}", rom.submapper, submapper));
+        rom.submapper = submapper;
+    }
+    if let Some(mirroring) = game
+        .mirroring
+        .filter(|&mirroring| mirroring != rom.screen_mirroring)
+    {
+        corrections.push(format!(
+            "mirroring {} -> {}",
+            rom.screen_mirroring.name(),
+            mirroring.name()
+        ));
+        rom.screen_mirroring = mirroring;
+    }
+    if let Some(battery) = game.battery.filter(|&battery| battery != rom.battery) {
+        corrections.push(format!("battery {} -> {}", rom.battery, battery));
+        rom.battery = battery;
+    }
+    if let Some(region) = game.region.filter(|&region| region != rom.region) {
+        corrections.push(format!("region {} -> {}", rom.region.name(), region.name()));
+        rom.region = region;
+    }
+
+    DatabaseMatch {
+        name: game.name.clone(),
+        corrections,
+    }
+}
+
+impl GameDatabase {
+    /// The entries of the bundled `gamedb.tsv`.
+    pub fn bundled() -> Self {
+        GameDatabase {
+            games: parse(include_str!("gamedb.tsv")).expect("The bundled game database is invalid"),
+        }
+    }
+
+    /// The bundled entries together with the ones of a user supplied database file, which take precedence.
+    pub fn with_file(path: &str) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+        let mut games = parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        games.extend(GameDatabase::bundled().games);
+        Ok(GameDatabase { games })
+    }
+}
+
+impl Rom {
+    /// Looks the ROM up in the game database and overrides the header values with the ones from the database.
+    /// Returns the match so it can be reported, `None` if the dump is unknown.
+    pub fn apply_database(&mut self, database: &GameDatabase) -> Option<DatabaseMatch> {
+        lookup(&database.games, self).map(|game| apply(game, self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::{hash, test::test_mapper_rom};
+
+    #[test]
+    fn test_bundled_database_is_valid() {
+        let mut rom = Rom::new(&std::fs::read("nestest.nes").unwrap()).unwrap();
+        let game = rom.apply_database(&GameDatabase::bundled()).unwrap();
+        assert_eq!(game.name, "nestest");
+        assert!(game.corrections.is_empty());
+    }
+
+    #[test]
+    fn test_database_file() {
+        let path = std::env::temp_dir().join("rust-nes-emulator-test-gamedb.tsv");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "158B0388\t-\t-\t-\tvertical\t-\t-\tnestest (vertical)\n",
+        )
+        .unwrap();
+        let database = GameDatabase::with_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        // the entries of the file come before the bundled ones
+        let mut rom = Rom::new(&std::fs::read("nestest.nes").unwrap()).unwrap();
+        let game = rom.apply_database(&database.unwrap()).unwrap();
+        assert_eq!(game.name, "nestest (vertical)");
+        assert_eq!(game.corrections, vec!["mirroring horizontal -> vertical"]);
+
+        assert!(GameDatabase::with_file("missing-gamedb.tsv").is_err());
+    }
+
+    #[test]
+    fn test_header_is_corrected() {
+        let mut rom = test_mapper_rom(1, vec![0xea; 0x8000], vec![0; 0x2000]);
+        let crc32 = rom.crc32();
+        let games = parse(&format!(
+            "# comment\n{:08X}\t-\t4\t-\tfour-screen\t1\tPAL\tTest Game\n",
+            crc32
+        ))
+        .unwrap();
+
+        let game = apply(lookup(&games, &rom).unwrap(), &mut rom);
+        assert_eq!(game.name, "Test Game");
+        assert_eq!(
+            game.corrections,
+            vec![
+                "mapper 1 -> 4",
+                "mirroring horizontal -> four-screen",
+                "battery false -> true",
+                "region NTSC -> PAL"
+            ]
+        );
+        assert_eq!(rom.mapper, 4);
+        assert_eq!(rom.submapper, 0);
+        assert_eq!(rom.screen_mirroring, Mirroring::FOURSCREEN);
+        assert!(rom.battery);
+        assert_eq!(rom.region, Region::Pal);
+    }
+
+    #[test]
+    fn test_sha1_has_to_match() {
+        let rom = test_mapper_rom(0, vec![0xea; 0x4000], vec![0; 0x2000]);
+        let line = |sha1: &str| format!("{:08x}\t{}\t-\t-\t-\t-\t-\tTest Game", rom.crc32(), sha1);
+
+        let games = parse(&line(&hash::to_hex(&rom.sha1()))).unwrap();
+        assert!(lookup(&games, &rom).is_some());
+        let games = parse(&line(&"00".repeat(20))).unwrap();
+        assert!(lookup(&games, &rom).is_none());
+    }
+
+    #[test]
+    fn test_invalid_lines_are_rejected() {
+        assert_eq!(
+            parse("# comment\n\n1234\t-\t0").err(),
+            Some("Game database line 3: expected 8 columns, found 3".to_string())
+        );
+        assert!(parse("1234\t-\t0\t0\tdiagonal\t0\t-\tTest").is_err());
+        assert!(parse("1234\tabc\t0\t0\tvertical\t0\t-\tTest").is_err());
+        assert!(parse("1234\t-\t0\t0\tvertical\t0\tSECAM\tTest").is_err());
+    }
+}
@@ -0,0 +1,915 @@
+mod database;
+pub mod hash;
+mod unif;
+
+pub use database::{DatabaseMatch, GameDatabase};
+
+use crate::{
+    audio::{DENDY_CPU_CLOCK_RATE, NTSC_CPU_CLOCK_RATE, PAL_CPU_CLOCK_RATE},
+    mapper::{self, Mapper},
+};
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A]; // part of the header
+const PRG_ROM_PAGE_SIZE: usize = 16384; // 16 kB page size of PRG ROM
+const CHR_ROM_PAGE_SIZE: usize = 8192; // 8 kB page size CHR ROM
+const NAMETABLE_SIZE: u16 = 0x400; // 1 KiB per nametable
+const TRAINER_SIZE: usize = 512;
+// iNES 1.0 boards are assumed to have 8 KiB of PRG RAM (or CHR RAM without CHR ROM)
+const DEFAULT_RAM_SIZE: usize = 0x2000;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mirroring {
+    VERTICAL,
+    HORIZONTAl,
+    FOURSCREEN,
+    /// All four nametables show the first 1 KiB of VRAM (used by AxROM, MMC1, ...).
+    SingleScreenLower,
+    /// All four nametables show the second 1 KiB of VRAM.
+    SingleScreenUpper,
+}
+
+/// Where a nametable access (PPU address 0x2000-0x2fff) ends up.
+#[derive(Debug, PartialEq)]
+pub enum NametableAddr {
+    /// Index into the 2 KiB of VRAM inside the console.
+    Vram(u16),
+    /// Offset into memory provided by the cartridge, e.g. the extra 2 KiB of four-screen boards.
+    Cartridge(u16),
+}
+
+impl Mirroring {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mirroring::VERTICAL => "vertical",
+            Mirroring::HORIZONTAl => "horizontal",
+            Mirroring::FOURSCREEN => "four-screen",
+            Mirroring::SingleScreenLower => "single-screen lower",
+            Mirroring::SingleScreenUpper => "single-screen upper",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Mirroring> {
+        [
+            Mirroring::HORIZONTAl,
+            Mirroring::VERTICAL,
+            Mirroring::FOURSCREEN,
+            Mirroring::SingleScreenLower,
+            Mirroring::SingleScreenUpper,
+        ]
+        .into_iter()
+        .find(|mirroring| mirroring.name().eq_ignore_ascii_case(name))
+    }
+
+    /// NES uses 1 KiB of VRAM to represent the state of a single screen. Since it has 2 KiB it can store 2 screen states.
+    /// The four nametables in the PPU memory map have to be mapped onto those two pages depending on the mirroring type.
+    ///
+    /// Horizontal:
+    ///  [ A ] [ a ]
+    ///  [ B ] [ b ]
+    ///
+    /// Vertical:
+    ///  [ A ] [ B ]
+    ///  [ a ] [ b ]
+    ///
+    /// Single screen (lower/upper):
+    ///  [ A ] [ a ]     [ B ] [ b ]
+    ///  [ a ] [ a ]     [ b ] [ b ]
+    ///
+    /// Four screen, C and D live on the cartridge:
+    ///  [ A ] [ B ]
+    ///  [ C ] [ D ]
+    pub fn map_nametable(&self, addr: u16) -> NametableAddr {
+        let index = addr & 0x0fff; // mirror down 0x3000-0x3eff and strip the 0x2000 base
+        let name_table = index / NAMETABLE_SIZE;
+        let offset = index % NAMETABLE_SIZE;
+
+        let page = match (self, name_table) {
+            (Mirroring::VERTICAL, table) => table % 2,
+            (Mirroring::HORIZONTAl, table) => table / 2,
+            (Mirroring::SingleScreenLower, _) => 0,
+            (Mirroring::SingleScreenUpper, _) => 1,
+            (Mirroring::FOURSCREEN, table @ (0 | 1)) => table,
+            (Mirroring::FOURSCREEN, _) => return NametableAddr::Cartridge(index - 0x800),
+        };
+
+        NametableAddr::Vram(page * NAMETABLE_SIZE + offset)
+    }
+}
+
+/// The file format a [`Rom`] was loaded from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RomFormat {
+    INes,
+    Nes2,
+    Unif,
+}
+
+impl RomFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RomFormat::INes => "iNES",
+            RomFormat::Nes2 => "NES 2.0",
+            RomFormat::Unif => "UNIF",
+        }
+    }
+}
+
+/// The console the game was made for, it decides the CPU/PPU timing.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    /// Runs on consoles of every region, emulated with the NTSC timing.
+    Multiple,
+    /// Famiclones sold in Russia, a PAL picture with NTSC-like CPU timing.
+    Dendy,
+}
+
+impl Region {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Multiple => "multi-region",
+            Region::Dendy => "Dendy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Region> {
+        [Region::Ntsc, Region::Pal, Region::Multiple, Region::Dendy]
+            .into_iter()
+            .find(|region| region.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        match self {
+            Region::Pal => PAL_CPU_CLOCK_RATE,
+            Region::Dendy => DENDY_CPU_CLOCK_RATE,
+            Region::Ntsc | Region::Multiple => NTSC_CPU_CLOCK_RATE,
+        }
+    }
+
+    /// PPU dots per CPU cycle as a fraction (dots, cycles), a PAL PPU runs 3.2 dots per CPU cycle.
+    pub fn ppu_dots_per_cpu_cycle(&self) -> (u16, u16) {
+        match self {
+            Region::Pal => (16, 5),
+            Region::Ntsc | Region::Multiple | Region::Dendy => (3, 1),
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Pal | Region::Dendy => 312,
+            Region::Ntsc | Region::Multiple => 262,
+        }
+    }
+
+    /// Dendy keeps the NTSC CPU timing for the NMI handlers by adding the extra 50 lines before vblank.
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Dendy => 291,
+            Region::Ntsc | Region::Pal | Region::Multiple => 241,
+        }
+    }
+
+    /// Only the NTSC PPU skips a dot on every other frame.
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        matches!(self, Region::Ntsc | Region::Multiple)
+    }
+
+    /// About 60.1 for NTSC and 50 for PAL and Dendy.
+    pub fn frame_rate(&self) -> f64 {
+        let (dots, cycles) = self.ppu_dots_per_cpu_cycle();
+        let mut dots_per_frame = 341.0 * self.scanlines_per_frame() as f64;
+        if self.skips_odd_frame_dot() {
+            dots_per_frame -= 0.5;
+        }
+        self.cpu_clock_rate() * dots as f64 / cycles as f64 / dots_per_frame
+    }
+}
+
+/// For ROMS in the iNES format, UNIF files are converted into the same structure (see [`unif`]).
+pub struct Rom {
+    pub format: RomFormat,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    /// 512 bytes loaded to 0x7000 - 0x71FF, only a few old dumps have one.
+    pub trainer: Option<Vec<u8>>,
+    pub mapper: u16,
+    /// Only NES 2.0 headers have a submapper, it tells apart boards that share a mapper number. 0 means unknown.
+    pub submapper: u8,
+    pub screen_mirroring: Mirroring,
+    /// The PRG RAM at 0x6000 - 0x7FFF is battery backed.
+    pub battery: bool,
+    /// RAM sizes in bytes, NVRAM being the battery backed part. Only NES 2.0 stores them, for the other formats
+    /// they are guessed from the battery flag and the missing CHR ROM.
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub region: Region,
+}
+
+impl Rom {
+    pub fn new(raw: &[u8]) -> Result<Rom, String> {
+        if raw.starts_with(&unif::UNIF_TAG) {
+            return Rom::from_unif(raw);
+        }
+        if raw.len() < 16 || raw[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        // control byte 2 bits 3-2 contain iNES version information
+        // if bit(3, 2) == 10 => iNES 2.0
+        // if bit(3, 2) == 00 => iNES 1.0
+        let nes2 = match (raw[7] >> 2) & 0b11 {
+            0b00 => false,
+            0b10 => true,
+            _ => return Err("Unknown iNES header version".to_string()),
+        };
+
+        // raw 6 and 7 represent the control bytes 1 and 2 respectively
+        // control byte 1 bits 7-4 contain the 4 lower bits of the mapper type
+        // control byte 2 bits 7-4 contain the 4 upper bits of the mapper type
+        // NES 2.0 adds another 4 bits in the lower nibble of raw 8 and uses the upper nibble for the submapper
+        let mut mapper = ((raw[7] & 0b1111_0000) | (raw[6] >> 4)) as u16;
+        let mut submapper = 0;
+        if nes2 {
+            mapper |= ((raw[8] & 0b1111) as u16) << 8;
+            submapper = raw[8] >> 4;
+        }
+
+        // control byte 1 bit 3 means four-screen VRAM layout
+        let four_screen = raw[6] & 0b1000 != 0;
+        // if control byte 1 bit 0 is equal to 0 it means horizontal mirroring, otherwise vertical
+        let vertical_mirroring = raw[6] & 0b1 != 0;
+        let screen_mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FOURSCREEN,
+            (false, true) => Mirroring::VERTICAL,
+            (false, false) => Mirroring::HORIZONTAl,
+        };
+
+        // raw 4 and 5 represent number of PRG and CHR ROM pages respectively
+        // NES 2.0 stores the upper bits of both in raw 9
+        let (prg_rom_size, chr_rom_size) = if nes2 {
+            (
+                nes2_rom_size(raw[4], raw[9] & 0b1111, PRG_ROM_PAGE_SIZE)
+                    .ok_or("PRG ROM size is too large")?,
+                nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE)
+                    .ok_or("CHR ROM size is too large")?,
+            )
+        } else {
+            (
+                raw[4] as usize * PRG_ROM_PAGE_SIZE,
+                raw[5] as usize * CHR_ROM_PAGE_SIZE,
+            )
+        };
+
+        // control byte 1 bit 1 means the cartridge has battery backed PRG RAM
+        let battery = raw[6] & 0b10 != 0;
+
+        // control byte 1 bit 2 represents the need for a 512-byte trainer at memory section 0x7000 - 0x71FF
+        // it's a data section created by Famicom to keep their own mapping, none of our mappers use it
+        let has_trainer = raw[6] & 0b100 != 0;
+
+        let prg_rom_start = 16 + if has_trainer { TRAINER_SIZE } else { 0 };
+        let too_large = "ROM size is too large";
+        let chr_rom_start = prg_rom_start.checked_add(prg_rom_size).ok_or(too_large)?;
+        let rom_end = chr_rom_start.checked_add(chr_rom_size).ok_or(too_large)?;
+        if raw.len() < rom_end {
+            return Err("File is smaller than the ROM sizes in its header".to_string());
+        }
+
+        // NES 2.0 stores the RAM sizes as shift counts (64 << n bytes, 0 means none) in raw 10 and 11,
+        // the lower nibble for volatile RAM and the upper one for battery backed RAM
+        // raw 12 holds the region
+        // iNES 1.0 only has the PRG RAM size in 8 KiB units in raw 8 (0 means 8 KiB) and a rarely set PAL flag in raw 9
+        let (prg_ram_size, prg_nvram_size, chr_ram_size, chr_nvram_size, region) = if nes2 {
+            let region = match raw[12] & 0b11 {
+                0 => Region::Ntsc,
+                1 => Region::Pal,
+                2 => Region::Multiple,
+                _ => Region::Dendy,
+            };
+            (
+                nes2_ram_size(raw[10] & 0b1111),
+                nes2_ram_size(raw[10] >> 4),
+                nes2_ram_size(raw[11] & 0b1111),
+                nes2_ram_size(raw[11] >> 4),
+                region,
+            )
+        } else {
+            let (prg_ram_size, prg_nvram_size, chr_ram_size) = guess_ram_sizes(
+                raw[8].max(1) as usize * DEFAULT_RAM_SIZE,
+                battery,
+                chr_rom_size,
+            );
+            let region = if raw[9] & 1 != 0 {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            };
+            (prg_ram_size, prg_nvram_size, chr_ram_size, 0, region)
+        };
+
+        Ok(Rom {
+            format: if nes2 {
+                RomFormat::Nes2
+            } else {
+                RomFormat::INes
+            },
+            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
+            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            trainer: has_trainer.then(|| raw[16..16 + TRAINER_SIZE].to_vec()),
+            mapper,
+            submapper,
+            screen_mirroring,
+            battery,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            region,
+        })
+    }
+
+    /// CRC-32 of the PRG ROM followed by the CHR ROM, the header isn't part of it.
+    pub fn crc32(&self) -> u32 {
+        hash::crc32(&[&self.prg_rom[..], &self.chr_rom[..]].concat())
+    }
+
+    /// SHA-1 of the PRG ROM followed by the CHR ROM, the header isn't part of it.
+    pub fn sha1(&self) -> [u8; 20] {
+        hash::sha1(&[&self.prg_rom[..], &self.chr_rom[..]].concat())
+    }
+
+    /// Writes the ROM as an iNES file. iNES 1.0 ROMs get an iNES 1.0 header, everything else a NES 2.0 header.
+    /// Values the header version can't store are an error instead of silently getting lost. The NES 2.0 fields
+    /// that aren't part of `Rom` (Vs. System type, miscellaneous ROMs, expansion device) are written as 0.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut header = [0; 16];
+        header[0..4].copy_from_slice(&NES_TAG);
+
+        let mirroring = match self.screen_mirroring {
+            Mirroring::VERTICAL => 0b1,
+            Mirroring::FOURSCREEN => 0b1000,
+            // single-screen boards switch the nametables themselves, the header has no way to express it
+            _ => 0,
+        };
+        let trainer = match &self.trainer {
+            Some(trainer) if trainer.len() != TRAINER_SIZE => {
+                return Err(format!("The trainer has to be {} bytes", TRAINER_SIZE))
+            }
+            trainer => trainer.is_some(),
+        };
+        header[6] = (self.mapper as u8 & 0b1111) << 4
+            | (trainer as u8) << 2
+            | (self.battery as u8) << 1
+            | mirroring;
+        header[7] = self.mapper as u8 & 0b1111_0000;
+
+        if self.format == RomFormat::INes {
+            if self.mapper > 0xff {
+                return Err("iNES 1.0 headers can't store mapper numbers above 255".to_string());
+            }
+            if self.submapper != 0 {
+                return Err("iNES 1.0 headers can't store a submapper".to_string());
+            }
+            header[4] = ines_page_count(self.prg_rom.len(), PRG_ROM_PAGE_SIZE, "PRG")?;
+            header[5] = ines_page_count(self.chr_rom.len(), CHR_ROM_PAGE_SIZE, "CHR")?;
+            let prg_ram_size = self.prg_ram_size + self.prg_nvram_size;
+            header[8] = match prg_ram_size.div_ceil(DEFAULT_RAM_SIZE) {
+                0 | 1 => 0,
+                pages => pages.min(0xff) as u8,
+            };
+            header[9] = match self.region {
+                Region::Ntsc => 0,
+                Region::Pal => 1,
+                region => {
+                    return Err(format!(
+                        "iNES 1.0 headers can't store the region {}",
+                        region.name()
+                    ))
+                }
+            };
+        } else {
+            if self.mapper > 0xfff || self.submapper > 0xf {
+                return Err("NES 2.0 headers can't store the mapper number".to_string());
+            }
+            header[7] |= 0b1000;
+            header[8] = self.submapper << 4 | (self.mapper >> 8) as u8;
+
+            let (prg_lsb, prg_msb) = nes2_rom_size_fields(self.prg_rom.len(), PRG_ROM_PAGE_SIZE)
+                .ok_or("NES 2.0 headers can't store the PRG ROM size")?;
+            let (chr_lsb, chr_msb) = nes2_rom_size_fields(self.chr_rom.len(), CHR_ROM_PAGE_SIZE)
+                .ok_or("NES 2.0 headers can't store the CHR ROM size")?;
+            header[4] = prg_lsb;
+            header[5] = chr_lsb;
+            header[9] = chr_msb << 4 | prg_msb;
+
+            header[10] =
+                nes2_ram_shift(self.prg_nvram_size)? << 4 | nes2_ram_shift(self.prg_ram_size)?;
+            header[11] =
+                nes2_ram_shift(self.chr_nvram_size)? << 4 | nes2_ram_shift(self.chr_ram_size)?;
+            header[12] = match self.region {
+                Region::Ntsc => 0,
+                Region::Pal => 1,
+                Region::Multiple => 2,
+                Region::Dendy => 3,
+            };
+        }
+
+        Ok([
+            &header[..],
+            self.trainer.as_deref().unwrap_or_default(),
+            &self.prg_rom,
+            &self.chr_rom,
+        ]
+        .concat())
+    }
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        shift => 64 << shift,
+    }
+}
+
+/// PRG RAM, PRG NVRAM and CHR RAM sizes for formats that don't store them.
+fn guess_ram_sizes(
+    prg_ram_size: usize,
+    battery: bool,
+    chr_rom_size: usize,
+) -> (usize, usize, usize) {
+    let chr_ram_size = if chr_rom_size == 0 {
+        DEFAULT_RAM_SIZE
+    } else {
+        0
+    };
+    if battery {
+        (0, prg_ram_size, chr_ram_size)
+    } else {
+        (prg_ram_size, 0, chr_ram_size)
+    }
+}
+
+/// The inverse of [`nes2_ram_size`].
+fn nes2_ram_shift(size: usize) -> Result<u8, String> {
+    match size {
+        0 => Ok(0),
+        _ if size.is_power_of_two() && (128..=64 << 15).contains(&size) => {
+            Ok((size.trailing_zeros() - 6) as u8)
+        }
+        _ => Err(format!(
+            "NES 2.0 headers can't store a RAM size of {} bytes",
+            size
+        )),
+    }
+}
+
+fn ines_page_count(size: usize, page_size: usize, name: &str) -> Result<u8, String> {
+    if !size.is_multiple_of(page_size) || size / page_size > 0xff {
+        return Err(format!(
+            "iNES 1.0 headers can't store a {} ROM size of {} bytes",
+            name, size
+        ));
+    }
+    Ok((size / page_size) as u8)
+}
+
+/// The inverse of [`nes2_rom_size`], uses the exponent-multiplier notation only if the page count doesn't work.
+fn nes2_rom_size_fields(size: usize, page_size: usize) -> Option<(u8, u8)> {
+    let pages = size / page_size;
+    if size.is_multiple_of(page_size) && pages < 0xf00 {
+        return Some((pages as u8, (pages >> 8) as u8));
+    }
+    (0..4).find_map(|multiplier| {
+        let factor = multiplier * 2 + 1;
+        let power = size / factor;
+        (size.is_multiple_of(factor) && power.is_power_of_two()).then(|| {
+            (
+                (power.trailing_zeros() as u8) << 2 | multiplier as u8,
+                0b1111,
+            )
+        })
+    })
+}
+
+/// NES 2.0 ROM sizes are the page count with 4 extra upper bits. If those upper bits are all set
+/// the lower byte is an exponent-multiplier pair instead: size = 2^E * (MM * 2 + 1) bytes.
+/// `None` if the size doesn't fit into memory.
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> Option<usize> {
+    if msb == 0b1111 {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        2usize.checked_pow(exponent)?.checked_mul(multiplier)
+    } else {
+        Some(((msb as usize) << 8 | lsb as usize) * page_size)
+    }
+}
+
+/// The cartridge as seen by the CPU and PPU buses. It is shared between both of them since the
+/// mapper sits on the CPU bus (PRG, bank switching registers) as well as on the PPU bus (CHR, nametables).
+pub struct Cartridge {
+    mapper: Box<dyn Mapper>,
+    // four-screen boards bring 2 KiB of their own VRAM for the third and fourth nametable
+    four_screen_vram: [u8; 0x800],
+}
+
+impl Cartridge {
+    pub fn new(rom: Rom) -> Self {
+        Cartridge::with_mapper(mapper::from_rom(rom))
+    }
+
+    pub fn with_mapper(mapper: Box<dyn Mapper>) -> Self {
+        Cartridge {
+            mapper,
+            four_screen_vram: [0; 0x800],
+        }
+    }
+
+    pub fn read_prg(&mut self, addr: u16) -> u8 {
+        self.mapper.read_prg(addr)
+    }
+
+    pub fn write_prg(&mut self, addr: u16, data: u8) {
+        self.mapper.write_prg(addr, data);
+    }
+
+    pub fn read_chr(&mut self, addr: u16) -> u8 {
+        self.mapper.read_chr(addr)
+    }
+
+    pub fn fetch_chr(&mut self, addr: u16) -> u8 {
+        self.mapper.fetch_chr(addr)
+    }
+
+    pub fn write_chr(&mut self, addr: u16, data: u8) {
+        self.mapper.write_chr(addr, data);
+    }
+
+    /// The mirroring is asked for on every access since some mappers switch it at runtime.
+    pub fn map_nametable(&mut self, addr: u16) -> NametableAddr {
+        self.mapper.map_nametable(addr)
+    }
+
+    pub fn fetch_nametable(&mut self, addr: u16) -> NametableAddr {
+        self.mapper.fetch_nametable(addr)
+    }
+
+    pub fn read_nametable(&mut self, offset: u16) -> u8 {
+        if self.mapper.mirroring() == Mirroring::FOURSCREEN {
+            self.four_screen_vram[offset as usize]
+        } else {
+            self.mapper.read_nametable(offset)
+        }
+    }
+
+    pub fn write_nametable(&mut self, offset: u16, data: u8) {
+        if self.mapper.mirroring() == Mirroring::FOURSCREEN {
+            self.four_screen_vram[offset as usize] = data;
+        } else {
+            self.mapper.write_nametable(offset, data);
+        }
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+
+    pub fn notify_ppu_register_write(&mut self, addr: u16, data: u8) {
+        self.mapper.notify_ppu_register_write(addr, data);
+    }
+
+    pub fn notify_scanline(&mut self, scanline: u16) {
+        self.mapper.notify_scanline(scanline);
+    }
+
+    pub fn cpu_tick(&mut self) {
+        self.mapper.cpu_tick();
+    }
+
+    pub fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.mapper.save_data()
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.mapper.disk_side_count()
+    }
+
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.mapper.insert_disk(side);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::*;
+
+    /// An NTSC ROM with the RAM sizes an iNES 1.0 header implies.
+    fn ines_rom(mapper: u16, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Rom {
+        let (prg_ram_size, prg_nvram_size, chr_ram_size) =
+            guess_ram_sizes(DEFAULT_RAM_SIZE, false, chr_rom.len());
+
+        Rom {
+            format: RomFormat::INes,
+            prg_rom,
+            chr_rom,
+            trainer: None,
+            mapper,
+            submapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAl,
+            battery: false,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+        }
+    }
+
+    /// This is a helper function for the CPU Tests to create test roms.
+    pub fn test_rom(program: Option<Vec<u8>>) -> Rom {
+        let prg_rom: Vec<u8> = match program {
+            Some(mut program_bytes) => {
+                program_bytes.resize(2 * PRG_ROM_PAGE_SIZE, 0);
+                program_bytes
+            }
+            None => vec![1; 2 * PRG_ROM_PAGE_SIZE],
+        };
+
+        let mut rom = ines_rom(0, prg_rom, vec![2; CHR_ROM_PAGE_SIZE]);
+        rom.screen_mirroring = Mirroring::VERTICAL;
+        rom
+    }
+
+    /// Goes through the file format, so the ROM looks exactly like it was loaded from a file.
+    fn mapper_rom(mapper: u8, submapper: Option<u8>, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Rom {
+        let mut rom = ines_rom(mapper as u16, prg_rom, chr_rom);
+        if let Some(submapper) = submapper {
+            rom.format = RomFormat::Nes2;
+            rom.submapper = submapper;
+        }
+
+        Rom::new(&rom.to_bytes().unwrap()).unwrap()
+    }
+
+    /// This is a helper function for the mapper tests to create roms with the given mapper number and memory.
+    /// The number of PRG and CHR pages in the header is derived from the length of the given vectors.
+    pub fn test_mapper_rom(mapper: u8, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Rom {
+        mapper_rom(mapper, None, prg_rom, chr_rom)
+    }
+
+    /// Same as [`test_mapper_rom`] but with a NES 2.0 header to be able to set the submapper.
+    pub fn test_submapper_rom(
+        mapper: u8,
+        submapper: u8,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+    ) -> Rom {
+        mapper_rom(mapper, Some(submapper), prg_rom, chr_rom)
+    }
+
+    #[test]
+    fn test_rom_works_as_expected() {
+        let test_rom = [
+            &[
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            &[1; 2 * PRG_ROM_PAGE_SIZE][..],
+            &[2; CHR_ROM_PAGE_SIZE],
+        ]
+        .concat();
+
+        let rom = Rom::new(&test_rom).unwrap();
+
+        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
+        assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
+        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
+    }
+
+    #[test]
+    fn test_with_trainer() {
+        let test_rom = [
+            &[
+                0x4E,
+                0x45,
+                0x53,
+                0x1A,
+                0x02,
+                0x01,
+                0x31 | 0b100,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+            ],
+            &[7; 512][..],
+            &[1; 2 * PRG_ROM_PAGE_SIZE],
+            &[2; CHR_ROM_PAGE_SIZE],
+        ]
+        .concat();
+
+        let rom: Rom = Rom::new(&test_rom).unwrap();
+
+        assert_eq!(rom.trainer, Some(vec!(7; 512)));
+        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
+        assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
+        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let test_rom = [
+            &[
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x53, 0x18, 0x21, 00, 0x70, 0x07, 0x03, 00, 00,
+                00,
+            ],
+            &[1; PRG_ROM_PAGE_SIZE][..],
+            &[2; CHR_ROM_PAGE_SIZE],
+        ]
+        .concat();
+        let rom = Rom::new(&test_rom).unwrap();
+
+        assert_eq!(rom.format, RomFormat::Nes2);
+        assert_eq!(rom.mapper, 0x115);
+        assert_eq!(rom.submapper, 2);
+        assert_eq!(rom.prg_rom, vec!(1; PRG_ROM_PAGE_SIZE));
+        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
+        assert!(rom.battery);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+        assert_eq!(rom.chr_nvram_size, 0);
+        assert_eq!(rom.region, Region::Dendy);
+    }
+
+    #[test]
+    fn test_nes2_exponent_rom_size() {
+        assert_eq!(
+            nes2_rom_size(0x02, 0x1, PRG_ROM_PAGE_SIZE),
+            Some(0x102 * PRG_ROM_PAGE_SIZE)
+        );
+        // 2^14 * 3
+        assert_eq!(
+            nes2_rom_size(0b0011_1001, 0xf, PRG_ROM_PAGE_SIZE),
+            Some(0xc000)
+        );
+        // 2^63 * 7
+        assert_eq!(nes2_rom_size(0xff, 0xf, PRG_ROM_PAGE_SIZE), None);
+    }
+
+    #[test]
+    fn test_nes2_rom_size_too_large() {
+        let mut header = [
+            0x4E, 0x45, 0x53, 0x1A, 0xff, 0xfd, 00, 0x08, 00, 0xff, 00, 00, 00, 00, 00, 00,
+        ];
+        assert_eq!(
+            Rom::new(&header).err(),
+            Some("PRG ROM size is too large".to_string())
+        );
+
+        // 2^63 bytes each, fits on its own but not together
+        header[4] = 0xfc;
+        header[5] = 0xfc;
+        assert_eq!(
+            Rom::new(&header).err(),
+            Some("ROM size is too large".to_string())
+        );
+    }
+
+    #[test]
+    fn test_truncated_rom_is_rejected() {
+        let mut test_rom = test_rom(None).to_bytes().unwrap();
+        test_rom.truncate(test_rom.len() - 1);
+
+        match Rom::new(&test_rom) {
+            Result::Ok(_) => panic!("should not load rom"),
+            Result::Err(str) => assert_eq!(str, "File is smaller than the ROM sizes in its header"),
+        }
+    }
+
+    #[test]
+    fn test_ines_round_trip() {
+        let mut rom = ines_rom(
+            0x42,
+            vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            vec![2; CHR_ROM_PAGE_SIZE],
+        );
+        rom.trainer = Some(vec![3; TRAINER_SIZE]);
+        rom.battery = true;
+        (rom.prg_ram_size, rom.prg_nvram_size) = (0, 0x8000);
+        rom.region = Region::Pal;
+
+        let bytes = rom.to_bytes().unwrap();
+        assert_eq!(
+            bytes[0..16],
+            [0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x26, 0x40, 0x04, 0x01, 00, 00, 00, 00, 00, 00]
+        );
+        assert_eq!(
+            bytes.len(),
+            16 + TRAINER_SIZE + 2 * PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE
+        );
+
+        let parsed = Rom::new(&bytes).unwrap();
+        assert_eq!(parsed.to_bytes(), Ok(bytes));
+        assert_eq!(parsed.trainer, rom.trainer);
+        assert_eq!(parsed.prg_nvram_size, 0x8000);
+        assert_eq!(parsed.region, Region::Pal);
+    }
+
+    #[test]
+    fn test_nes2_round_trip() {
+        let mut rom = ines_rom(0x1a5, vec![1; 3 * 1024], vec![]);
+        rom.format = RomFormat::Nes2;
+        rom.submapper = 3;
+        rom.screen_mirroring = Mirroring::FOURSCREEN;
+        (rom.prg_ram_size, rom.prg_nvram_size) = (0x2000, 0x800);
+        (rom.chr_ram_size, rom.chr_nvram_size) = (0x8000, 0);
+        rom.region = Region::Dendy;
+
+        let bytes = rom.to_bytes().unwrap();
+        // 3 KiB isn't a multiple of the page size: 2^10 * (1 * 2 + 1)
+        assert_eq!(
+            bytes[0..16],
+            [
+                0x4E, 0x45, 0x53, 0x1A, 0x29, 00, 0x58, 0xa8, 0x31, 0x0f, 0x57, 0x09, 0x03, 00, 00,
+                00
+            ]
+        );
+
+        let parsed = Rom::new(&bytes).unwrap();
+        assert_eq!(parsed.prg_rom, rom.prg_rom);
+        assert_eq!(parsed.to_bytes(), Ok(bytes));
+    }
+
+    #[test]
+    fn test_unrepresentable_headers_are_rejected() {
+        let mut rom = ines_rom(0x100, vec![1; PRG_ROM_PAGE_SIZE], vec![]);
+        assert!(rom.to_bytes().is_err());
+        rom.format = RomFormat::Nes2;
+        assert!(rom.to_bytes().is_ok());
+
+        let mut rom = ines_rom(0, vec![1; PRG_ROM_PAGE_SIZE], vec![]);
+        rom.region = Region::Dendy;
+        assert!(rom.to_bytes().is_err());
+
+        let mut rom = ines_rom(0, vec![1; 1000], vec![]);
+        assert!(rom.to_bytes().is_err());
+        rom.format = RomFormat::Nes2;
+        assert_eq!(
+            rom.to_bytes(),
+            Err("NES 2.0 headers can't store the PRG ROM size".to_string())
+        );
+        rom.prg_rom = vec![1; PRG_ROM_PAGE_SIZE];
+        rom.prg_ram_size = 1000;
+        assert_eq!(
+            rom.to_bytes(),
+            Err("NES 2.0 headers can't store a RAM size of 1000 bytes".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mirroring_maps_nametables() {
+        use NametableAddr::{Cartridge, Vram};
+
+        let map = |mirroring: Mirroring| {
+            [0x2005, 0x2405, 0x2805, 0x2c05].map(|addr| mirroring.map_nametable(addr))
+        };
+
+        assert_eq!(
+            map(Mirroring::HORIZONTAl),
+            [Vram(0x005), Vram(0x005), Vram(0x405), Vram(0x405)]
+        );
+        assert_eq!(
+            map(Mirroring::VERTICAL),
+            [Vram(0x005), Vram(0x405), Vram(0x005), Vram(0x405)]
+        );
+        assert_eq!(
+            map(Mirroring::SingleScreenLower),
+            [Vram(0x005), Vram(0x005), Vram(0x005), Vram(0x005)]
+        );
+        assert_eq!(
+            map(Mirroring::SingleScreenUpper),
+            [Vram(0x405), Vram(0x405), Vram(0x405), Vram(0x405)]
+        );
+        assert_eq!(
+            map(Mirroring::FOURSCREEN),
+            [Vram(0x005), Vram(0x405), Cartridge(0x005), Cartridge(0x405)]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! UNIF (Universal NES Image Format) loader. Instead of a fixed header with a mapper number, a UNIF file is a list of
+//! chunks and names the board the game was dumped from. The board name is looked up in [`BOARDS`] and the file is
+//! converted into the same [`Rom`] an iNES file produces, so the rest of the emulator never sees the difference.
+//! Boards whose mapper isn't implemented are rejected instead of running them on the wrong hardware.
+//! https://www.nesdev.org/wiki/UNIF
+//!
+//! Layout:
+//! ```
+//! 0x00 - 0x03: "UNIF"
+//! 0x04 - 0x07: revision (little endian)
+//! 0x08 - 0x1F: reserved
+//! 0x20 -     : chunks, each a 4 byte ID, a 4 byte little endian length and the data
+//! ```
+//! Chunks used here:
+//! ```
+//! MAPR:        board name, null terminated
+//! PRG0 - PRGF: PRG ROM, concatenated in the order of their hex digit
+//! CHR0 - CHRF: CHR ROM, concatenated in the order of their hex digit
+//! MIRR:        0 horizontal, 1 vertical, 2/3 single screen lower/upper, 4 four-screen, 5 mapper controlled
+//! BATR:        the PRG RAM is battery backed
+//! TVCI:        0 NTSC, 1 PAL, 2 both
+//! ```
+
+use super::{guess_ram_sizes, Mirroring, Region, Rom, RomFormat, DEFAULT_RAM_SIZE};
+
+pub const UNIF_TAG: [u8; 4] = *b"UNIF";
+const HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+
+/// Board names (without the "NES-"/"HVC-"/"UNL-" prefixes) and the iNES mapper and submapper they correspond to.
+const BOARDS: &[(&str, u16, u8)] = &[
+    ("NROM", 0, 0),
+    ("NROM-128", 0, 0),
+    ("NROM-256", 0, 0),
+    ("RROM", 0, 0),
+    ("SAROM", 1, 0),
+    ("SBROM", 1, 0),
+    ("SCROM", 1, 0),
+    ("SEROM", 1, 0),
+    ("SGROM", 1, 0),
+    ("SKROM", 1, 0),
+    ("SLROM", 1, 0),
+    ("SL1ROM", 1, 0),
+    ("SNROM", 1, 0),
+    ("SOROM", 1, 0),
+    ("SUROM", 1, 0),
+    ("SXROM", 1, 0),
+    ("UNROM", 2, 0),
+    ("UOROM", 2, 0),
+    ("CNROM", 3, 0),
+    ("TBROM", 4, 0),
+    ("TEROM", 4, 0),
+    ("TFROM", 4, 0),
+    ("TGROM", 4, 0),
+    ("TKROM", 4, 0),
+    ("TLROM", 4, 0),
+    ("TNROM", 4, 0),
+    ("TSROM", 4, 0),
+    ("TVROM", 4, 0),
+    ("EKROM", 5, 0),
+    ("ELROM", 5, 0),
+    ("ETROM", 5, 0),
+    ("EWROM", 5, 0),
+    ("AMROM", 7, 0),
+    ("ANROM", 7, 0),
+    ("AN1ROM", 7, 0),
+    ("AOROM", 7, 0),
+    ("PEEOROM", 9, 0),
+    ("PNROM", 9, 0),
+    ("FJROM", 10, 0),
+    ("FKROM", 10, 0),
+    ("GNROM", 66, 0),
+    ("MHROM", 66, 0),
+    ("BNROM", 34, 2),
+    ("AVE-NINA-01", 34, 1),
+    ("AVE-NINA-02", 34, 1),
+    ("BTR", 69, 0),
+    ("JLROM", 69, 0),
+    ("JSROM", 69, 0),
+    ("CAMERICA-BF9093", 71, 0),
+    ("CAMERICA-BF9096", 232, 0),
+    ("CAMERICA-BF9097", 71, 1),
+    ("COLORDREAMS-74*377", 11, 0),
+];
+
+/// Looks up the mapper and submapper of a board, the manufacturer prefix is optional.
+fn board_mapper(board: &str) -> Option<(u16, u8)> {
+    let name = ["NES-", "HVC-", "UNL-"]
+        .iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board);
+
+    BOARDS
+        .iter()
+        .find(|(known, _, _)| known.eq_ignore_ascii_case(name))
+        .map(|&(_, mapper, submapper)| (mapper, submapper))
+}
+
+/// Index of a PRGn/CHRn chunk, n being a single hex digit.
+fn rom_chunk_index(id: &[u8], kind: &[u8; 3]) -> Option<usize> {
+    if &id[..3] != kind {
+        return None;
+    }
+    (id[3] as char).to_digit(16).map(|index| index as usize)
+}
+
+impl Rom {
+    pub fn from_unif(raw: &[u8]) -> Result<Rom, String> {
+        if raw.len() < HEADER_SIZE || raw[0..4] != UNIF_TAG {
+            return Err("File is not in UNIF format".to_string());
+        }
+
+        let mut board = None;
+        let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+        let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+        let mut screen_mirroring = Mirroring::HORIZONTAl;
+        let mut battery = false;
+        let mut region = Region::Ntsc;
+
+        let mut pos = HEADER_SIZE;
+        while pos < raw.len() {
+            if raw.len() < pos + CHUNK_HEADER_SIZE {
+                return Err("UNIF chunk header is truncated".to_string());
+            }
+            let id = &raw[pos..pos + 4];
+            let length =
+                u32::from_le_bytes([raw[pos + 4], raw[pos + 5], raw[pos + 6], raw[pos + 7]])
+                    as usize;
+            let start = pos + CHUNK_HEADER_SIZE;
+            if raw.len() - start < length {
+                return Err(format!(
+                    "UNIF chunk {} is truncated",
+                    String::from_utf8_lossy(id)
+                ));
+            }
+            let data = &raw[start..start + length];
+
+            match id {
+                b"MAPR" => {
+                    let name = data.split(|&byte| byte == 0).next().unwrap_or_default();
+                    board = Some(String::from_utf8_lossy(name).trim().to_string());
+                }
+                b"MIRR" => {
+                    screen_mirroring = match data.first() {
+                        Some(1) => Mirroring::VERTICAL,
+                        Some(2) => Mirroring::SingleScreenLower,
+                        Some(3) => Mirroring::SingleScreenUpper,
+                        Some(4) => Mirroring::FOURSCREEN,
+                        // 5 means the mapper decides, every mapper with mirroring control sets it up by itself
+                        _ => Mirroring::HORIZONTAl,
+                    }
+                }
+                b"BATR" => battery = true,
+                b"TVCI" => {
+                    region = match data.first() {
+                        Some(1) => Region::Pal,
+                        Some(2) => Region::Multiple,
+                        _ => Region::Ntsc,
+                    }
+                }
+                _ => {
+                    if let Some(index) = rom_chunk_index(id, b"PRG") {
+                        prg_chunks[index] = Some(data);
+                    } else if let Some(index) = rom_chunk_index(id, b"CHR") {
+                        chr_chunks[index] = Some(data);
+                    }
+                    // everything else (NAME, READ, DINF, CRC checksums, ...) is informational only
+                }
+            }
+
+            pos = start + length;
+        }
+
+        let board = board.ok_or("UNIF file has no MAPR chunk".to_string())?;
+        let (mapper, submapper) =
+            board_mapper(&board).ok_or(format!("Unknown UNIF board {}", board))?;
+        if !crate::mapper::is_supported(mapper) {
+            return Err(format!(
+                "UNIF board {} (mapper {}) is not supported",
+                board, mapper
+            ));
+        }
+        let concat = |chunks: [Option<&[u8]>; 16]| {
+            chunks
+                .iter()
+                .flatten()
+                .flat_map(|chunk| chunk.iter())
+                .copied()
+                .collect::<Vec<u8>>()
+        };
+        let prg_rom = concat(prg_chunks);
+        let chr_rom = concat(chr_chunks);
+        if prg_rom.is_empty() {
+            return Err("UNIF file has no PRG ROM".to_string());
+        }
+        let (prg_ram_size, prg_nvram_size, chr_ram_size) =
+            guess_ram_sizes(DEFAULT_RAM_SIZE, battery, chr_rom.len());
+
+        Ok(Rom {
+            format: RomFormat::Unif,
+            prg_rom,
+            chr_rom,
+            trainer: None,
+            mapper,
+            submapper,
+            screen_mirroring,
+            battery,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size: 0,
+            region,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    fn create_unif(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut raw = UNIF_TAG.to_vec();
+        raw.extend(7u32.to_le_bytes());
+        raw.resize(HEADER_SIZE, 0);
+        for chunk in chunks {
+            raw.extend(chunk);
+        }
+        raw
+    }
+
+    #[test]
+    fn test_unif_is_converted_into_rom() {
+        let raw = create_unif(&[
+            chunk(b"MAPR", b"NES-ANROM\0"),
+            chunk(b"NAME", b"Test\0"),
+            chunk(b"PRG0", &[1; 0x8000]),
+            chunk(b"CHR0", &[2; 0x2000]),
+            chunk(b"MIRR", &[1]),
+            chunk(b"TVCI", &[1]),
+        ]);
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.format, RomFormat::Unif);
+        assert_eq!(rom.region, Region::Pal);
+
+        assert_eq!(rom.mapper, 7);
+        assert_eq!(rom.submapper, 0);
+        assert_eq!(rom.prg_rom, vec![1; 0x8000]);
+        assert_eq!(rom.chr_rom, vec![2; 0x2000]);
+        assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
+        assert!(!rom.battery);
+    }
+
+    #[test]
+    fn test_rom_chunks_are_ordered_by_index() {
+        let raw = create_unif(&[
+            chunk(b"PRG1", &[2; 0x4000]),
+            chunk(b"MAPR", b"BNROM\0"),
+            chunk(b"PRG0", &[1; 0x4000]),
+            chunk(b"BATR", &[1]),
+        ]);
+        let rom = Rom::from_unif(&raw).unwrap();
+
+        assert_eq!(rom.mapper, 34);
+        assert_eq!(rom.submapper, 2);
+        assert_eq!(rom.prg_rom[0x3fff], 1);
+        assert_eq!(rom.prg_rom[0x4000], 2);
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.screen_mirroring, Mirroring::HORIZONTAl);
+        assert!(rom.battery);
+    }
+
+    #[test]
+    fn test_invalid_unif_is_rejected() {
+        let unknown = create_unif(&[
+            chunk(b"MAPR", b"UNL-SOMETHING\0"),
+            chunk(b"PRG0", &[0; 0x4000]),
+        ]);
+        assert_eq!(
+            Rom::new(&unknown).err(),
+            Some("Unknown UNIF board UNL-SOMETHING".to_string())
+        );
+
+        let unsupported =
+            create_unif(&[chunk(b"MAPR", b"NES-SNROM\0"), chunk(b"PRG0", &[0; 0x4000])]);
+        assert_eq!(
+            Rom::new(&unsupported).err(),
+            Some("UNIF board NES-SNROM (mapper 1) is not supported".to_string())
+        );
+
+        let mut truncated = create_unif(&[chunk(b"MAPR", b"NROM\0"), chunk(b"PRG0", &[0; 0x4000])]);
+        truncated.truncate(truncated.len() - 1);
+        assert!(Rom::new(&truncated).is_err());
+
+        let no_board = create_unif(&[chunk(b"PRG0", &[0; 0x4000])]);
+        assert!(Rom::new(&no_board).is_err());
+    }
+}
@@ -13,7 +13,7 @@ use bitflags::bitflags;
 
 use crate::{
     bus::Bus,
-    interrupt::{Interrupt, BRK, NMI},
+    interrupt::{Interrupt, BRK, IRQ, NMI},
     opcode::{self, AddressingMode, Mnemonic, OpCode},
 };
 
@@ -113,6 +113,8 @@ pub struct CPU {
     pub stack_register: u8,
     // memory is accessed via this bus
     pub bus: Bus,
+    // set by the callback to stop `run_with_callback`
+    halted: bool,
 }
 
 impl Mem for CPU {
@@ -875,9 +877,15 @@ impl CPU {
             program_counter: 0,
             stack_register: STACK_RESET,
             bus,
+            halted: false,
         }
     }
 
+    /// Makes `run_with_callback` return before executing the next instruction.
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
+
     /// This function gets called on the RESET INTERRUPT signal.
     /// It resets all registers and sets the program counter to the value in address 0xFFFC.
     pub fn reset(&mut self) {
@@ -903,9 +911,17 @@ impl CPU {
 
         loop {
             callback(self);
+            if self.halted {
+                return;
+            }
 
             if let Some(_nmi) = self.bus.poll_nmi_status() {
                 self.interrupt(NMI);
+            } else if self.bus.poll_irq_status()
+                && !self.status.contains(CpuFlags::INTERRUPT_DISABLE)
+            {
+                // unlike NMI the IRQ line is level triggered, it keeps firing until the source is acknowledged
+                self.interrupt(IRQ);
             }
 
             let code = self.mem_read(self.program_counter);
@@ -3,6 +3,7 @@
 pub enum InterruptType {
     NMI,
     BRK,
+    IRQ,
 }
 
 // pub(super) might be useless here, I kept it because it is something new I haven't seen before.
@@ -28,3 +29,10 @@ pub(super) const BRK: Interrupt = Interrupt {
     b_flag_mask: 0b0011_0000,
     cpu_cycles: 1,
 };
+
+pub(super) const IRQ: Interrupt = Interrupt {
+    _itype: InterruptType::IRQ,
+    vector_addr: 0xFFFE,
+    b_flag_mask: 0b0010_0000,
+    cpu_cycles: 2,
+};
@@ -1,20 +1,41 @@
+use audio::{wav, SAMPLE_RATE};
 use bus::Bus;
-use cartridge::Rom;
+use cartridge::{Cartridge, DatabaseMatch, GameDatabase, Rom};
 use cpu::CPU;
+use mapper::{
+    fds::{disk::FdsDisk, Fds},
+    nsf::{Nsf, NsfMapper},
+};
 // use sdl2::{event::Event, keyboard::Keycode, pixels::Color, EventPump};
 
 use trace::trace;
 
+mod audio;
 mod bus;
 mod cartridge;
 mod cpu;
 mod interrupt;
+mod mapper;
 mod opcode;
+mod patch;
 mod ppu;
 mod render;
+mod romtool;
 mod tile_viewer;
 mod trace;
 
+const USAGE: &str = "usage:
+    rust-nes-emulator                       writes the CPU trace of nestest.nes
+    rust-nes-emulator run <file.nes|file.unf|file.fds> [--bios <file>] [--patch <file>] [--frames <count>] [--side <number>]
+        [--no-sprite-limit] [--region ntsc|pal|dendy] [--screenshot <file.bmp>] [--gamedb <file.tsv>]
+    rust-nes-emulator nsf <file.nsf> [--track <number>] [--seconds <count>] [--pal] [--out <file.wav>]
+    rust-nes-emulator info <file.nes|file.unf> [--json] [--gamedb <file.tsv>]
+    rust-nes-emulator chr-export <file.nes|file.unf> [--palette <c0,c1,c2,c3>] [--out <file.bmp>]
+    rust-nes-emulator chr-import <file.nes> <sheet.bmp> [--palette <c0,c1,c2,c3>] [--out <file.nes>]
+    rust-nes-emulator header <file.nes|file.unf> [--mapper <number>] [--submapper <number>] [--mirroring <name>]
+        [--battery yes|no] [--prg-ram <bytes>] [--prg-nvram <bytes>] [--chr-ram <bytes>] [--chr-nvram <bytes>]
+        [--region ntsc|pal|multi-region|dendy] [--nes2] [--out <file.nes>]";
+
 fn main() {
     // init sdl2
     // let sdl_context = sdl2::init().unwrap();
@@ -34,6 +55,28 @@ fn main() {
     //     .create_texture_target(PixelFormatEnum::RGB24, 32, 32)
     //     .unwrap();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None => {
+            trace_nestest();
+            Ok(())
+        }
+        Some("run") => run(&args[1..]),
+        Some("nsf") => render_nsf(&args[1..]),
+        Some("info") => romtool::info(&args[1..]),
+        Some("chr-export") => romtool::export_chr(&args[1..]),
+        Some("chr-import") => romtool::import_chr(&args[1..]),
+        Some("header") => romtool::edit_header(&args[1..]),
+        Some(_) => Err(USAGE.to_string()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn trace_nestest() {
     let bytes = std::fs::read("nestest.nes").unwrap();
     let rom = Rom::new(&bytes).unwrap();
     let bus = Bus::new(rom);
@@ -49,6 +92,194 @@ fn main() {
     });
 }
 
+/// Runs a game without any output for the given number of frames. Afterwards the save data (like the changes
+/// to an FDS disk) is written next to the game as `<file>.sav`, it's loaded again on the next run.
+/// An IPS/UPS/BPS patch is applied to the game before loading it, either the one passed with `--patch` or one
+/// with the same name as the game (`game.ips` for `game.nes`).
+/// `--screenshot` writes the last frame as a BMP file.
+/// `--no-sprite-limit` draws all sprites of a scanline instead of only the first 8, which gets rid of sprite flicker.
+/// Header mistakes of known dumps are corrected with the game database passed with `--gamedb`.
+/// The timing of the console follows the region of the game from the header or the game database, `--region`
+/// overrides it. FDS games are always run as NTSC since the disk system was only sold in Japan.
+fn run(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut bios = None;
+    let mut patch = None;
+    let mut frames = 600;
+    let mut side = 0;
+    let mut sprite_limit = true;
+    let mut forced_region = None;
+    let mut screenshot = None;
+    let mut gamedb = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bios" => bios = Some(args.next().ok_or(USAGE)?),
+            "--patch" => patch = Some(args.next().ok_or(USAGE)?.clone()),
+            "--frames" => frames = parse_number(args.next())?,
+            "--side" => side = parse_number(args.next())?,
+            "--no-sprite-limit" => sprite_limit = false,
+            "--screenshot" => screenshot = Some(args.next().ok_or(USAGE)?),
+            "--gamedb" => gamedb = Some(args.next().ok_or(USAGE)?),
+            "--region" => {
+                let region = args.next().ok_or(USAGE)?;
+                forced_region = Some(
+                    cartridge::Region::from_name(region)
+                        .ok_or(format!("Unknown region {}", region))?,
+                );
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let save_path = format!("{}.sav", path);
+
+    let mut bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    if let Some(patch) = patch.or_else(|| patch::find_patch(path)) {
+        let data = std::fs::read(&patch).map_err(|e| format!("Can't read {}: {}", patch, e))?;
+        bytes = patch::apply(&bytes, &data).map_err(|e| format!("Can't apply {}: {}", patch, e))?;
+        println!("Applied {}", patch);
+    }
+    let mut region = cartridge::Region::Ntsc;
+    let cartridge = if path.to_lowercase().ends_with(".fds") {
+        let bios = bios.ok_or("FDS disk images need the BIOS, pass it with --bios <file>")?;
+        let bios = std::fs::read(bios).map_err(|e| format!("Can't read {}: {}", bios, e))?;
+        let save = std::fs::read(&save_path).ok();
+        let disk = FdsDisk::new(&bytes, save.as_deref())?;
+        Cartridge::with_mapper(Box::new(Fds::new(disk, bios)?))
+    } else {
+        let mut rom = Rom::new(&bytes)?;
+        let database = match gamedb {
+            Some(path) => GameDatabase::with_file(path)?,
+            None => GameDatabase::bundled(),
+        };
+        if let Some(game) = rom.apply_database(&database) {
+            report_database_match(&game);
+        }
+        region = rom.region;
+        Cartridge::new(rom)
+    };
+
+    let region = forced_region.unwrap_or(region);
+    println!(
+        "Running with {} timing at {:.3} frames per second",
+        region.name(),
+        region.frame_rate()
+    );
+
+    let mut bus = Bus::with_cartridge(cartridge);
+    bus.set_region(region);
+    bus.set_sprite_limit(sprite_limit);
+    if side > 0 {
+        if side >= bus.disk_side_count() {
+            return Err(format!("There is no disk side {}", side));
+        }
+        bus.insert_disk(Some(side));
+    }
+
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+    cpu.run_with_callback(move |cpu| {
+        if cpu.bus.frame_count() >= frames {
+            cpu.halt();
+        }
+    });
+
+    if let Some(screenshot) = screenshot {
+        std::fs::write(screenshot, cpu.bus.frame().to_bmp())
+            .map_err(|e| format!("Can't write {}: {}", screenshot, e))?;
+        println!("Saved the last frame to {}", screenshot);
+    }
+    if let Some(data) = cpu.bus.save_data() {
+        std::fs::write(&save_path, data)
+            .map_err(|e| format!("Can't write {}: {}", save_path, e))?;
+        println!("Saved {}", save_path);
+    }
+    Ok(())
+}
+
+fn report_database_match(game: &DatabaseMatch) {
+    if game.corrections.is_empty() {
+        println!("Found {} in the game database", game.name);
+    } else {
+        println!(
+            "Found {} in the game database, corrected the header: {}",
+            game.name,
+            game.corrections.join(", ")
+        );
+    }
+}
+
+/// Plays a track of an NSF file and writes the audio output to a WAV file.
+fn render_nsf(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut out = None;
+    let mut track = None;
+    let mut seconds = 30;
+    let mut pal = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = Some(args.next().ok_or(USAGE)?.clone()),
+            "--track" => track = Some(parse_number(args.next())?),
+            "--seconds" => seconds = parse_number(args.next())?,
+            "--pal" => pal = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let out = out.unwrap_or_else(|| format!("{}.wav", path));
+
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let nsf = Nsf::new(&bytes)?;
+    let track = track.unwrap_or(nsf.starting_song as usize);
+    if track == 0 || track > nsf.song_count as usize {
+        return Err(format!(
+            "There is no track {}, the file has {}",
+            track, nsf.song_count
+        ));
+    }
+    let region = if pal {
+        cartridge::Region::Pal
+    } else {
+        nsf.region()
+    };
+    println!(
+        "{} - {} ({}), track {} of {}",
+        nsf.name, nsf.artist, nsf.copyright, track, nsf.song_count
+    );
+
+    let mapper = NsfMapper::new(nsf, track as u8, region);
+    let mut bus = Bus::with_cartridge(Cartridge::with_mapper(Box::new(mapper)));
+    bus.set_region(region);
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    let sample_count = seconds * SAMPLE_RATE as usize;
+    let mut samples = Vec::with_capacity(sample_count);
+    cpu.run_with_callback(|cpu| {
+        samples.extend(cpu.bus.take_audio_samples());
+        if samples.len() >= sample_count {
+            cpu.halt();
+        }
+    });
+    samples.truncate(sample_count);
+
+    std::fs::write(&out, wav::encode(&samples))
+        .map_err(|e| format!("Can't write {}: {}", out, e))?;
+    println!("Wrote {}", out);
+    Ok(())
+}
+
+fn parse_number(arg: Option<&String>) -> Result<usize, String> {
+    arg.and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| USAGE.to_string())
+}
+
 // fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
 //     let mut frame_idx = 0;
 //     let mut update = false;
@@ -0,0 +1,131 @@
+//! AxROM (mapper 7): 32 KiB PRG banks switched as a whole and a single-screen nametable selected by software.
+//! Boards come with 8 KiB of CHR RAM.
+//! https://www.nesdev.org/wiki/AxROM
+//!
+//! Bank select register at 0x8000 - 0xFFFF:
+//! ```
+//! 7  bit  0
+//! ---- ----
+//! xxxM xPPP
+//!    |  |||
+//!    |  +++- Select 32 KB PRG ROM bank for CPU $8000-$FFFF
+//!    +------ Select 1 KB VRAM page for all 4 nametables
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Axrom {
+            prg_rom: rom.prg_rom,
+            chr,
+            chr_is_ram,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xffff => {
+                let bank_count = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                let bank = self.prg_bank % bank_count;
+                self.prg_rom[(bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            self.prg_bank = (data & 0b111) as usize;
+            self.mirroring = if data & 0b1_0000 == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr[addr as usize]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cartridge::test::test_mapper_rom, mapper::test::banks};
+
+    #[test]
+    fn test_prg_bank_switching() {
+        let mut mapper = Axrom::new(test_mapper_rom(7, banks(PRG_BANK_SIZE, 4), vec![]));
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_prg(0xffff), 0);
+
+        mapper.write_prg(0x8000, 0b0000_0010);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xffff), 2);
+
+        // bank numbers wrap around for smaller boards
+        mapper.write_prg(0x8000, 0b0000_0111);
+        assert_eq!(mapper.read_prg(0xc000), 3);
+    }
+
+    #[test]
+    fn test_16k_prg_is_mirrored() {
+        let mut prg = vec![0; 0x4000];
+        prg[0] = 0x11;
+        prg[0x3fff] = 0x22;
+        let mut mapper = Axrom::new(test_mapper_rom(7, prg, vec![]));
+        assert_eq!(mapper.read_prg(0x8000), 0x11);
+        assert_eq!(mapper.read_prg(0xc000), 0x11);
+        assert_eq!(mapper.read_prg(0xbfff), 0x22);
+        assert_eq!(mapper.read_prg(0xffff), 0x22);
+    }
+
+    #[test]
+    fn test_single_screen_select() {
+        let mut mapper = Axrom::new(test_mapper_rom(7, banks(PRG_BANK_SIZE, 1), vec![]));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.write_prg(0xc000, 0b0001_0000);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+
+        mapper.write_prg(0xc000, 0b0000_0000);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut mapper = Axrom::new(test_mapper_rom(7, banks(PRG_BANK_SIZE, 1), vec![]));
+        mapper.write_chr(0x1234, 0x66);
+        assert_eq!(mapper.read_chr(0x1234), 0x66);
+    }
+}
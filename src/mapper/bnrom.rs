@@ -0,0 +1,160 @@
+//! Mapper 34 covers two unrelated boards: BNROM (submapper 2) switches 32 KiB of PRG ROM through a register at
+//! 0x8000 - 0xFFFF and has CHR RAM, NINA-001 (submapper 1) switches PRG and two 4 KiB CHR ROM banks through
+//! registers that overlap its PRG RAM. Without a submapper the presence of more than 8 KiB CHR ROM means NINA-001.
+//! https://www.nesdev.org/wiki/INES_Mapper_034
+//!
+//! NINA-001 registers (writes also go to the PRG RAM underneath):
+//! ```
+//! 0x7FFD: 32 KiB PRG bank
+//! 0x7FFE: 4 KiB CHR bank at 0x0000
+//! 0x7FFF: 4 KiB CHR bank at 0x1000
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+pub struct Bnrom {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000], // only present on NINA-001 boards
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    nina001: bool,
+    prg_bank: usize,
+    chr_banks: [usize; 2],
+    mirroring: Mirroring,
+}
+
+impl Bnrom {
+    pub fn new(rom: Rom) -> Self {
+        let nina001 = match rom.submapper {
+            1 => true,
+            2 => false,
+            _ => rom.chr_rom.len() > 0x2000,
+        };
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Bnrom {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr,
+            chr_is_ram,
+            nina001,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff if self.nina001 => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xffff => {
+                let bank_count = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                let bank = self.prg_bank % bank_count;
+                self.prg_rom[(bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff if self.nina001 => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+                match addr {
+                    0x7ffd => self.prg_bank = (data & 0b1) as usize,
+                    0x7ffe => self.chr_banks[0] = (data & 0b1111) as usize,
+                    0x7fff => self.chr_banks[1] = (data & 0b1111) as usize,
+                    _ => {}
+                }
+            }
+            0x8000..=0xffff if !self.nina001 => self.prg_bank = data as usize,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        if !self.nina001 {
+            return self.chr[addr as usize];
+        }
+
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] % bank_count;
+        self.chr[bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cartridge::test::{test_mapper_rom, test_submapper_rom},
+        mapper::test::banks,
+    };
+
+    #[test]
+    fn test_bnrom() {
+        let mut mapper = Bnrom::new(test_submapper_rom(34, 2, banks(PRG_BANK_SIZE, 4), vec![]));
+        mapper.write_prg(0x8000, 3);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xffff), 3);
+
+        // registers of the other board do nothing
+        mapper.write_prg(0x7ffd, 1);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+
+        mapper.write_chr(0x1000, 0x66);
+        assert_eq!(mapper.read_chr(0x1000), 0x66);
+    }
+
+    #[test]
+    fn test_nina001() {
+        let mut mapper = Bnrom::new(test_submapper_rom(
+            34,
+            1,
+            banks(PRG_BANK_SIZE, 2),
+            banks(CHR_BANK_SIZE, 8),
+        ));
+        mapper.write_prg(0x7ffd, 1);
+        mapper.write_prg(0x7ffe, 5);
+        mapper.write_prg(0x7fff, 6);
+
+        assert_eq!(mapper.read_prg(0x8000), 1);
+        assert_eq!(mapper.read_chr(0x0000), 5);
+        assert_eq!(mapper.read_chr(0x1000), 6);
+        assert_eq!(mapper.read_prg(0x7fff), 6);
+
+        // BNROM register writes are ignored
+        mapper.write_prg(0x8000, 0);
+        assert_eq!(mapper.read_prg(0x8000), 1);
+    }
+
+    #[test]
+    fn test_board_detection_without_submapper() {
+        let bnrom = Bnrom::new(test_mapper_rom(34, banks(PRG_BANK_SIZE, 4), vec![]));
+        assert!(!bnrom.nina001);
+
+        let nina001 = Bnrom::new(test_mapper_rom(
+            34,
+            banks(PRG_BANK_SIZE, 2),
+            banks(CHR_BANK_SIZE, 8),
+        ));
+        assert!(nina001.nina001);
+    }
+}
@@ -0,0 +1,123 @@
+//! Camerica/Codemasters boards (mapper 71, BF909x): 16 KiB PRG bank at 0x8000 with the last bank fixed at 0xC000
+//! and CHR RAM. The BF9097 board used by Fire Hawk (submapper 1) additionally selects a single-screen nametable.
+//! https://www.nesdev.org/wiki/INES_Mapper_071
+//!
+//! Registers:
+//! ```
+//! 0x9000 - 0x9FFF: Fire Hawk only, bit 4 selects the upper or lower nametable
+//! 0xC000 - 0xFFFF: PRG bank at 0x8000
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+pub struct Camerica {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    fire_hawk: bool,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Camerica {
+    pub fn new(rom: Rom) -> Self {
+        let fire_hawk = rom.submapper == 1;
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Camerica {
+            prg_rom: rom.prg_rom,
+            chr,
+            chr_is_ram,
+            fire_hawk,
+            prg_bank: 0,
+            mirroring: if fire_hawk {
+                Mirroring::SingleScreenLower
+            } else {
+                rom.screen_mirroring
+            },
+        }
+    }
+}
+
+impl Mapper for Camerica {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match addr {
+            0x8000..=0xbfff => self.prg_bank % bank_count,
+            0xc000..=0xffff => bank_count - 1,
+            _ => return 0,
+        };
+        self.prg_rom[bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x9000..=0x9fff if self.fire_hawk => {
+                self.mirroring = if data & 0b1_0000 == 0 {
+                    Mirroring::SingleScreenLower
+                } else {
+                    Mirroring::SingleScreenUpper
+                }
+            }
+            0xc000..=0xffff => self.prg_bank = (data & 0b1111) as usize,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr[addr as usize]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cartridge::test::{test_mapper_rom, test_submapper_rom},
+        mapper::test::banks,
+    };
+
+    #[test]
+    fn test_prg_banks() {
+        let mut mapper = Camerica::new(test_mapper_rom(71, banks(PRG_BANK_SIZE, 8), vec![]));
+        mapper.write_prg(0xc000, 3);
+
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xbfff), 3);
+        assert_eq!(mapper.read_prg(0xc000), 7);
+    }
+
+    #[test]
+    fn test_fire_hawk_mirroring() {
+        let mut mapper = Camerica::new(test_submapper_rom(71, 1, banks(PRG_BANK_SIZE, 8), vec![]));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.write_prg(0x9000, 0b1_0000);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        // the PRG bank is not affected
+        assert_eq!(mapper.read_prg(0x8000), 0);
+    }
+
+    #[test]
+    fn test_mirroring_is_fixed_on_other_boards() {
+        let mut mapper = Camerica::new(test_mapper_rom(71, banks(PRG_BANK_SIZE, 8), vec![]));
+        let mirroring = mapper.mirroring();
+
+        mapper.write_prg(0x9000, 0b1_0000);
+        assert_eq!(mapper.mirroring(), mirroring);
+    }
+}
@@ -0,0 +1,384 @@
+//! The wavetable sound channel of the FDS RAM adapter. A 64 step, 6 bit waveform is played back at a 12 bit
+//! frequency that is bent by a modulation unit (a second 64 step table of frequency deltas). Both have an envelope.
+//! https://www.nesdev.org/wiki/FDS_audio
+//!
+//! Registers:
+//! ```
+//! 0x4040 - 0x407F: wavetable RAM, writable while bit 7 of 0x4089 is set
+//! 0x4080: volume envelope (bit 7: off, bit 6: increase, bits 0 - 5: speed or gain)
+//! 0x4082 - 0x4083: frequency low and high (bit 7: halt waveform, bit 6: halt envelopes)
+//! 0x4084: modulation envelope, same layout as 0x4080
+//! 0x4085: modulation counter
+//! 0x4086 - 0x4087: modulation frequency low and high (bit 7: halt modulation)
+//! 0x4088: modulation table write, only while modulation is halted
+//! 0x4089: bit 7: wavetable write enable, bits 0 - 1: master volume
+//! 0x408A: envelope speed multiplier
+//! 0x4090: volume gain (read)
+//! 0x4092: modulation gain (read)
+//! ```
+
+// the channel at full volume is about 2.4 times as loud as an APU pulse channel
+const AUDIO_SCALE: f32 = 0.0043;
+// output level for the master volumes 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [i32; 4] = [36, 24, 17, 14];
+// modulation table entries as counter changes, `None` resets the counter
+const MOD_STEPS: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+
+struct Envelope {
+    speed: u8,
+    gain: u8,
+    increase: bool,
+    off: bool,
+    timer: u32,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            speed: 0,
+            gain: 0,
+            increase: false,
+            off: true,
+            timer: 0,
+        }
+    }
+
+    fn write(&mut self, data: u8, master_speed: u8) {
+        self.speed = data & 0b11_1111;
+        self.increase = data & 0b0100_0000 != 0;
+        self.off = data & 0b1000_0000 != 0;
+        self.reset_timer(master_speed);
+        if self.off {
+            self.gain = self.speed;
+        }
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    /// Returns whether the gain was updated.
+    fn tick(&mut self, master_speed: u8) -> bool {
+        if self.off || master_speed == 0 {
+            return false;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return false;
+        }
+
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+        true
+    }
+}
+
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    wave_write_enabled: bool,
+    wave_position: usize,
+    wave_accumulator: u16,
+    frequency: u16,
+    halt_wave: bool,
+    halt_envelopes: bool,
+    master_volume: usize,
+    master_speed: u8,
+    volume: Envelope,
+    modulation: Envelope,
+    mod_table: [u8; 64],
+    mod_position: usize,
+    mod_accumulator: u16,
+    mod_frequency: u16,
+    mod_halted: bool,
+    mod_counter: i8,
+    mod_output: i32,
+    output: i32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write_enabled: false,
+            wave_position: 0,
+            wave_accumulator: 0,
+            frequency: 0,
+            halt_wave: false,
+            halt_envelopes: false,
+            master_volume: 0,
+            master_speed: 0xe8,
+            volume: Envelope::new(),
+            modulation: Envelope::new(),
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_accumulator: 0,
+            mod_frequency: 0,
+            mod_halted: true,
+            mod_counter: 0,
+            mod_output: 0,
+            output: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407f => self.wave_table[(addr - 0x4040) as usize],
+            0x4090 => 0b0100_0000 | self.volume.gain,
+            0x4092 => 0b0100_0000 | self.modulation.gain,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407f if self.wave_write_enabled => {
+                self.wave_table[(addr - 0x4040) as usize] = data & 0b11_1111
+            }
+            0x4080 => self.volume.write(data, self.master_speed),
+            0x4082 => self.frequency = self.frequency & 0x0f00 | data as u16,
+            0x4083 => {
+                self.frequency = self.frequency & 0x00ff | ((data & 0x0f) as u16) << 8;
+                self.halt_wave = data & 0b1000_0000 != 0;
+                self.halt_envelopes = data & 0b0100_0000 != 0;
+                if self.halt_wave {
+                    self.wave_position = 0;
+                }
+                if self.halt_envelopes {
+                    self.volume.reset_timer(self.master_speed);
+                    self.modulation.reset_timer(self.master_speed);
+                }
+            }
+            0x4084 => self.modulation.write(data, self.master_speed),
+            0x4085 => self.set_mod_counter((data & 0x7f) as i32),
+            0x4086 => self.mod_frequency = self.mod_frequency & 0x0f00 | data as u16,
+            0x4087 => {
+                self.mod_frequency = self.mod_frequency & 0x00ff | ((data & 0x0f) as u16) << 8;
+                self.mod_halted = data & 0b1000_0000 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            0x4088 if self.mod_halted => {
+                // every write fills two entries of the table
+                self.mod_table[self.mod_position] = data & 0b111;
+                self.mod_table[(self.mod_position + 1) & 0x3f] = data & 0b111;
+                self.mod_position = (self.mod_position + 2) & 0x3f;
+            }
+            0x4089 => {
+                self.wave_write_enabled = data & 0b1000_0000 != 0;
+                self.master_volume = (data & 0b11) as usize;
+            }
+            0x408a => {
+                self.master_speed = data;
+                self.volume.reset_timer(self.master_speed);
+                self.modulation.reset_timer(self.master_speed);
+            }
+            _ => {}
+        }
+    }
+
+    /// The counter is a 7 bit signed value.
+    fn set_mod_counter(&mut self, value: i32) {
+        let value = if value >= 64 {
+            value - 128
+        } else if value < -64 {
+            value + 128
+        } else {
+            value
+        };
+        self.mod_counter = value as i8;
+    }
+
+    /// The pitch bend for the current counter and gain, the formula is taken from the nesdev wiki.
+    fn update_mod_output(&mut self) {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0f;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= self.frequency as i32;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_output = temp;
+    }
+
+    fn tick_modulator(&mut self) -> bool {
+        if self.mod_halted || self.mod_frequency == 0 {
+            return false;
+        }
+
+        let (accumulator, overflow) = self.mod_accumulator.overflowing_add(self.mod_frequency);
+        self.mod_accumulator = accumulator;
+        if !overflow {
+            return false;
+        }
+
+        match MOD_STEPS[self.mod_table[self.mod_position] as usize] {
+            Some(step) => self.set_mod_counter(self.mod_counter as i32 + step as i32),
+            None => self.set_mod_counter(0),
+        }
+        self.mod_position = (self.mod_position + 1) & 0x3f;
+        true
+    }
+
+    fn update_output(&mut self) {
+        // the output is held while the wavetable is written
+        if !self.wave_write_enabled {
+            let level = self.volume.gain.min(32) as i32 * MASTER_VOLUMES[self.master_volume];
+            self.output = self.wave_table[self.wave_position] as i32 * level / 1152;
+        }
+    }
+
+    /// Called once per CPU cycle.
+    pub fn tick(&mut self) {
+        if !self.halt_wave && !self.halt_envelopes {
+            self.volume.tick(self.master_speed);
+            if self.modulation.tick(self.master_speed) {
+                self.update_mod_output();
+            }
+        }
+        if self.tick_modulator() {
+            self.update_mod_output();
+        }
+
+        if self.halt_wave {
+            self.wave_position = 0;
+            self.update_output();
+            return;
+        }
+
+        self.update_output();
+        let step = self.frequency as i32 + self.mod_output;
+        if step > 0 && !self.wave_write_enabled {
+            let (accumulator, overflow) = self.wave_accumulator.overflowing_add(step as u16);
+            self.wave_accumulator = accumulator;
+            if overflow {
+                self.wave_position = (self.wave_position + 1) & 0x3f;
+            }
+        }
+    }
+
+    /// Current output level, 0 - 63.
+    fn level(&self) -> u8 {
+        self.output as u8
+    }
+
+    pub fn output(&self) -> f32 {
+        self.level() as f32 * AUDIO_SCALE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_wave(audio: &mut FdsAudio, wave: impl Fn(u16) -> u8) {
+        audio.write(0x4089, 0b1000_0000);
+        for i in 0..64 {
+            audio.write(0x4040 + i, wave(i));
+        }
+        audio.write(0x4089, 0);
+    }
+
+    #[test]
+    fn test_wavetable_is_only_writable_when_enabled() {
+        let mut audio = FdsAudio::new();
+        audio.write(0x4040, 0x3f);
+        assert_eq!(audio.read(0x4040), 0);
+
+        write_wave(&mut audio, |_| 0x3f);
+        assert_eq!(audio.read(0x4040), 0x3f);
+        assert_eq!(audio.read(0x407f), 0x3f);
+    }
+
+    #[test]
+    fn test_wave_playback() {
+        let mut audio = FdsAudio::new();
+        // first half of the wave is high, second half low
+        write_wave(&mut audio, |i| if i < 32 { 0x3f } else { 0 });
+        audio.write(0x4080, 0b1010_0000); // envelope off, gain 32
+        audio.write(0x4082, 0x00);
+        audio.write(0x4083, 0x04); // frequency 0x400: one step every 64 cycles
+
+        audio.tick();
+        assert_eq!(audio.level(), 63);
+
+        let mut outputs = Vec::new();
+        for _ in 0..64 * 64 {
+            audio.tick();
+            outputs.push(audio.level());
+        }
+        let high = outputs.iter().filter(|&&output| output == 63).count();
+        assert_eq!(high, 32 * 64);
+    }
+
+    #[test]
+    fn test_master_volume() {
+        let mut audio = FdsAudio::new();
+        write_wave(&mut audio, |_| 0x3f);
+        audio.write(0x4080, 0b1010_0000);
+        audio.write(0x4089, 0b11); // 2/5
+        audio.tick();
+        assert_eq!(audio.level() as u32, 63 * 32 * 14 / 1152);
+    }
+
+    #[test]
+    fn test_volume_envelope() {
+        let mut audio = FdsAudio::new();
+        audio.write(0x408a, 1);
+        audio.write(0x4080, 0b0100_0000); // increase with speed 0: every 8 cycles
+        audio.write(0x4083, 0);
+
+        for _ in 0..8 * 4 {
+            audio.tick();
+        }
+        assert_eq!(audio.read(0x4090), 0b0100_0000 | 4);
+    }
+
+    #[test]
+    fn test_mod_table_writes() {
+        let mut audio = FdsAudio::new();
+        audio.write(0x4087, 0b1000_0000);
+        audio.write(0x4088, 0b101);
+        audio.write(0x4088, 0b011);
+        assert_eq!(audio.mod_table[..4], [0b101, 0b101, 0b011, 0b011]);
+
+        audio.write(0x4087, 0);
+        audio.write(0x4088, 0b111);
+        assert_eq!(audio.mod_table[4], 0);
+    }
+
+    #[test]
+    fn test_mod_counter_wraps() {
+        let mut audio = FdsAudio::new();
+        audio.write(0x4085, 0x7f);
+        assert_eq!(audio.mod_counter, -1);
+        audio.write(0x4085, 0x3f);
+        assert_eq!(audio.mod_counter, 63);
+    }
+}
@@ -0,0 +1,250 @@
+//! Disk images in the .fds format. The format only stores the blocks of each disk side, without the gaps between
+//! them and without checksums. The drive needs both, so they are added when loading and removed again when saving.
+//! https://www.nesdev.org/wiki/FDS_file_format
+//! https://www.nesdev.org/wiki/FDS_disk_format
+//!
+//! Modified disks are never written back to the image. Instead the changes are stored as an IPS patch that is
+//! applied on top of the image the next time it's loaded.
+
+use crate::patch::ips;
+
+pub const SIDE_SIZE: usize = 65500;
+const HEADER: [u8; 4] = [0x46, 0x44, 0x53, 0x1A]; // "FDS\x1a"
+const HEADER_SIZE: usize = 16;
+// the first block of every side starts with the disk verification string
+const DISK_INFO_START: &[u8] = b"\x01*NINTENDO-HVC*";
+
+// a side starts with 28300 bits of gap, every block is followed by 976 bits of gap
+pub const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+pub const GAP_END: u8 = 0x80;
+// room for the gaps on a completely filled side
+const GAPPED_SIDE_SIZE: usize = 0x14000;
+
+pub struct FdsDisk {
+    image: Vec<u8>,
+    header_size: usize,
+    sides: Vec<Vec<u8>>,
+}
+
+impl FdsDisk {
+    /// Loads an image with or without fwNES header. `save` is the patch returned by an earlier [`FdsDisk::save_data`].
+    pub fn new(image: &[u8], save: Option<&[u8]>) -> Result<FdsDisk, String> {
+        let patched = match save {
+            Some(save) => ips::apply(image, save)?,
+            None => image.to_vec(),
+        };
+
+        let header_size = if patched.starts_with(&HEADER) {
+            HEADER_SIZE
+        } else {
+            0
+        };
+        let data = &patched[header_size.min(patched.len())..];
+        if data.is_empty() || data.len() % SIDE_SIZE != 0 {
+            return Err("File is not a valid FDS disk image".to_string());
+        }
+
+        let sides: Vec<Vec<u8>> = data.chunks(SIDE_SIZE).map(add_gaps).collect();
+        if data
+            .chunks(SIDE_SIZE)
+            .any(|side| !side.starts_with(DISK_INFO_START))
+        {
+            return Err("Disk side doesn't start with the disk info block".to_string());
+        }
+
+        Ok(FdsDisk {
+            image: image.to_vec(),
+            header_size,
+            sides,
+        })
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    /// A side as the drive sees it, including gaps and checksums.
+    pub fn side(&mut self, side: usize) -> &mut [u8] {
+        &mut self.sides[side]
+    }
+
+    /// The changes to the original image as IPS patch, `None` if nothing changed.
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        let mut current = self.image[..self.header_size].to_vec();
+        for side in &self.sides {
+            current.extend(remove_gaps(side));
+        }
+
+        if current == self.image {
+            None
+        } else {
+            Some(ips::create(&self.image, &current))
+        }
+    }
+}
+
+/// The checksum the drive writes after every block, a CRC-16 that doesn't include the gap end mark.
+/// https://www.nesdev.org/wiki/FDS_disk_format#CRC
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc = Crc(0x8000);
+    data.iter().for_each(|&byte| crc.update(byte));
+    crc.update(0);
+    crc.update(0);
+    crc.0
+}
+
+pub struct Crc(pub u16);
+
+impl Crc {
+    pub fn update(&mut self, byte: u8) {
+        for bit in 0..8 {
+            let carry = self.0 & 1 != 0;
+            self.0 = (self.0 >> 1) | ((byte >> bit) as u16 & 1) << 15;
+            if carry {
+                self.0 ^= 0x8408;
+            }
+        }
+    }
+}
+
+/// Returns the length of the block starting with `block_type`, the file data block takes its size from the
+/// preceding file header block.
+fn block_length(block_type: u8, file_size: usize) -> Option<usize> {
+    match block_type {
+        1 => Some(56), // disk info
+        2 => Some(2),  // file amount
+        3 => Some(16), // file header
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+/// Returns the next block, or `None` when the data doesn't contain any more valid blocks.
+fn next_block<'a>(data: &'a [u8], pos: usize, file_size: &mut usize) -> Option<&'a [u8]> {
+    let length = block_length(*data.get(pos)?, *file_size)?;
+    let block = data.get(pos..pos + length)?;
+    if block[0] == 3 {
+        *file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+    }
+    Some(block)
+}
+
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut gapped = vec![0; LEADING_GAP];
+    let mut pos = 0;
+    let mut file_size = 0;
+
+    while let Some(block) = next_block(side, pos, &mut file_size) {
+        gapped.push(GAP_END);
+        gapped.extend(block);
+        gapped.extend(crc(block).to_le_bytes());
+        gapped.extend([0; BLOCK_GAP]);
+        pos += block.len();
+    }
+
+    gapped.resize(gapped.len().max(GAPPED_SIDE_SIZE), 0);
+    gapped
+}
+
+fn remove_gaps(gapped: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut pos = 0;
+    let mut file_size = 0;
+
+    loop {
+        while pos < gapped.len() && gapped[pos] == 0 {
+            pos += 1;
+        }
+        if gapped.get(pos) != Some(&GAP_END) {
+            break;
+        }
+
+        match next_block(gapped, pos + 1, &mut file_size) {
+            Some(block) => {
+                side.extend(block);
+                pos += 1 + block.len() + 2; // gap end mark and checksum
+            }
+            None => break,
+        }
+    }
+
+    side.resize(SIDE_SIZE, 0);
+    side
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// A disk side with a single file containing `file`.
+    pub fn test_side(file: &[u8]) -> Vec<u8> {
+        let mut side = DISK_INFO_START.to_vec();
+        side.resize(56, 0);
+        side.extend([2, 1]);
+        let mut header = vec![
+            3, 0, 0, b'F', b'I', b'L', b'E', b' ', b' ', b' ', b' ', 0, 0,
+        ];
+        header.extend((file.len() as u16).to_le_bytes());
+        header.push(0);
+        side.extend(header);
+        side.push(4);
+        side.extend(file);
+        side.resize(SIDE_SIZE, 0);
+        side
+    }
+
+    #[test]
+    fn test_images_with_and_without_header() {
+        let side = test_side(&[1, 2, 3]);
+        let mut with_header = HEADER.to_vec();
+        with_header.push(2);
+        with_header.resize(HEADER_SIZE, 0);
+        with_header.extend(&side);
+        with_header.extend(&side);
+
+        assert_eq!(FdsDisk::new(&side, None).unwrap().side_count(), 1);
+        assert_eq!(FdsDisk::new(&with_header, None).unwrap().side_count(), 2);
+        assert!(FdsDisk::new(&side[..1000], None).is_err());
+        assert!(FdsDisk::new(&vec![0; SIDE_SIZE], None).is_err());
+    }
+
+    #[test]
+    fn test_gaps() {
+        let side = test_side(&[1, 2, 3]);
+        let mut disk = FdsDisk::new(&side, None).unwrap();
+        let gapped = disk.side(0);
+
+        assert!(gapped[..LEADING_GAP].iter().all(|&byte| byte == 0));
+        assert_eq!(gapped[LEADING_GAP], GAP_END);
+        assert_eq!(gapped[LEADING_GAP + 1..LEADING_GAP + 16], *DISK_INFO_START);
+        let file_amount = LEADING_GAP + 1 + 56 + 2 + BLOCK_GAP;
+        let [crc_low, crc_high] = crc(&[2, 1]).to_le_bytes();
+        assert_eq!(
+            gapped[file_amount..file_amount + 5],
+            [GAP_END, 2, 1, crc_low, crc_high]
+        );
+
+        assert_eq!(remove_gaps(gapped), side);
+    }
+
+    #[test]
+    fn test_save_data() {
+        let side = test_side(&[1, 2, 3]);
+        let mut disk = FdsDisk::new(&side, None).unwrap();
+        assert_eq!(disk.save_data(), None);
+
+        // change the second byte of the file
+        let file_data = LEADING_GAP + (1 + 56 + 2 + BLOCK_GAP) + (1 + 2 + 2 + BLOCK_GAP);
+        let file_data = file_data + (1 + 16 + 2 + BLOCK_GAP) + 1 + 2;
+        assert_eq!(disk.side(0)[file_data], 2);
+        disk.side(0)[file_data] = 0x42;
+
+        let save = disk.save_data().unwrap();
+        let reloaded = FdsDisk::new(&side, Some(&save)).unwrap();
+        let mut expected = side.clone();
+        expected[56 + 2 + 16 + 2] = 0x42;
+        assert_eq!(reloaded.image, side);
+        assert_eq!(remove_gaps(&reloaded.sides[0]), expected);
+    }
+}
@@ -0,0 +1,497 @@
+//! Famicom Disk System. The RAM adapter plugs into the cartridge slot and provides 32 KiB of PRG RAM, 8 KiB of
+//! CHR RAM, a BIOS ROM, a timer IRQ, the serial interface to the disk drive and an extra sound channel.
+//! Games are loaded from disk into RAM by the BIOS, which isn't freely distributable and has to be provided.
+//! https://www.nesdev.org/wiki/Family_Computer_Disk_System
+//!
+//! CPU memory map:
+//! ```
+//! 0x4020 - 0x4026: timer, I/O enable, disk write data and disk control (write)
+//! 0x4030 - 0x4033: disk status, disk read data, drive status and external connector (read)
+//! 0x4040 - 0x4092: sound, see [`audio`]
+//! 0x6000 - 0xDFFF: PRG RAM
+//! 0xE000 - 0xFFFF: BIOS ROM
+//! ```
+
+pub mod audio;
+pub mod disk;
+
+use crate::cartridge::Mirroring;
+
+use self::{
+    audio::FdsAudio,
+    disk::{Crc, FdsDisk},
+};
+
+use super::Mapper;
+
+pub const BIOS_SIZE: usize = 0x2000;
+
+// the drive needs some time to get the head back to the start of the disk
+const HEAD_RETURN_CYCLES: u32 = 50000;
+// the drive transfers about 96.4 kbit/s, that's one byte every ~150 CPU cycles
+const BYTE_CYCLES: u32 = 150;
+
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    mirroring: Mirroring,
+    disk: FdsDisk,
+    side: Option<usize>,
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    previous_crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+    transfer_complete: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    position: usize,
+    delay: u32,
+    read_data: u8,
+    write_data: u8,
+    crc: Crc,
+    audio: FdsAudio,
+}
+
+impl Fds {
+    pub fn new(disk: FdsDisk, bios: Vec<u8>) -> Result<Self, String> {
+        if bios.len() != BIOS_SIZE {
+            return Err(format!(
+                "FDS BIOS has to be {} bytes, got {}",
+                BIOS_SIZE,
+                bios.len()
+            ));
+        }
+
+        Ok(Fds {
+            bios,
+            prg_ram: vec![0; 0x8000],
+            chr_ram: vec![0; 0x2000],
+            mirroring: Mirroring::HORIZONTAl,
+            disk,
+            side: Some(0),
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            previous_crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            transfer_complete: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            position: 0,
+            delay: 0,
+            read_data: 0,
+            write_data: 0,
+            crc: Crc(0),
+            audio: FdsAudio::new(),
+        })
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.motor_on = data & 0b0000_0001 != 0;
+        self.reset_transfer = data & 0b0000_0010 != 0;
+        self.read_mode = data & 0b0000_0100 != 0;
+        self.mirroring = if data & 0b0000_1000 == 0 {
+            Mirroring::VERTICAL
+        } else {
+            Mirroring::HORIZONTAl
+        };
+        self.crc_control = data & 0b0001_0000 != 0;
+        self.disk_ready = data & 0b0100_0000 != 0;
+        self.disk_irq_enabled = data & 0b1000_0000 != 0;
+        self.disk_irq = false;
+    }
+
+    fn tick_timer(&mut self) {
+        if !self.timer_enabled || !self.disk_registers_enabled {
+            return;
+        }
+
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            self.timer_enabled = self.timer_repeat;
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    /// Moves the disk under the head, one byte is read or written every [`BYTE_CYCLES`].
+    fn tick_drive(&mut self) {
+        let side = match self.side {
+            Some(side) if self.motor_on => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+
+        if self.end_of_head {
+            self.delay = HEAD_RETURN_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let data = self.disk.side(side);
+        if self.read_mode {
+            let byte = data[self.position];
+            let mut irq = self.disk_irq_enabled;
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if byte != 0 && !self.gap_ended {
+                // the gap end mark itself is transferred without an IRQ
+                self.gap_ended = true;
+                irq = false;
+            }
+
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = byte;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let byte = if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+                if !self.disk_ready {
+                    self.crc = Crc(0);
+                }
+                let byte = if self.disk_ready { self.write_data } else { 0 };
+                self.crc.update(byte);
+                byte
+            } else {
+                // after the block the drive writes the two checksum bytes on its own
+                if !self.previous_crc_control {
+                    self.crc.update(0);
+                    self.crc.update(0);
+                }
+                let byte = self.crc.0 as u8;
+                self.crc.0 >>= 8;
+                byte
+            };
+            data[self.position] = byte;
+            self.gap_ended = false;
+        }
+
+        self.previous_crc_control = self.crc_control;
+        self.position += 1;
+        if self.position >= data.len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4030 => {
+                let status = self.timer_irq as u8
+                    | (self.transfer_complete as u8) << 1
+                    | (self.end_of_head as u8) << 6;
+                self.timer_irq = false;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                status
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 => {
+                let no_disk = self.side.is_none();
+                // without a disk the drive also reports not ready and write protected
+                no_disk as u8 | ((no_disk || !self.scanning) as u8) << 1 | (no_disk as u8) << 2
+            }
+            0x4033 => 0b1000_0000, // battery is good
+            0x4040..=0x4092 if self.sound_registers_enabled => self.audio.read(addr),
+            0x6000..=0xdfff => self.prg_ram[(addr - 0x6000) as usize],
+            0xe000..=0xffff => self.bios[(addr - 0xe000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4023 => {
+                self.disk_registers_enabled = data & 0b01 != 0;
+                self.sound_registers_enabled = data & 0b10 != 0;
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4020..=0x4026 if !self.disk_registers_enabled => {}
+            0x4020 => self.timer_reload = self.timer_reload & 0xff00 | data as u16,
+            0x4021 => self.timer_reload = self.timer_reload & 0x00ff | (data as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = data & 0b01 != 0;
+                self.timer_enabled = data & 0b10 != 0;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => self.write_control(data),
+            0x4040..=0x408a if self.sound_registers_enabled => self.audio.write(addr, data),
+            0x6000..=0xdfff => self.prg_ram[(addr - 0x6000) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr_ram[addr as usize] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn cpu_tick(&mut self) {
+        self.tick_timer();
+        self.tick_drive();
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.disk.save_data()
+    }
+
+    fn disk_side_count(&self) -> usize {
+        self.disk.side_count()
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.side = side.filter(|&side| side < self.disk.side_count());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use disk::test::test_side;
+
+    fn new_fds() -> Fds {
+        let disk = FdsDisk::new(&[test_side(&[1, 2, 3]), test_side(&[4])].concat(), None);
+        let mut fds = Fds::new(disk.unwrap(), vec![0xea; BIOS_SIZE]).unwrap();
+        fds.write_prg(0x4023, 0b11);
+        fds
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut fds = new_fds();
+        fds.write_prg(0x6000, 1);
+        fds.write_prg(0xdfff, 2);
+        fds.write_prg(0xe000, 3);
+
+        assert_eq!(fds.read_prg(0x6000), 1);
+        assert_eq!(fds.read_prg(0xdfff), 2);
+        assert_eq!(fds.read_prg(0xe000), 0xea);
+        assert_eq!(fds.read_prg(0x4033), 0b1000_0000);
+
+        fds.write_chr(0x1fff, 4);
+        assert_eq!(fds.read_chr(0x1fff), 4);
+    }
+
+    #[test]
+    fn test_bios_size_is_checked() {
+        let disk = FdsDisk::new(&test_side(&[]), None).unwrap();
+        assert!(Fds::new(disk, vec![0; 0x1000]).is_err());
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut fds = new_fds();
+        fds.write_prg(0x4025, 0b0010_0000);
+        assert_eq!(fds.mirroring(), Mirroring::VERTICAL);
+        fds.write_prg(0x4025, 0b0010_1000);
+        assert_eq!(fds.mirroring(), Mirroring::HORIZONTAl);
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        let mut fds = new_fds();
+        fds.write_prg(0x4020, 2);
+        fds.write_prg(0x4021, 0);
+        fds.write_prg(0x4022, 0b11);
+
+        for _ in 0..2 {
+            fds.cpu_tick();
+        }
+        assert!(!fds.irq_pending());
+        fds.cpu_tick();
+        assert!(fds.irq_pending());
+
+        // reading the status acknowledges, the timer repeats
+        assert_eq!(fds.read_prg(0x4030) & 1, 1);
+        assert!(!fds.irq_pending());
+        for _ in 0..3 {
+            fds.cpu_tick();
+        }
+        assert!(fds.irq_pending());
+    }
+
+    #[test]
+    fn test_timer_needs_disk_registers() {
+        let mut fds = new_fds();
+        fds.write_prg(0x4022, 0b11);
+        fds.write_prg(0x4023, 0b10);
+        fds.cpu_tick();
+        assert!(!fds.irq_pending());
+
+        // the other registers are ignored too
+        fds.write_prg(0x4025, 0b0000_1000);
+        assert_eq!(fds.mirroring(), Mirroring::HORIZONTAl);
+    }
+
+    #[test]
+    fn test_disk_read() {
+        let mut fds = new_fds();
+        assert_eq!(fds.read_prg(0x4032) & 0b11, 0b10);
+
+        // motor on, read mode, IRQ after every byte
+        fds.write_prg(0x4025, 0b1110_0101);
+        let mut cycles = 0;
+        while !fds.irq_pending() {
+            fds.cpu_tick();
+            cycles += 1;
+            assert!(cycles < 1_000_000);
+        }
+
+        assert_eq!(fds.read_prg(0x4032) & 0b11, 0);
+        // the gap end mark isn't reported, the first byte is the block type of the disk info block
+        assert_eq!(fds.read_prg(0x4031), 0x01);
+        assert!(!fds.irq_pending());
+
+        let mut data = Vec::new();
+        while data.len() < 14 {
+            fds.cpu_tick();
+            if fds.irq_pending() {
+                data.push(fds.read_prg(0x4031));
+            }
+        }
+        assert_eq!(data, b"*NINTENDO-HVC*");
+    }
+
+    #[test]
+    fn test_disk_sides() {
+        let mut fds = new_fds();
+        assert_eq!(fds.disk_side_count(), 2);
+
+        fds.insert_disk(None);
+        assert_eq!(fds.read_prg(0x4032) & 0b111, 0b111);
+        fds.insert_disk(Some(1));
+        assert_eq!(fds.read_prg(0x4032) & 0b001, 0);
+        fds.insert_disk(Some(2));
+        assert_eq!(fds.read_prg(0x4032) & 0b001, 1);
+    }
+
+    #[test]
+    fn test_sound_registers_need_enable() {
+        let mut fds = new_fds();
+        fds.write_prg(0x4089, 0b1000_0000);
+        fds.write_prg(0x4040, 0x3f);
+        assert_eq!(fds.read_prg(0x4040), 0x3f);
+
+        fds.write_prg(0x4023, 0b01);
+        assert_eq!(fds.read_prg(0x4040), 0);
+    }
+
+    #[test]
+    fn test_disk_write_is_saved() {
+        fn write_byte(fds: &mut Fds, byte: u8) {
+            fds.write_prg(0x4024, byte);
+            let position = fds.position;
+            while fds.position == position {
+                fds.cpu_tick();
+            }
+        }
+
+        let mut fds = new_fds();
+        assert_eq!(fds.save_data(), None);
+
+        // rewrite the disk info block with a different manufacturer code
+        let mut block = test_side(&[])[..56].to_vec();
+        block[15] = 0xa4;
+
+        fds.write_prg(0x4025, 0b0110_0001);
+        while fds.position < disk::LEADING_GAP {
+            write_byte(&mut fds, 0);
+        }
+        write_byte(&mut fds, disk::GAP_END);
+        for &byte in &block {
+            write_byte(&mut fds, byte);
+        }
+        fds.write_prg(0x4025, 0b0111_0001);
+        write_byte(&mut fds, 0);
+        write_byte(&mut fds, 0);
+
+        let crc_position = disk::LEADING_GAP + 1 + block.len();
+        let crc = fds.disk.side(0)[crc_position..crc_position + 2].to_vec();
+        assert_eq!(crc, disk::crc(&block).to_le_bytes());
+
+        let save = fds.save_data().unwrap();
+        let image = [test_side(&[1, 2, 3]), test_side(&[4])].concat();
+        let mut reloaded = FdsDisk::new(&image, Some(&save)).unwrap();
+        assert_eq!(reloaded.side(0)[disk::LEADING_GAP + 1 + 15], 0xa4);
+        assert_eq!(reloaded.side(1)[disk::LEADING_GAP + 1 + 15], 0);
+    }
+}
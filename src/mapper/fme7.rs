@@ -0,0 +1,491 @@
+//! Sunsoft FME-7 and Sunsoft 5B (mapper 69). Registers are written in two steps: the command number goes to 0x8000,
+//! its parameter to 0xA000. The 5B is an FME-7 with a built-in Yamaha YM2149F (an AY-3-8910 clone) that adds
+//! three square channels with a shared noise generator and envelope.
+//! https://www.nesdev.org/wiki/Sunsoft_FME-7
+//! https://www.nesdev.org/wiki/Sunsoft_5B_audio
+//!
+//! Registers:
+//! ```
+//! 0x8000 - 0x9FFF: command
+//! 0xA000 - 0xBFFF: parameter
+//!     0x0 - 0x7: 1 KiB CHR banks
+//!     0x8: bank at 0x6000 (bit 7: RAM enable, bit 6: RAM instead of ROM, bits 0 - 5: ROM bank)
+//!     0x9 - 0xB: 8 KiB PRG banks at 0x8000, 0xA000 and 0xC000, 0xE000 is fixed to the last bank
+//!     0xC: mirroring (0: vertical, 1: horizontal, 2: single screen lower, 3: single screen upper)
+//!     0xD: IRQ control (bit 0: IRQ enable, bit 7: counter enable), every write acknowledges the IRQ
+//!     0xE - 0xF: IRQ counter low and high byte
+//! 0xC000 - 0xDFFF: 5B audio register select
+//! 0xE000 - 0xFFFF: 5B audio register write
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// a single channel at full volume is about as loud as an APU pulse channel at full volume
+const AUDIO_SCALE: f32 = 0.113;
+
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+}
+
+impl Tone {
+    fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+}
+
+/// The YM2149F inside the 5B. Registers:
+/// ```
+/// 0x0 - 0x5: tone periods of channels A, B and C (low 8 bits, high 4 bits)
+/// 0x6: noise period
+/// 0x7: disable tone (bits 0 - 2) and noise (bits 3 - 5) per channel
+/// 0x8 - 0xA: volume of channels A, B and C (bit 4 uses the envelope instead)
+/// 0xB - 0xC: envelope period low and high byte
+/// 0xD: envelope shape (bit 3: continue, bit 2: attack, bit 1: alternate, bit 0: hold)
+/// ```
+pub(super) struct Sunsoft5b {
+    register: u8,
+    tones: [Tone; 3],
+    disable: u8,
+    volumes: [u8; 3],
+    noise_period: u8,
+    noise_counter: u8,
+    noise_shift: u32,
+    envelope_period: u16,
+    envelope_counter: u16,
+    envelope_shape: u8,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_hold: Option<u8>,
+    divider: u8,
+    // the output levels are logarithmic, 1.5 dB per step of the 5 bit level
+    levels: [f32; 32],
+}
+
+impl Sunsoft5b {
+    pub(super) fn new() -> Self {
+        let mut levels = [0.0; 32];
+        for (level, value) in levels.iter_mut().enumerate().skip(1) {
+            *value = 10f32.powf((level as f32 - 31.0) * 1.5 / 20.0);
+        }
+
+        Sunsoft5b {
+            register: 0,
+            tones: std::array::from_fn(|_| Tone {
+                period: 0,
+                counter: 0,
+                output: false,
+            }),
+            disable: 0,
+            volumes: [0; 3],
+            noise_period: 0,
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope_period: 0,
+            envelope_counter: 0,
+            envelope_shape: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_hold: None,
+            divider: 0,
+            levels,
+        }
+    }
+
+    pub(super) fn select(&mut self, register: u8) {
+        self.register = register & 0b1111;
+    }
+
+    pub(super) fn write(&mut self, data: u8) {
+        match self.register {
+            0x0..=0x5 => {
+                let tone = &mut self.tones[self.register as usize / 2];
+                tone.period = if self.register & 1 == 0 {
+                    tone.period & 0x0f00 | data as u16
+                } else {
+                    tone.period & 0x00ff | ((data & 0x0f) as u16) << 8
+                };
+            }
+            0x6 => self.noise_period = data & 0b1_1111,
+            0x7 => self.disable = data,
+            0x8..=0xa => self.volumes[self.register as usize - 0x8] = data & 0b1_1111,
+            0xb => self.envelope_period = self.envelope_period & 0xff00 | data as u16,
+            0xc => self.envelope_period = self.envelope_period & 0x00ff | (data as u16) << 8,
+            0xd => {
+                self.envelope_shape = data & 0b1111;
+                self.envelope_step = 0;
+                self.envelope_counter = 0;
+                self.envelope_attack = data & 0b0100 != 0;
+                self.envelope_hold = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Called once per CPU cycle. The chip divides the CPU clock by 16 for the tone generators,
+    /// the noise generator runs at half of that and the envelope at twice that speed.
+    pub(super) fn tick(&mut self) {
+        self.divider = (self.divider + 1) % 32;
+
+        if self.divider & 0b111 == 0 {
+            self.envelope_counter += 1;
+            if self.envelope_counter >= self.envelope_period {
+                self.envelope_counter = 0;
+                self.clock_envelope();
+            }
+        }
+        if self.divider & 0b1111 == 0 {
+            self.tones.iter_mut().for_each(Tone::tick);
+        }
+        if self.divider == 0 {
+            self.noise_counter += 1;
+            if self.noise_counter >= self.noise_period {
+                self.noise_counter = 0;
+                let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+                self.noise_shift = (self.noise_shift >> 1) | feedback << 16;
+            }
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_hold.is_some() {
+            return;
+        }
+
+        self.envelope_step += 1;
+        if self.envelope_step < 32 {
+            return;
+        }
+
+        let shape = self.envelope_shape;
+        let end = if self.envelope_attack { 31 } else { 0 };
+        if shape & 0b1000 == 0 {
+            self.envelope_hold = Some(0);
+        } else if shape & 0b0001 != 0 {
+            // with alternate set the level jumps to the opposite end of the ramp before holding
+            let flipped = shape & 0b0010 != 0;
+            self.envelope_hold = Some(if flipped { 31 - end } else { end });
+        } else {
+            if shape & 0b0010 != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        match self.envelope_hold {
+            Some(level) => level,
+            None if self.envelope_attack => self.envelope_step,
+            None => 31 - self.envelope_step,
+        }
+    }
+
+    pub(super) fn output(&self) -> f32 {
+        let noise = self.noise_shift & 1 != 0;
+
+        (0..3)
+            .filter(|&channel| {
+                let tone_disabled = self.disable & (0b1 << channel) != 0;
+                let noise_disabled = self.disable & (0b1000 << channel) != 0;
+                (self.tones[channel].output || tone_disabled) && (noise || noise_disabled)
+            })
+            .map(|channel| {
+                let volume = self.volumes[channel];
+                let level = if volume & 0b1_0000 != 0 {
+                    self.envelope_level()
+                } else if volume == 0 {
+                    0
+                } else {
+                    // the 4 bit volume skips every other step of the 5 bit level
+                    volume * 2 + 1
+                };
+                self.levels[level as usize]
+            })
+            .sum::<f32>()
+            * AUDIO_SCALE
+    }
+}
+
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    command: u8,
+    chr_banks: [u8; 8],
+    prg_banks: [u8; 3],
+    low_bank: u8,
+    mirroring: Mirroring,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Fme7 {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr,
+            chr_is_ram,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 3],
+            low_bank: 0,
+            mirroring: rom.screen_mirroring,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn read_prg_rom(&self, bank: usize, addr: u16) -> u8 {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        self.prg_rom[(bank % bank_count) * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = data,
+            0x8 => self.low_bank = data,
+            0x9..=0xb => self.prg_banks[self.command as usize - 0x9] = data & 0b11_1111,
+            0xc => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::VERTICAL,
+                    1 => Mirroring::HORIZONTAl,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xd => {
+                self.irq_enabled = data & 0b0000_0001 != 0;
+                self.irq_counter_enabled = data & 0b1000_0000 != 0;
+                self.irq_pending = false;
+            }
+            0xe => self.irq_counter = self.irq_counter & 0xff00 | data as u16,
+            _ => self.irq_counter = self.irq_counter & 0x00ff | (data as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        let ram_selected = self.low_bank & 0b0100_0000 != 0;
+        let ram_enabled = self.low_bank & 0b1000_0000 != 0;
+
+        match addr {
+            0x6000..=0x7fff if ram_selected && ram_enabled => {
+                self.prg_ram[(addr - 0x6000) as usize]
+            }
+            0x6000..=0x7fff if ram_selected => 0,
+            0x6000..=0x7fff => self.read_prg_rom((self.low_bank & 0b11_1111) as usize, addr),
+            0x8000..=0xdfff => {
+                let slot = (addr - 0x8000) as usize / PRG_BANK_SIZE;
+                self.read_prg_rom(self.prg_banks[slot] as usize, addr)
+            }
+            0xe000..=0xffff => self.read_prg_rom(self.prg_rom.len() / PRG_BANK_SIZE - 1, addr),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff if self.low_bank & 0b1100_0000 == 0b1100_0000 => {
+                self.prg_ram[(addr - 0x6000) as usize] = data
+            }
+            0x8000..=0x9fff => self.command = data & 0b1111,
+            0xa000..=0xbfff => self.write_parameter(data),
+            0xc000..=0xdfff => self.audio.select(data),
+            0xe000..=0xffff => self.audio.write(data),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        let index = bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE;
+        self.chr[index % self.chr.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_tick(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xffff && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cartridge::test::test_mapper_rom, mapper::test::banks};
+
+    fn new_fme7() -> Fme7 {
+        Fme7::new(test_mapper_rom(
+            69,
+            banks(PRG_BANK_SIZE, 32),
+            banks(CHR_BANK_SIZE, 256),
+        ))
+    }
+
+    fn command(mapper: &mut Fme7, command: u8, parameter: u8) {
+        mapper.write_prg(0x8000, command);
+        mapper.write_prg(0xa000, parameter);
+    }
+
+    fn audio(mapper: &mut Fme7, register: u8, data: u8) {
+        mapper.write_prg(0xc000, register);
+        mapper.write_prg(0xe000, data);
+    }
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        let mut mapper = new_fme7();
+        command(&mut mapper, 0x9, 3);
+        command(&mut mapper, 0xa, 4);
+        command(&mut mapper, 0xb, 5);
+        command(&mut mapper, 0x7, 200);
+
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xa000), 4);
+        assert_eq!(mapper.read_prg(0xc000), 5);
+        assert_eq!(mapper.read_prg(0xe000), 31);
+        assert_eq!(mapper.read_chr(0x1c00), 200);
+    }
+
+    #[test]
+    fn test_bank_at_0x6000() {
+        let mut mapper = new_fme7();
+        command(&mut mapper, 0x8, 7);
+        assert_eq!(mapper.read_prg(0x6000), 7);
+
+        // RAM selected but disabled
+        command(&mut mapper, 0x8, 0b0100_0000);
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+
+        command(&mut mapper, 0x8, 0b1100_0000);
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut mapper = new_fme7();
+        command(&mut mapper, 0xc, 2);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        let mut mapper = new_fme7();
+        command(&mut mapper, 0xe, 2);
+        command(&mut mapper, 0xf, 0);
+        command(&mut mapper, 0xd, 0b1000_0001);
+
+        mapper.cpu_tick();
+        mapper.cpu_tick();
+        assert!(!mapper.irq_pending());
+        mapper.cpu_tick();
+        assert!(mapper.irq_pending());
+
+        command(&mut mapper, 0xd, 0b1000_0001);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_irq_counter_without_irq() {
+        let mut mapper = new_fme7();
+        command(&mut mapper, 0xd, 0b1000_0000);
+        mapper.cpu_tick();
+        assert!(!mapper.irq_pending());
+        assert_eq!(mapper.irq_counter, 0xffff);
+    }
+
+    #[test]
+    fn test_square_channel() {
+        let mut mapper = new_fme7();
+        audio(&mut mapper, 0x0, 1); // period 1: toggles every 16 CPU cycles
+        audio(&mut mapper, 0x7, 0b11_1110); // only tone A
+        audio(&mut mapper, 0x8, 0x0f);
+
+        let outputs: Vec<bool> = (0..64)
+            .map(|_| {
+                mapper.cpu_tick();
+                mapper.audio_output() > 0.0
+            })
+            .collect();
+        assert_eq!(outputs.iter().filter(|&&high| high).count(), 32);
+        assert!(outputs[15..31].iter().all(|&high| high == outputs[15]));
+        assert!(outputs[31..47].iter().all(|&high| high != outputs[15]));
+
+        let full = mapper.audio.levels[31] * AUDIO_SCALE;
+        audio(&mut mapper, 0x7, 0b11_1111); // tone disabled means constant output
+        mapper.cpu_tick();
+        assert_eq!(mapper.audio_output(), full);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let mut audio = Sunsoft5b::new();
+        audio.envelope_period = 1;
+        audio.envelope_shape = 0b1101; // attack and hold: ramps up once, then stays at the top
+        audio.envelope_attack = true;
+
+        for _ in 0..8 * 31 {
+            audio.tick();
+        }
+        assert_eq!(audio.envelope_level(), 31);
+        for _ in 0..8 * 10 {
+            audio.tick();
+        }
+        assert_eq!(audio.envelope_level(), 31);
+
+        audio.select(0xd);
+        audio.write(0b0000); // decay once, then silence
+        assert_eq!(audio.envelope_level(), 31);
+        for _ in 0..8 * 32 {
+            audio.tick();
+        }
+        assert_eq!(audio.envelope_level(), 0);
+    }
+}
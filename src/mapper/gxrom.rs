@@ -0,0 +1,113 @@
+//! GxROM (mapper 66) and Color Dreams (mapper 11). Both boards have a single latch that switches 32 KiB of PRG ROM
+//! and 8 KiB of CHR ROM at once, they only differ in the bit layout of the register.
+//! https://www.nesdev.org/wiki/GxROM
+//! https://www.nesdev.org/wiki/Color_Dreams
+//!
+//! Bank select register at 0x8000 - 0xFFFF:
+//! ```
+//! GxROM:        --PP --CC
+//! Color Dreams: CCCC --PP
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+pub struct Gxrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    color_dreams: bool,
+    prg_bank: usize,
+    chr_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Gxrom {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, _) = chr_or_ram(rom.chr_rom);
+
+        Gxrom {
+            prg_rom: rom.prg_rom,
+            chr,
+            color_dreams: rom.mapper == 11,
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Gxrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xffff => {
+                let bank_count = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+                let bank = self.prg_bank % bank_count;
+                self.prg_rom[(bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr < 0x8000 {
+            return;
+        }
+
+        if self.color_dreams {
+            self.prg_bank = (data & 0b11) as usize;
+            self.chr_bank = (data >> 4) as usize;
+        } else {
+            self.prg_bank = ((data >> 4) & 0b11) as usize;
+            self.chr_bank = (data & 0b11) as usize;
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        self.chr[(self.chr_bank % bank_count) * CHR_BANK_SIZE + addr as usize]
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cartridge::test::test_mapper_rom, mapper::test::banks};
+
+    #[test]
+    fn test_gxrom_banks() {
+        let mut mapper = Gxrom::new(test_mapper_rom(
+            66,
+            banks(PRG_BANK_SIZE, 4),
+            banks(CHR_BANK_SIZE, 4),
+        ));
+        mapper.write_prg(0x8000, 0b0010_0011);
+
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xffff), 2);
+        assert_eq!(mapper.read_chr(0x0000), 3);
+        assert_eq!(mapper.read_chr(0x1fff), 3);
+    }
+
+    #[test]
+    fn test_color_dreams_banks() {
+        let mut mapper = Gxrom::new(test_mapper_rom(
+            11,
+            banks(PRG_BANK_SIZE, 4),
+            banks(CHR_BANK_SIZE, 16),
+        ));
+        mapper.write_prg(0xc000, 0b1010_0001);
+
+        assert_eq!(mapper.read_prg(0x8000), 1);
+        assert_eq!(mapper.read_chr(0x0000), 10);
+    }
+}
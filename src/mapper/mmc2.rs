@@ -0,0 +1,257 @@
+//! MMC2 (mapper 9, PxROM) and MMC4 (mapper 10, FxROM). Both switch CHR banks automatically: each 4 KiB pattern
+//! table has two banks registered and a latch decides which one is used. The latch flips whenever the PPU fetches
+//! tile $FD or $FE from that pattern table, which lets a game change graphics mid-screen without any timing code.
+//! https://www.nesdev.org/wiki/MMC2
+//! https://www.nesdev.org/wiki/MMC4
+//!
+//! Registers:
+//! ```
+//! 0xA000 - 0xAFFF: PRG bank at 0x8000 (MMC2: 8 KiB, the rest is fixed to the last three banks;
+//!                                      MMC4: 16 KiB, 0xC000 is fixed to the last bank)
+//! 0xB000 - 0xBFFF: CHR bank at 0x0000 used while latch 0 is $FD
+//! 0xC000 - 0xCFFF: CHR bank at 0x0000 used while latch 0 is $FE
+//! 0xD000 - 0xDFFF: CHR bank at 0x1000 used while latch 1 is $FD
+//! 0xE000 - 0xEFFF: CHR bank at 0x1000 used while latch 1 is $FE
+//! 0xF000 - 0xFFFF: mirroring (0: vertical, 1: horizontal)
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const CHR_BANK_SIZE: usize = 0x1000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Latch {
+    FD,
+    FE,
+}
+
+pub struct Mmc2 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000], // only present on MMC4 boards
+    chr: Vec<u8>,
+    mmc4: bool,
+    prg_bank: usize,
+    // index 0 is the pattern table at 0x0000, index 1 the one at 0x1000
+    chr_banks_fd: [usize; 2],
+    chr_banks_fe: [usize; 2],
+    latches: [Latch; 2],
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, _) = chr_or_ram(rom.chr_rom);
+
+        Mmc2 {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr,
+            mmc4: rom.mapper == 10,
+            prg_bank: 0,
+            chr_banks_fd: [0; 2],
+            chr_banks_fe: [0; 2],
+            latches: [Latch::FE; 2],
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        if self.mmc4 {
+            0x4000
+        } else {
+            0x2000
+        }
+    }
+
+    /// The latch only changes after the triggering tile has been fetched, so the tile itself still uses the old bank.
+    /// MMC2 reacts to a single address for latch 0 while MMC4 uses the whole 8 byte range like latch 1.
+    fn update_latch(&mut self, addr: u16) {
+        match addr {
+            0x0fd8 => self.latches[0] = Latch::FD,
+            0x0fe8 => self.latches[0] = Latch::FE,
+            0x0fd9..=0x0fdf if self.mmc4 => self.latches[0] = Latch::FD,
+            0x0fe9..=0x0fef if self.mmc4 => self.latches[0] = Latch::FE,
+            0x1fd8..=0x1fdf => self.latches[1] = Latch::FD,
+            0x1fe8..=0x1fef => self.latches[1] = Latch::FE,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        let bank_size = self.prg_bank_size();
+        let bank_count = (self.prg_rom.len() / bank_size).max(1);
+
+        match addr {
+            0x6000..=0x7fff if self.mmc4 => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xffff => {
+                let slot = (addr - 0x8000) as usize / bank_size;
+                let slots = 0x8000 / bank_size;
+                let bank = match slot {
+                    0 => self.prg_bank % bank_count,
+                    // everything above the switchable bank is fixed to the end of the PRG ROM, small ROMs are mirrored
+                    _ => (bank_count * slots + slot - slots) % bank_count,
+                };
+                self.prg_rom[bank * bank_size + (addr as usize % bank_size)]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        let value = (data & 0b1_1111) as usize;
+
+        match addr {
+            0x6000..=0x7fff if self.mmc4 => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0xa000..=0xafff => self.prg_bank = (data & 0b1111) as usize,
+            0xb000..=0xbfff => self.chr_banks_fd[0] = value,
+            0xc000..=0xcfff => self.chr_banks_fe[0] = value,
+            0xd000..=0xdfff => self.chr_banks_fd[1] = value,
+            0xe000..=0xefff => self.chr_banks_fe[1] = value,
+            0xf000..=0xffff => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::VERTICAL
+                } else {
+                    Mirroring::HORIZONTAl
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let table = addr as usize / CHR_BANK_SIZE;
+        let bank = match self.latches[table] {
+            Latch::FD => self.chr_banks_fd[table],
+            Latch::FE => self.chr_banks_fe[table],
+        };
+        let bank_count = self.chr.len() / CHR_BANK_SIZE;
+        let data = self.chr[(bank % bank_count) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE];
+
+        self.update_latch(addr);
+        data
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cartridge::test::test_mapper_rom, mapper::test::banks};
+
+    #[test]
+    fn test_mmc2_prg_banks() {
+        let mut mapper = Mmc2::new(test_mapper_rom(
+            9,
+            banks(0x2000, 16),
+            banks(CHR_BANK_SIZE, 32),
+        ));
+        mapper.write_prg(0xa000, 5);
+
+        assert_eq!(mapper.read_prg(0x8000), 5);
+        assert_eq!(mapper.read_prg(0xa000), 13);
+        assert_eq!(mapper.read_prg(0xc000), 14);
+        assert_eq!(mapper.read_prg(0xe000), 15);
+    }
+
+    #[test]
+    fn test_prg_smaller_than_fixed_banks() {
+        let mut mapper = Mmc2::new(test_mapper_rom(
+            9,
+            banks(0x2000, 2),
+            banks(CHR_BANK_SIZE, 32),
+        ));
+        assert_eq!(mapper.read_prg(0xa000), 1);
+        assert_eq!(mapper.read_prg(0xc000), 0);
+        assert_eq!(mapper.read_prg(0xe000), 1);
+    }
+
+    #[test]
+    fn test_mmc4_prg_banks() {
+        let mut mapper = Mmc2::new(test_mapper_rom(
+            10,
+            banks(0x4000, 8),
+            banks(CHR_BANK_SIZE, 32),
+        ));
+        mapper.write_prg(0xa000, 3);
+
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xbfff), 3);
+        assert_eq!(mapper.read_prg(0xc000), 7);
+
+        mapper.write_prg(0x6000, 0x66);
+        assert_eq!(mapper.read_prg(0x6000), 0x66);
+    }
+
+    #[test]
+    fn test_chr_latch_switches_after_fetch() {
+        let mut mapper = Mmc2::new(test_mapper_rom(
+            9,
+            banks(0x2000, 4),
+            banks(CHR_BANK_SIZE, 32),
+        ));
+        mapper.write_prg(0xb000, 1); // 0x0000, latch $FD
+        mapper.write_prg(0xc000, 2); // 0x0000, latch $FE
+        mapper.write_prg(0xd000, 3); // 0x1000, latch $FD
+        mapper.write_prg(0xe000, 4); // 0x1000, latch $FE
+
+        assert_eq!(mapper.read_chr(0x0000), 2);
+        assert_eq!(mapper.read_chr(0x1000), 4);
+
+        // the fetch of tile $FD itself still uses the old bank
+        assert_eq!(mapper.read_chr(0x0fd8), 2);
+        assert_eq!(mapper.read_chr(0x0000), 1);
+        assert_eq!(mapper.read_chr(0x1000), 4);
+
+        assert_eq!(mapper.read_chr(0x1fdc), 4);
+        assert_eq!(mapper.read_chr(0x1000), 3);
+
+        mapper.read_chr(0x0fe8);
+        mapper.read_chr(0x1fe8);
+        assert_eq!(mapper.read_chr(0x0000), 2);
+        assert_eq!(mapper.read_chr(0x1000), 4);
+    }
+
+    #[test]
+    fn test_mmc2_latch0_only_reacts_to_single_address() {
+        let mut mmc2 = Mmc2::new(test_mapper_rom(
+            9,
+            banks(0x2000, 4),
+            banks(CHR_BANK_SIZE, 32),
+        ));
+        let mut mmc4 = Mmc2::new(test_mapper_rom(
+            10,
+            banks(0x4000, 2),
+            banks(CHR_BANK_SIZE, 32),
+        ));
+        for mapper in [&mut mmc2, &mut mmc4] {
+            mapper.write_prg(0xb000, 1);
+            mapper.write_prg(0xc000, 2);
+            mapper.read_chr(0x0fd9);
+        }
+
+        assert_eq!(mmc2.read_chr(0x0000), 2);
+        assert_eq!(mmc4.read_chr(0x0000), 1);
+    }
+
+    #[test]
+    fn test_mirroring_control() {
+        let mut mapper = Mmc2::new(test_mapper_rom(
+            9,
+            banks(0x2000, 4),
+            banks(CHR_BANK_SIZE, 2),
+        ));
+        mapper.write_prg(0xf000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::HORIZONTAl);
+        mapper.write_prg(0xf000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::VERTICAL);
+    }
+}
@@ -0,0 +1,728 @@
+//! MMC5 (mapper 5, ExROM): the most capable Nintendo mapper. Besides the usual PRG/CHR banking in several bank
+//! sizes it comes with 1 KiB of extra RAM (ExRAM) that can be used as an additional nametable or as extended
+//! attributes (a palette and CHR bank per tile), a nametable filled with a single tile, a vertical split screen,
+//! a scanline IRQ and an 8x8 bit hardware multiplier.
+//! https://www.nesdev.org/wiki/MMC5
+//!
+//! The MMC5 figures out what the PPU is doing by watching the fetches on the PPU bus and the writes to PPUCTRL and
+//! PPUMASK on the CPU bus. The PPU tells the mapper when a new scanline starts (see [`Mapper::notify_scanline`]),
+//! from there on every nametable fetch of the renderer is one background tile. The first 32 are the columns 2 - 33
+//! of the line, followed by the sprites and the two tiles prefetched as columns 0 - 1 of the next line. Every tile
+//! is followed by its two pattern fetches, the pattern fetches after those belong to sprites.
+//!
+//! Registers:
+//! ```
+//! 0x5100: PRG mode (0: 32 KiB, 1: 16 KiB + 16 KiB, 2: 16 KiB + 8 KiB + 8 KiB, 3: 4 x 8 KiB)
+//! 0x5101: CHR mode (0: 8 KiB, 1: 4 KiB, 2: 2 KiB, 3: 1 KiB)
+//! 0x5102 - 0x5103: PRG RAM write protection, writes are allowed when they are set to 0b10 and 0b01
+//! 0x5104: ExRAM mode (0: nametable, 1: extended attributes, 2: CPU RAM, 3: CPU read-only RAM)
+//! 0x5105: nametable mapping, two bits per nametable (0: VRAM page 0, 1: VRAM page 1, 2: ExRAM, 3: fill mode)
+//! 0x5106 - 0x5107: fill mode tile and attribute
+//! 0x5113 - 0x5117: PRG banks, bit 7 of 0x5114 - 0x5116 selects ROM (1) or RAM (0)
+//! 0x5120 - 0x512B: CHR banks, 0x5120 - 0x5127 are used for sprites, 0x5128 - 0x512B for the background
+//! 0x5130: upper CHR bank bits
+//! 0x5200 - 0x5202: vertical split mode, scroll and CHR bank
+//! 0x5203 - 0x5204: scanline IRQ compare value and IRQ enable/status
+//! 0x5205 - 0x5206: unsigned 8x8 bit multiplier, reading returns the 16 bit product
+//! 0x5C00 - 0x5FFF: ExRAM
+//! ```
+
+use crate::cartridge::{Mirroring, NametableAddr, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x10000; // the biggest configuration, 2 chips of 32 KiB
+const CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ExRamMode {
+    Nametable,
+    ExtendedAttributes,
+    Ram,
+    ReadOnlyRam,
+}
+
+/// What the last nametable access mapped to [`NametableAddr::Cartridge`] actually reads from.
+#[derive(Clone, Copy)]
+enum CartridgeNametable {
+    ExRam(u16),
+    Fill(u16),
+    /// Attribute byte of the extended attributes for the tile at this ExRAM offset.
+    ExtendedAttribute(u16),
+    /// Nametable or attribute byte of the split screen, offset into ExRAM.
+    Split(u16),
+}
+
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    ex_ram: [u8; 0x400],
+
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    ex_ram_mode: ExRamMode,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    prg_banks: [u8; 5], // 0x5113 - 0x5117
+    sprite_chr_banks: [u16; 8],
+    background_chr_banks: [u16; 4],
+    upper_chr_bits: u16,
+    // in 8x8 sprite mode the set of CHR registers written last is used for everything
+    last_chr_write_background: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_chr_bank: u8,
+
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    // state derived from snooping the PPU
+    sprites_8x16: bool,
+    rendering_enabled: bool,
+    in_frame: bool,
+    scanline: u16,
+    scanline_counter: u8,
+    tile_fetches: u16,
+    pattern_fetches: u16, // since the last tile
+    // screen position of the last tile fetched
+    tile_column: u16,
+    tile_scanline: u16,
+    last_tile: u16,
+    split_active: bool,
+    cartridge_nametable: CartridgeNametable,
+}
+
+impl Mmc5 {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, _) = chr_or_ram(rom.chr_rom);
+
+        Mmc5 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr,
+            ex_ram: [0; 0x400],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            ex_ram_mode: ExRamMode::Nametable,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xff],
+            sprite_chr_banks: [0; 8],
+            background_chr_banks: [0; 4],
+            upper_chr_bits: 0,
+            last_chr_write_background: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_chr_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            sprites_8x16: false,
+            rendering_enabled: false,
+            in_frame: false,
+            scanline: 0,
+            scanline_counter: 0,
+            tile_fetches: 0,
+            pattern_fetches: 0,
+            tile_column: 0,
+            tile_scanline: 0,
+            last_tile: 0,
+            split_active: false,
+            cartridge_nametable: CartridgeNametable::Fill(0),
+        }
+    }
+
+    /// Returns whether the 8 KiB slot at `addr` maps ROM and which 8 KiB bank it is.
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        let slot = (addr - 0x6000) as usize / PRG_BANK_SIZE;
+        if slot == 0 {
+            return (false, (self.prg_banks[0] & 0b111) as usize);
+        }
+
+        // the register is selected by the PRG mode, bigger banks ignore the lowest bank bits
+        let (register, bank_slots) = match (self.prg_mode, slot) {
+            (0, _) => (4, 4),
+            (1, 1 | 2) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 1 | 2) => (2, 2),
+            (2, 3) => (3, 1),
+            (2, _) => (4, 1),
+            (_, slot) => (slot, 1),
+        };
+        let value = self.prg_banks[register];
+        let offset = (slot - 1) % bank_slots;
+        let bank = (value & 0x7f) as usize & !(bank_slots - 1);
+
+        // 0x5117 always maps ROM
+        let is_rom = register == 4 || value & 0x80 != 0;
+        if is_rom {
+            (true, bank + offset)
+        } else {
+            (false, (bank + offset) & 0b111)
+        }
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    fn sprite_fetch(&self) -> bool {
+        self.in_frame && self.pattern_fetches >= 2
+    }
+
+    /// Maps a pattern table address to an offset into CHR memory using one of the two register sets.
+    /// The register used for a slot is always the last one belonging to it, e.g. 0x5127 for 8 KiB banks.
+    fn chr_addr(&self, addr: u16, background: bool) -> usize {
+        let bank_size = CHR_BANK_SIZE << (3 - self.chr_mode);
+        let slot = addr as usize / bank_size;
+        let registers_per_slot = 1 << (3 - self.chr_mode);
+        let bank = if background {
+            // the background set only has four registers which are repeated for both pattern tables
+            let registers_per_slot = registers_per_slot.min(4);
+            self.background_chr_banks[((slot + 1) * registers_per_slot - 1) % 4]
+        } else {
+            self.sprite_chr_banks[(slot + 1) * registers_per_slot - 1]
+        };
+
+        (bank as usize * bank_size + addr as usize % bank_size) % self.chr.len()
+    }
+
+    fn split_tile_row(&self) -> u16 {
+        (self.split_scroll as u16 + self.tile_scanline) % 240 / 8
+    }
+
+    fn in_split_region(&self, tile: u16) -> bool {
+        if self.split_control & 0x80 == 0 || self.ex_ram_mode == ExRamMode::Ram {
+            return false;
+        }
+        let threshold = (self.split_control & 0b1_1111) as u16;
+        if self.split_control & 0x40 == 0 {
+            tile < threshold
+        } else {
+            tile >= threshold
+        }
+    }
+
+    fn ex_ram_as_nametable(&self) -> bool {
+        matches!(
+            self.ex_ram_mode,
+            ExRamMode::Nametable | ExRamMode::ExtendedAttributes
+        )
+    }
+
+    /// The nametable as selected by 0x5105.
+    fn mapped_nametable(&mut self, addr: u16) -> NametableAddr {
+        let index = addr & 0x0fff;
+        let offset = index % 0x400;
+        match (self.nametable_mapping >> (index / 0x400 * 2)) & 0b11 {
+            0 => NametableAddr::Vram(offset),
+            1 => NametableAddr::Vram(0x400 + offset),
+            2 => {
+                self.cartridge_nametable = CartridgeNametable::ExRam(offset);
+                NametableAddr::Cartridge(offset)
+            }
+            _ => {
+                self.cartridge_nametable = CartridgeNametable::Fill(offset);
+                NametableAddr::Cartridge(offset)
+            }
+        }
+    }
+
+    /// Writes the nametable mapping into a mirroring, only used for the common patterns.
+    fn mapping_to_mirroring(&self) -> Mirroring {
+        match self.nametable_mapping {
+            0x44 => Mirroring::VERTICAL,
+            0x00 => Mirroring::SingleScreenLower,
+            0x55 => Mirroring::SingleScreenUpper,
+            // the MMC5 can produce layouts without an equivalent, the PPU always asks `map_nametable` anyway
+            _ => Mirroring::HORIZONTAl,
+        }
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x5204 => {
+                let status = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                status
+            }
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5c00..=0x5fff => match self.ex_ram_mode {
+                ExRamMode::Ram | ExRamMode::ReadOnlyRam => self.ex_ram[(addr - 0x5c00) as usize],
+                _ => 0,
+            },
+            0x6000..=0xffff => {
+                let (is_rom, bank) = self.prg_bank(addr);
+                let offset = addr as usize % PRG_BANK_SIZE;
+                if is_rom {
+                    let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+                    self.prg_rom[(bank % bank_count) * PRG_BANK_SIZE + offset]
+                } else {
+                    self.prg_ram[bank * PRG_BANK_SIZE + offset]
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5100 => self.prg_mode = data & 0b11,
+            0x5101 => self.chr_mode = data & 0b11,
+            0x5102 => self.prg_ram_protect[0] = data & 0b11,
+            0x5103 => self.prg_ram_protect[1] = data & 0b11,
+            0x5104 => {
+                self.ex_ram_mode = match data & 0b11 {
+                    0 => ExRamMode::Nametable,
+                    1 => ExRamMode::ExtendedAttributes,
+                    2 => ExRamMode::Ram,
+                    _ => ExRamMode::ReadOnlyRam,
+                }
+            }
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0b11,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = data,
+            0x5120..=0x5127 => {
+                self.sprite_chr_banks[(addr - 0x5120) as usize] = self.upper_chr_bits | data as u16;
+                self.last_chr_write_background = false;
+            }
+            0x5128..=0x512b => {
+                self.background_chr_banks[(addr - 0x5128) as usize] =
+                    self.upper_chr_bits | data as u16;
+                self.last_chr_write_background = true;
+            }
+            0x5130 => self.upper_chr_bits = ((data & 0b11) as u16) << 8,
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_chr_bank = data,
+            0x5203 => self.irq_compare = data,
+            0x5204 => self.irq_enabled = data & 0x80 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5c00..=0x5fff => {
+                let index = (addr - 0x5c00) as usize;
+                match self.ex_ram_mode {
+                    // while the PPU is not rendering the nametable modes can't be written properly
+                    ExRamMode::Nametable | ExRamMode::ExtendedAttributes => {
+                        self.ex_ram[index] = if self.in_frame { data } else { 0 }
+                    }
+                    ExRamMode::Ram => self.ex_ram[index] = data,
+                    ExRamMode::ReadOnlyRam => {}
+                }
+            }
+            0x6000..=0xffff => {
+                let (is_rom, bank) = self.prg_bank(addr);
+                if !is_rom && self.prg_ram_writable() {
+                    self.prg_ram[bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_addr(addr, self.last_chr_write_background)]
+    }
+
+    fn fetch_chr(&mut self, addr: u16) -> u8 {
+        let sprite_fetch = self.sprite_fetch();
+        if self.in_frame {
+            self.pattern_fetches += 1;
+        }
+
+        if self.in_frame && !sprite_fetch {
+            if self.split_active {
+                // the split screen brings its own vertical scroll, so the row inside the tile is replaced
+                let fine_y = (self.split_scroll as u16 + self.tile_scanline) % 8;
+                let offset = (addr & 0x0ff8 | fine_y) as usize;
+                let bank = self.split_chr_bank as usize * 0x1000;
+                return self.chr[(bank + offset % 0x1000) % self.chr.len()];
+            }
+            if self.ex_ram_mode == ExRamMode::ExtendedAttributes {
+                let bank = (self.ex_ram[self.last_tile as usize] & 0b11_1111) as usize
+                    | (self.upper_chr_bits as usize >> 2);
+                return self.chr[(bank * 0x1000 + addr as usize % 0x1000) % self.chr.len()];
+            }
+        }
+
+        let background = if self.sprites_8x16 {
+            self.in_frame && !sprite_fetch
+        } else {
+            self.last_chr_write_background
+        };
+        self.chr[self.chr_addr(addr, background)]
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mapping_to_mirroring()
+    }
+
+    fn map_nametable(&mut self, addr: u16) -> NametableAddr {
+        self.mapped_nametable(addr)
+    }
+
+    fn fetch_nametable(&mut self, addr: u16) -> NametableAddr {
+        let offset = (addr & 0x0fff) % 0x400;
+        let is_attribute = offset >= 0x3c0;
+
+        if self.in_frame {
+            if !is_attribute {
+                (self.tile_column, self.tile_scanline) = if self.tile_fetches < 32 {
+                    (self.tile_fetches + 2, self.scanline)
+                } else {
+                    (self.tile_fetches - 32, self.scanline + 1)
+                };
+                self.tile_fetches += 1;
+                self.pattern_fetches = 0;
+                self.last_tile = offset;
+                self.split_active = self.tile_column < 32 && self.in_split_region(self.tile_column);
+                if self.split_active {
+                    let row = self.split_tile_row();
+                    self.cartridge_nametable =
+                        CartridgeNametable::Split(row * 32 + self.tile_column);
+                    return NametableAddr::Cartridge(offset);
+                }
+            } else if self.split_active {
+                let row = self.split_tile_row();
+                self.cartridge_nametable =
+                    CartridgeNametable::Split(0x3c0 + (row / 4) * 8 + self.tile_column / 4);
+                return NametableAddr::Cartridge(offset);
+            } else if self.ex_ram_mode == ExRamMode::ExtendedAttributes {
+                self.cartridge_nametable = CartridgeNametable::ExtendedAttribute(self.last_tile);
+                return NametableAddr::Cartridge(offset);
+            }
+        }
+
+        self.mapped_nametable(addr)
+    }
+
+    fn read_nametable(&mut self, _offset: u16) -> u8 {
+        match self.cartridge_nametable {
+            CartridgeNametable::ExRam(offset) if self.ex_ram_as_nametable() => {
+                self.ex_ram[offset as usize]
+            }
+            CartridgeNametable::ExRam(_) => 0,
+            CartridgeNametable::Fill(offset) if offset >= 0x3c0 => {
+                self.fill_attribute * 0b0101_0101
+            }
+            CartridgeNametable::Fill(_) => self.fill_tile,
+            // the palette of the extended attributes applies to the whole tile, so all four quadrants get it
+            CartridgeNametable::ExtendedAttribute(tile) => {
+                (self.ex_ram[tile as usize] >> 6) * 0b0101_0101
+            }
+            CartridgeNametable::Split(offset) if offset >= 0x3c0 => {
+                let row = self.split_tile_row();
+                let shift = (row & 0b10) << 1 | (self.tile_column & 0b10);
+                ((self.ex_ram[offset as usize] >> shift) & 0b11) * 0b0101_0101
+            }
+            CartridgeNametable::Split(offset) => self.ex_ram[offset as usize],
+        }
+    }
+
+    fn write_nametable(&mut self, _offset: u16, data: u8) {
+        if let CartridgeNametable::ExRam(offset) = self.cartridge_nametable {
+            if self.ex_ram_as_nametable() {
+                self.ex_ram[offset as usize] = data;
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+
+    fn notify_ppu_register_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x2000 => self.sprites_8x16 = data & 0b0010_0000 != 0,
+            0x2001 => {
+                self.rendering_enabled = data & 0b0001_1000 != 0;
+                if !self.rendering_enabled {
+                    self.in_frame = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn notify_scanline(&mut self, scanline: u16) {
+        self.tile_fetches = 0;
+        self.pattern_fetches = 0;
+        self.split_active = false;
+
+        if !self.rendering_enabled || scanline >= 240 {
+            self.in_frame = false;
+            return;
+        }
+
+        self.scanline = scanline;
+        if !self.in_frame {
+            self.in_frame = true;
+            self.scanline_counter = 0;
+            self.irq_pending = false;
+        } else {
+            self.scanline_counter = self.scanline_counter.wrapping_add(1);
+            if self.scanline_counter == self.irq_compare {
+                self.irq_pending = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cartridge::test::test_mapper_rom, mapper::test::banks};
+
+    fn new_mmc5() -> Mmc5 {
+        Mmc5::new(test_mapper_rom(
+            5,
+            banks(PRG_BANK_SIZE, 16),
+            banks(CHR_BANK_SIZE, 64),
+        ))
+    }
+
+    fn start_frame(mapper: &mut Mmc5) {
+        mapper.notify_ppu_register_write(0x2001, 0b0001_1000);
+        mapper.notify_scanline(0);
+    }
+
+    #[test]
+    fn test_multiplier() {
+        let mut mapper = new_mmc5();
+        assert_eq!(mapper.read_prg(0x5205), 0x01);
+        assert_eq!(mapper.read_prg(0x5206), 0xfe);
+
+        mapper.write_prg(0x5205, 0x12);
+        mapper.write_prg(0x5206, 0x34);
+        assert_eq!(mapper.read_prg(0x5205), 0xa8);
+        assert_eq!(mapper.read_prg(0x5206), 0x03);
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5203, 3);
+        mapper.write_prg(0x5204, 0x80);
+        start_frame(&mut mapper);
+
+        mapper.notify_scanline(1);
+        mapper.notify_scanline(2);
+        assert!(!mapper.irq_pending());
+        assert_eq!(mapper.read_prg(0x5204), 0b0100_0000);
+
+        mapper.notify_scanline(3);
+        assert!(mapper.irq_pending());
+
+        // reading the status acknowledges the IRQ
+        assert_eq!(mapper.read_prg(0x5204), 0b1100_0000);
+        assert!(!mapper.irq_pending());
+
+        mapper.notify_scanline(240);
+        assert_eq!(mapper.read_prg(0x5204), 0);
+    }
+
+    #[test]
+    fn test_scanline_irq_disabled() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5203, 1);
+        start_frame(&mut mapper);
+        mapper.notify_scanline(1);
+
+        assert!(!mapper.irq_pending());
+        mapper.write_prg(0x5204, 0x80);
+        assert!(mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_no_irq_without_rendering() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5203, 1);
+        mapper.write_prg(0x5204, 0x80);
+        for scanline in 0..240 {
+            mapper.notify_scanline(scanline);
+        }
+
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        let mut mapper = new_mmc5();
+        // power on: mode 3 with 0x5117 pointing to the last bank
+        assert_eq!(mapper.read_prg(0xe000), 15);
+
+        mapper.write_prg(0x5100, 0);
+        mapper.write_prg(0x5117, 0x85);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xe000), 7);
+
+        mapper.write_prg(0x5100, 1);
+        mapper.write_prg(0x5115, 0x83);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xa000), 3);
+        assert_eq!(mapper.read_prg(0xc000), 4);
+
+        mapper.write_prg(0x5100, 2);
+        mapper.write_prg(0x5116, 0x89);
+        assert_eq!(mapper.read_prg(0xc000), 9);
+        assert_eq!(mapper.read_prg(0xe000), 5);
+
+        mapper.write_prg(0x5100, 3);
+        mapper.write_prg(0x5114, 0x8b);
+        assert_eq!(mapper.read_prg(0x8000), 11);
+    }
+
+    #[test]
+    fn test_prg_ram_write_protection() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5113, 1);
+        mapper.write_prg(0x6000, 0x66);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+
+        mapper.write_prg(0x5102, 0b10);
+        mapper.write_prg(0x5103, 0b01);
+        mapper.write_prg(0x6000, 0x66);
+        assert_eq!(mapper.read_prg(0x6000), 0x66);
+
+        // the same RAM bank can be mapped into the ROM area
+        mapper.write_prg(0x5114, 0x01);
+        assert_eq!(mapper.read_prg(0x8000), 0x66);
+    }
+
+    #[test]
+    fn test_chr_modes() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5101, 3);
+        mapper.write_prg(0x5122, 7);
+        assert_eq!(mapper.read_chr(0x0800), 7);
+
+        mapper.write_prg(0x5101, 1);
+        mapper.write_prg(0x5127, 3);
+        assert_eq!(mapper.read_chr(0x1000), 12);
+        assert_eq!(mapper.read_chr(0x1400), 13);
+
+        mapper.write_prg(0x5128 + 3, 2);
+        assert_eq!(mapper.read_chr(0x0000), 8);
+        assert_eq!(mapper.read_chr(0x1000), 8);
+    }
+
+    #[test]
+    fn test_fill_mode_and_ex_ram_nametable() {
+        let mut mapper = new_mmc5();
+        // nametable 0: fill mode, nametable 1: ExRAM
+        mapper.write_prg(0x5105, 0b0000_1011);
+        mapper.write_prg(0x5106, 0x42);
+        mapper.write_prg(0x5107, 0b10);
+
+        assert_eq!(
+            mapper.map_nametable(0x2005),
+            NametableAddr::Cartridge(0x005)
+        );
+        assert_eq!(mapper.read_nametable(0x005), 0x42);
+        mapper.map_nametable(0x23c0);
+        assert_eq!(mapper.read_nametable(0x3c0), 0b1010_1010);
+
+        mapper.write_prg(0x5104, 2);
+        mapper.write_prg(0x5c05, 0x66);
+        mapper.write_prg(0x5104, 0);
+        mapper.map_nametable(0x2405);
+        assert_eq!(mapper.read_nametable(0x005), 0x66);
+
+        assert_eq!(mapper.map_nametable(0x2805), NametableAddr::Vram(0x005));
+    }
+
+    #[test]
+    fn test_extended_attributes() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5104, 2);
+        mapper.write_prg(0x5c00 + 33, 0b1100_0010); // palette 3, 4 KiB CHR bank 2
+        mapper.write_prg(0x5104, 1);
+        start_frame(&mut mapper);
+
+        mapper.fetch_nametable(0x2000 + 33);
+        assert_eq!(
+            mapper.fetch_nametable(0x23c0),
+            NametableAddr::Cartridge(0x3c0)
+        );
+        assert_eq!(mapper.read_nametable(0x3c0), 0xff);
+        assert_eq!(mapper.fetch_chr(0x0010), 8);
+    }
+
+    #[test]
+    fn test_vertical_split() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5104, 2);
+        mapper.write_prg(0x5c00 + 32 + 1, 0x77); // second row, second tile
+        mapper.write_prg(0x5c00 + 0x3c0, 0b11); // attribute of the top left quadrant
+        mapper.write_prg(0x5104, 0);
+        mapper.write_prg(0x5200, 0x80 | 2); // split the two leftmost tiles
+        mapper.write_prg(0x5201, 7);
+        mapper.write_prg(0x5202, 3);
+        start_frame(&mut mapper);
+
+        // accesses through 0x2007 don't count as tiles
+        mapper.map_nametable(0x2000);
+        mapper.read_chr(0x0000);
+
+        // the first fetch of a line is the third tile, outside of the split
+        assert_eq!(mapper.fetch_nametable(0x2002), NametableAddr::Vram(0x002));
+        for column in 3..34 {
+            mapper.fetch_nametable(0x2000 + column);
+        }
+
+        // the first two tiles of the next line use its row of the split, scanline 1 with scroll 7
+        mapper.fetch_nametable(0x2000);
+        assert_eq!(
+            mapper.fetch_nametable(0x2001),
+            NametableAddr::Cartridge(0x001)
+        );
+        assert_eq!(mapper.read_nametable(0x001), 0x77);
+        mapper.fetch_nametable(0x23c0);
+        assert_eq!(mapper.read_nametable(0x3c0), 0xff);
+        assert_eq!(mapper.fetch_chr(0x0070), 12);
+    }
+
+    #[test]
+    fn test_sprite_fetches_between_tiles() {
+        let mut mapper = new_mmc5();
+        mapper.write_prg(0x5101, 3);
+        mapper.write_prg(0x5120, 5);
+        mapper.write_prg(0x5128, 9);
+        mapper.notify_ppu_register_write(0x2000, 0b0010_0000);
+        start_frame(&mut mapper);
+
+        let fetch_tile = |mapper: &mut Mmc5| {
+            mapper.fetch_nametable(0x2000);
+            mapper.fetch_nametable(0x23c0);
+            [mapper.fetch_chr(0x0000), mapper.fetch_chr(0x0008)]
+        };
+        for _ in 0..32 {
+            assert_eq!(fetch_tile(&mut mapper), [9, 9]);
+        }
+        // 8 sprites, then the first two tiles of the next line
+        for _ in 0..16 {
+            assert_eq!(mapper.fetch_chr(0x0000), 5);
+        }
+        assert_eq!(fetch_tile(&mut mapper), [9, 9]);
+        assert_eq!(fetch_tile(&mut mapper), [9, 9]);
+    }
+}
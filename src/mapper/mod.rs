@@ -0,0 +1,205 @@
+//! Mappers are the circuits on the cartridge that decide what the CPU and PPU actually see when they access
+//! cartridge space. The simplest ones (NROM) just wire the ROM chips to the buses, others switch banks of PRG/CHR
+//! memory in and out, change the nametable mirroring at runtime or even generate interrupts.
+//!
+//! The iNES header stores a mapper number, see https://www.nesdev.org/wiki/Mapper for the list.
+//!
+//! CPU memory map as seen by a mapper:
+//! ```
+//! 0x4020 - 0x5FFF: expansion area, rarely used
+//! 0x6000 - 0x7FFF: PRG RAM (SRAM), optionally battery backed
+//! 0x8000 - 0xFFFF: PRG ROM, writes to this area usually go to the mapper registers
+//! ```
+//! PPU memory map as seen by a mapper:
+//! ```
+//! 0x0000 - 0x1FFF: pattern tables (CHR ROM or CHR RAM)
+//! 0x2000 - 0x2FFF: nametables, usually backed by the console VRAM according to the mirroring
+//! ```
+
+pub mod axrom;
+pub mod bnrom;
+pub mod camerica;
+pub mod fds;
+pub mod fme7;
+pub mod gxrom;
+pub mod mmc2;
+pub mod mmc5;
+pub mod nrom;
+pub mod nsf;
+pub mod vrc;
+pub mod vrc6;
+
+use crate::cartridge::{Mirroring, NametableAddr, Rom};
+
+use self::{
+    axrom::Axrom, bnrom::Bnrom, camerica::Camerica, fme7::Fme7, gxrom::Gxrom, mmc2::Mmc2,
+    mmc5::Mmc5, nrom::Nrom, vrc::Vrc, vrc6::Vrc6,
+};
+
+pub trait Mapper {
+    /// CPU read in the range 0x4020 - 0xFFFF.
+    fn read_prg(&mut self, addr: u16) -> u8;
+
+    /// CPU write in the range 0x4020 - 0xFFFF.
+    fn write_prg(&mut self, addr: u16, data: u8);
+
+    /// PPU read in the range 0x0000 - 0x1FFF.
+    /// The fetches of the renderer end up here as well unless [`Mapper::fetch_chr`] is overridden, so mappers that
+    /// watch the PPU address bus (like the MMC2/MMC4 latches) are notified of every pattern table fetch.
+    fn read_chr(&mut self, addr: u16) -> u8;
+
+    /// Pattern table fetch of the renderer. Only mappers that have to tell these apart from the accesses through
+    /// 0x2007 (like the MMC5 counting the fetches of a scanline) override this.
+    fn fetch_chr(&mut self, addr: u16) -> u8 {
+        self.read_chr(addr)
+    }
+
+    /// PPU write in the range 0x0000 - 0x1FFF, only has an effect on boards with CHR RAM.
+    fn write_chr(&mut self, addr: u16, data: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// Decides whether a nametable access goes to the console VRAM or to memory on the cartridge.
+    fn map_nametable(&mut self, addr: u16) -> NametableAddr {
+        self.mirroring().map_nametable(addr)
+    }
+
+    /// Same as [`Mapper::map_nametable`] for the nametable fetches of the renderer, see [`Mapper::fetch_chr`].
+    fn fetch_nametable(&mut self, addr: u16) -> NametableAddr {
+        self.map_nametable(addr)
+    }
+
+    /// Only called for nametable accesses mapped to [`NametableAddr::Cartridge`].
+    fn read_nametable(&mut self, _offset: u16) -> u8 {
+        0
+    }
+
+    /// Only called for nametable accesses mapped to [`NametableAddr::Cartridge`].
+    fn write_nametable(&mut self, _offset: u16, _data: u8) {}
+
+    /// State of the mapper's IRQ line, the CPU services it as long as this returns `true`.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Called for CPU writes to the PPU registers 0x2000 - 0x2007. The cartridge is connected to the CPU bus,
+    /// so some mappers (MMC5) watch these writes to find out about the sprite size or whether rendering is enabled.
+    fn notify_ppu_register_write(&mut self, _addr: u16, _data: u8) {}
+
+    /// Called by the PPU when it starts working on a new scanline (0 - 239 visible, 240 - 261 vertical blank).
+    fn notify_scanline(&mut self, _scanline: u16) {}
+
+    /// Called once per CPU cycle, for mappers with cycle based IRQ counters or expansion audio.
+    fn cpu_tick(&mut self) {}
+
+    /// Current output of the expansion audio chip on the cartridge, on the same scale as the APU output (0.0 - 1.0).
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// Data that has to outlive the emulator, like the changes made to a disk. `None` if there is nothing to save.
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Number of disk sides for mappers with a disk drive, 0 for regular cartridges.
+    fn disk_side_count(&self) -> usize {
+        0
+    }
+
+    /// Changes the disk side in the drive, `None` ejects the disk.
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+}
+
+pub fn from_rom(rom: Rom) -> Box<dyn Mapper> {
+    match constructor(rom.mapper) {
+        Some(new) => new(rom),
+        None => {
+            println!(
+                "Mapper {} is not supported, falling back to NROM",
+                rom.mapper
+            );
+            Box::new(Nrom::new(rom))
+        }
+    }
+}
+
+/// Whether [`from_rom`] has an implementation for the mapper number.
+pub fn is_supported(mapper: u16) -> bool {
+    constructor(mapper).is_some()
+}
+
+/// The implementation of every supported mapper number.
+fn constructor(mapper: u16) -> Option<fn(Rom) -> Box<dyn Mapper>> {
+    let new: fn(Rom) -> Box<dyn Mapper> = match mapper {
+        0 => |rom| Box::new(Nrom::new(rom)),
+        5 => |rom| Box::new(Mmc5::new(rom)),
+        7 => |rom| Box::new(Axrom::new(rom)),
+        9 | 10 => |rom| Box::new(Mmc2::new(rom)),
+        11 | 66 => |rom| Box::new(Gxrom::new(rom)),
+        21 | 22 | 23 | 25 => |rom| Box::new(Vrc::new(rom)),
+        24 | 26 => |rom| Box::new(Vrc6::new(rom)),
+        34 => |rom| Box::new(Bnrom::new(rom)),
+        69 => |rom| Box::new(Fme7::new(rom)),
+        71 => |rom| Box::new(Camerica::new(rom)),
+        _ => return None,
+    };
+    Some(new)
+}
+
+/// Name of the chip or board behind a mapper number, `None` if it isn't known.
+pub fn name(mapper: u16, submapper: u8) -> Option<&'static str> {
+    let name = match (mapper, submapper) {
+        (0, _) => "NROM",
+        (1, _) => "MMC1",
+        (2, _) => "UxROM",
+        (3, _) => "CNROM",
+        (4, _) => "MMC3",
+        (5, _) => "MMC5",
+        (7, _) => "AxROM",
+        (9, _) => "MMC2",
+        (10, _) => "MMC4",
+        (11, _) => "Color Dreams",
+        (21, 1) => "VRC4a",
+        (21, 2) => "VRC4c",
+        (21, _) => "VRC4a/VRC4c",
+        (22, _) => "VRC2a",
+        (23, 1) => "VRC4f",
+        (23, 2) => "VRC4e",
+        (23, 3) => "VRC2b",
+        (23, _) => "VRC2b/VRC4e",
+        (24, _) => "VRC6a",
+        (25, 1) => "VRC4b",
+        (25, 2) => "VRC4d",
+        (25, 3) => "VRC2c",
+        (25, _) => "VRC4b/VRC4d",
+        (26, _) => "VRC6b",
+        (34, 1) => "NINA-001",
+        (34, 2) => "BNROM",
+        (34, _) => "BNROM/NINA-001",
+        (66, _) => "GxROM",
+        (69, _) => "Sunsoft FME-7",
+        (71, 1) => "Camerica BF9097",
+        (71, _) => "Camerica BF9093",
+        (232, _) => "Camerica BF9096",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Boards without CHR ROM have 8 KiB of CHR RAM instead.
+fn chr_or_ram(chr_rom: Vec<u8>) -> (Vec<u8>, bool) {
+    if chr_rom.is_empty() {
+        (vec![0; 0x2000], true)
+    } else {
+        (chr_rom, false)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    /// Creates `count` banks of `size` bytes, every bank is filled with its own bank number.
+    pub fn banks(size: usize, count: usize) -> Vec<u8> {
+        (0..count).flat_map(|bank| vec![bank as u8; size]).collect()
+    }
+}
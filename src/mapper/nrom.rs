@@ -0,0 +1,64 @@
+//! NROM (mapper 0): no bank switching at all. PRG ROM is either 16 KiB (mirrored into the upper half) or 32 KiB.
+//! https://www.nesdev.org/wiki/NROM
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Nrom {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr,
+            chr_is_ram,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    /// PRG Rom Size might be 16 KiB or 32 KiB.
+    /// Because [0x8000 … 0x10000] mapped region is 32 KiB of addressable space, the upper 16 KiB needs to be mapped to the lower 16 KiB (if a game has only 16 KiB of PRG ROM)
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xffff => {
+                let index = (addr - 0x8000) as usize % self.prg_rom.len();
+                self.prg_rom[index]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        // NROM has no registers, writes to the PRG ROM are ignored
+        if let 0x6000..=0x7fff = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr[addr as usize]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
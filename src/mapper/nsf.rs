@@ -0,0 +1,465 @@
+//! NSF music files. An NSF contains the sound code and data of a game together with the addresses of an INIT and a
+//! PLAY routine. There is no cartridge, so this builds a synthetic one: the file is mapped into the CPU address space
+//! (optionally with 4 KiB bank switching) and a tiny driver program calls INIT once and PLAY at the rate given in the
+//! header. PLAY is triggered through an IRQ of the cartridge, so the CPU runs unchanged.
+//! https://www.nesdev.org/wiki/NSF
+//!
+//! Header:
+//! ```
+//! 0x00 - 0x04: "NESM\x1a"
+//! 0x05: version
+//! 0x06: number of songs
+//! 0x07: starting song (1 based)
+//! 0x08 - 0x0D: load, init and play address
+//! 0x0E - 0x6D: song name, artist and copyright (32 bytes each, null terminated)
+//! 0x6E - 0x6F: play speed on NTSC in microseconds
+//! 0x70 - 0x77: initial bank numbers, all zero when the file doesn't use bank switching
+//! 0x78 - 0x79: play speed on PAL in microseconds
+//! 0x7A: bit 0: PAL, bit 1: NTSC and PAL
+//! 0x7B: expansion chips (bit 0: VRC6, bit 1: VRC7, bit 2: FDS, bit 3: MMC5, bit 4: Namco 163, bit 5: Sunsoft 5B)
+//! ```
+//!
+//! CPU memory map:
+//! ```
+//! 0x4040 - 0x408A: FDS sound
+//! 0x4100 - 0x4113: driver
+//! 0x5FF6 - 0x5FF7: bank registers for 0x6000 - 0x7FFF, only for tunes using FDS sound
+//! 0x5FF8 - 0x5FFF: bank registers for 0x8000 - 0xFFFF
+//! 0x6000 - 0x7FFF: RAM, NSF data for tunes using FDS sound
+//! 0x8000 - 0xFFFF: NSF data, the interrupt vectors point to the driver
+//! ```
+
+use crate::cartridge::{Mirroring, Region};
+
+use super::{fds::audio::FdsAudio, fme7::Sunsoft5b, vrc6::Vrc6Audio, Mapper};
+
+const HEADER_SIZE: usize = 0x80;
+const BANK_SIZE: usize = 0x1000;
+
+const VRC6: u8 = 0b00_0001;
+const FDS: u8 = 0b00_0100;
+const SUNSOFT_5B: u8 = 0b10_0000;
+
+const DRIVER_START: u16 = 0x4100;
+const DRIVER_IRQ: u16 = 0x4110;
+const DRIVER_RTI: u16 = 0x4113;
+
+pub struct Nsf {
+    pub song_count: u8,
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    pub banks: [u8; 8],
+    pub regions: u8,
+    pub expansion: u8,
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn new(raw: &[u8]) -> Result<Nsf, String> {
+        if !raw.starts_with(b"NESM\x1a") {
+            return Err("File is not in NSF format".to_string());
+        }
+        if raw.len() <= HEADER_SIZE {
+            return Err("NSF file doesn't contain any data".to_string());
+        }
+
+        let word = |index: usize| u16::from_le_bytes([raw[index], raw[index + 1]]);
+        let text = |index: usize| {
+            let field = &raw[index..index + 32];
+            let end = field.iter().position(|&c| c == 0).unwrap_or(32);
+            String::from_utf8_lossy(&field[..end]).to_string()
+        };
+
+        let nsf = Nsf {
+            song_count: raw[0x06],
+            starting_song: raw[0x07],
+            load_addr: word(0x08),
+            init_addr: word(0x0a),
+            play_addr: word(0x0c),
+            name: text(0x0e),
+            artist: text(0x2e),
+            copyright: text(0x4e),
+            ntsc_speed: word(0x6e),
+            banks: raw[0x70..0x78].try_into().unwrap(),
+            pal_speed: word(0x78),
+            regions: raw[0x7a] & 0b11,
+            expansion: raw[0x7b],
+            data: raw[HEADER_SIZE..].to_vec(),
+        };
+
+        // only the FDS has bank registers for 0x6000 - 0x7FFF
+        let banks_below_0x8000 = nsf.is_bank_switched() && nsf.expansion & FDS != 0;
+        if nsf.load_addr < 0x6000 || (!banks_below_0x8000 && nsf.load_addr < 0x8000) {
+            return Err(format!("Invalid NSF load address {:04X}", nsf.load_addr));
+        }
+        Ok(nsf)
+    }
+
+    pub fn is_bank_switched(&self) -> bool {
+        self.banks.iter().any(|&bank| bank != 0)
+    }
+
+    /// The region the file was made for, files that support both are played as NTSC.
+    pub fn region(&self) -> Region {
+        if self.regions == 0b01 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+}
+
+/// The synthetic cartridge that plays `song` (1 based) of an NSF file.
+pub struct NsfMapper {
+    data: Vec<u8>,
+    ram: [u8; 0x2000],
+    banks: [usize; 10], // 0x6000 - 0xFFFF
+    driver: [u8; 0x14],
+    play_period: f64,
+    play_counter: f64,
+    irq_pending: bool,
+    fds: Option<FdsAudio>,
+    vrc6: Option<Vrc6Audio>,
+    sunsoft_5b: Option<Sunsoft5b>,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: Nsf, song: u8, region: Region) -> Self {
+        // the data is aligned to the banks, with bank switching everything in front of the load address is padding
+        // without bank switching the data starts at 0x6000, the first two banks are the RAM of FDS tunes
+        let padding = if nsf.is_bank_switched() {
+            nsf.load_addr as usize % BANK_SIZE
+        } else {
+            nsf.load_addr as usize - 0x6000
+        };
+        // Dendy consoles run at 50 frames per second like PAL ones
+        let pal_timing = matches!(region, Region::Pal | Region::Dendy);
+        let mut data = vec![0; padding];
+        data.extend(&nsf.data);
+        data.resize(data.len().div_ceil(BANK_SIZE).max(10) * BANK_SIZE, 0);
+
+        let banks = if nsf.is_bank_switched() {
+            // 0x6000 - 0x7FFF start with the same banks as 0xE000 - 0xFFFF
+            let mut banks = [0; 10];
+            banks[..2].copy_from_slice(&nsf.banks[6..]);
+            banks[2..].copy_from_slice(&nsf.banks);
+            banks.map(|bank| bank as usize)
+        } else {
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+        };
+
+        let [init_low, init_high] = nsf.init_addr.to_le_bytes();
+        let [play_low, play_high] = nsf.play_addr.to_le_bytes();
+        #[rustfmt::skip]
+        let driver = [
+            0x78,                         // 0x4100: SEI
+            0xd8,                         // 0x4101: CLD
+            0xa2, 0xff,                   // 0x4102: LDX #$FF
+            0x9a,                         // 0x4104: TXS
+            0xa9, song.wrapping_sub(1),   // 0x4105: LDA #song
+            0xa2, pal_timing as u8,       // 0x4107: LDX #region
+            0x20, init_low, init_high,    // 0x4109: JSR INIT
+            0x58,                         // 0x410C: CLI
+            0x4c, 0x0d, 0x41,             // 0x410D: JMP $410D
+            0x20, play_low, play_high,    // 0x4110: JSR PLAY (IRQ handler)
+            0x40,                         // 0x4113: RTI
+        ];
+
+        // the play routine is called once per frame when the file doesn't set a speed
+        let (speed, default_speed) = if pal_timing {
+            (nsf.pal_speed, 19997)
+        } else {
+            (nsf.ntsc_speed, 16639)
+        };
+        let speed = if speed == 0 { default_speed } else { speed };
+
+        if nsf.expansion & !(VRC6 | FDS | SUNSOFT_5B) != 0 {
+            println!(
+                "NSF uses unsupported expansion chips {:06b}, they will be silent",
+                nsf.expansion & !(VRC6 | FDS | SUNSOFT_5B)
+            );
+        }
+
+        NsfMapper {
+            data,
+            ram: [0; 0x2000],
+            banks,
+            driver,
+            play_period: speed as f64 * region.cpu_clock_rate() / 1_000_000.0,
+            play_counter: 0.0,
+            irq_pending: false,
+            fds: (nsf.expansion & FDS != 0).then(FdsAudio::new),
+            vrc6: (nsf.expansion & VRC6 != 0).then(Vrc6Audio::new),
+            sunsoft_5b: (nsf.expansion & SUNSOFT_5B != 0).then(Sunsoft5b::new),
+        }
+    }
+
+    fn data_index(&self, addr: u16) -> usize {
+        let slot = (addr - 0x6000) as usize / BANK_SIZE;
+        let index = self.banks[slot] * BANK_SIZE + addr as usize % BANK_SIZE;
+        index % self.data.len()
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x4092 => self.fds.as_ref().map_or(0, |fds| fds.read(addr)),
+            0x4100..=0x4113 => self.driver[(addr - DRIVER_START) as usize],
+            0x6000..=0x7fff if self.fds.is_some() => self.data[self.data_index(addr)],
+            0x6000..=0x7fff => self.ram[(addr - 0x6000) as usize],
+            // the interrupt vectors point into the driver
+            0xfffa => DRIVER_RTI as u8,
+            0xfffb => (DRIVER_RTI >> 8) as u8,
+            0xfffc => DRIVER_START as u8,
+            0xfffd => (DRIVER_START >> 8) as u8,
+            0xfffe => {
+                // the CPU reads the vector when it services the IRQ
+                self.irq_pending = false;
+                DRIVER_IRQ as u8
+            }
+            0xffff => (DRIVER_IRQ >> 8) as u8,
+            0x8000..=0xfff9 => self.data[self.data_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x408a => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write(addr, data);
+                }
+            }
+            0x5ff6..=0x5ff7 if self.fds.is_some() => {
+                self.banks[(addr - 0x5ff6) as usize] = data as usize
+            }
+            0x5ff8..=0x5fff => self.banks[(addr - 0x5ff6) as usize] = data as usize,
+            // FDS tunes run from RAM
+            0x6000..=0xdfff if self.fds.is_some() => {
+                let index = self.data_index(addr);
+                self.data[index] = data;
+            }
+            0x6000..=0x7fff => self.ram[(addr - 0x6000) as usize] = data,
+            0x9000..=0xb002 if self.vrc6.is_some() => {
+                if let Some(vrc6) = &mut self.vrc6 {
+                    vrc6.write(addr & 0xf003, data);
+                }
+            }
+            0xc000..=0xdfff => {
+                if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+                    sunsoft_5b.select(data);
+                }
+            }
+            0xe000..=0xffff => {
+                if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+                    sunsoft_5b.write(data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::HORIZONTAl
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_tick(&mut self) {
+        self.play_counter += 1.0;
+        if self.play_counter >= self.play_period {
+            self.play_counter -= self.play_period;
+            self.irq_pending = true;
+        }
+
+        if let Some(fds) = &mut self.fds {
+            fds.tick();
+        }
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.tick();
+        }
+        if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+            sunsoft_5b.tick();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.fds.as_ref().map_or(0.0, FdsAudio::output)
+            + self.vrc6.as_ref().map_or(0.0, Vrc6Audio::output)
+            + self.sunsoft_5b.as_ref().map_or(0.0, Sunsoft5b::output)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{
+        bus::Bus,
+        cartridge::Cartridge,
+        cpu::{Mem, CPU},
+    };
+
+    /// An NSF file with the given load address and bank numbers, `data` is placed right after the header.
+    pub fn test_nsf(load_addr: u16, banks: [u8; 8], data: &[u8]) -> Vec<u8> {
+        let mut raw = b"NESM\x1a\x01\x03\x02".to_vec();
+        raw.extend(load_addr.to_le_bytes());
+        raw.extend(0x8000u16.to_le_bytes()); // init
+        raw.extend(0x8003u16.to_le_bytes()); // play
+        raw.extend(b"Song");
+        raw.resize(0x2e, 0);
+        raw.extend(b"Artist");
+        raw.resize(0x6e, 0);
+        raw.extend(16639u16.to_le_bytes());
+        raw.extend(banks);
+        raw.extend(19997u16.to_le_bytes());
+        raw.extend([0b10, VRC6]);
+        raw.resize(HEADER_SIZE, 0);
+        raw.extend(data);
+        raw
+    }
+
+    #[test]
+    fn test_header() {
+        let nsf = Nsf::new(&test_nsf(0x8000, [0; 8], &[0x60])).unwrap();
+        assert_eq!(nsf.song_count, 3);
+        assert_eq!(nsf.starting_song, 2);
+        assert_eq!(nsf.init_addr, 0x8000);
+        assert_eq!(nsf.play_addr, 0x8003);
+        assert_eq!(nsf.name, "Song");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "");
+        assert_eq!(nsf.region(), Region::Ntsc);
+        assert!(!nsf.is_bank_switched());
+
+        assert!(Nsf::new(b"NES\x1a").is_err());
+        assert!(Nsf::new(&test_nsf(0x6000, [0; 8], &[0x60])).is_err());
+        // bank switched data below 0x8000 is only visible with the FDS bank registers
+        assert!(Nsf::new(&test_nsf(0x6000, [1; 8], &[0x60])).is_err());
+    }
+
+    #[test]
+    fn test_data_without_bank_switching() {
+        let nsf = Nsf::new(&test_nsf(0x8100, [0; 8], &[1, 2, 3])).unwrap();
+        let mut mapper = NsfMapper::new(nsf, 1, Region::Ntsc);
+        assert_eq!(mapper.read_prg(0x8100), 1);
+        assert_eq!(mapper.read_prg(0x8102), 3);
+        assert_eq!(mapper.read_prg(0x80ff), 0);
+    }
+
+    #[test]
+    fn test_bank_switching() {
+        let data: Vec<u8> = (0..4).flat_map(|bank| vec![bank; BANK_SIZE]).collect();
+        let nsf = Nsf::new(&test_nsf(0x8000, [0, 1, 2, 3, 0, 1, 2, 3], &data)).unwrap();
+        let mut mapper = NsfMapper::new(nsf, 1, Region::Ntsc);
+        assert_eq!(mapper.read_prg(0x9000), 1);
+        assert_eq!(mapper.read_prg(0xf000), 3);
+
+        mapper.write_prg(0x5ff8, 3);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+    }
+
+    #[test]
+    fn test_fds_bank_switching_below_0x8000() {
+        let data: Vec<u8> = (0..4).flat_map(|bank| vec![bank; BANK_SIZE]).collect();
+        let mut raw = test_nsf(0x6000, [0, 1, 2, 3, 0, 1, 2, 3], &data);
+        raw[0x7b] = FDS;
+        let mut mapper = NsfMapper::new(Nsf::new(&raw).unwrap(), 1, Region::Ntsc);
+        assert_eq!(mapper.read_prg(0x6000), 2);
+        assert_eq!(mapper.read_prg(0x7000), 3);
+
+        mapper.write_prg(0x5ff6, 1);
+        assert_eq!(mapper.read_prg(0x6000), 1);
+        // the banks are RAM
+        mapper.write_prg(0x6000, 0x66);
+        assert_eq!(mapper.read_prg(0x6000), 0x66);
+        mapper.write_prg(0x5ff9, 1);
+        assert_eq!(mapper.read_prg(0x9000), 0x66);
+    }
+
+    #[test]
+    fn test_driver_and_vectors() {
+        let nsf = Nsf::new(&test_nsf(0x8000, [0; 8], &[0x60])).unwrap();
+        let mut mapper = NsfMapper::new(nsf, 2, Region::Pal);
+
+        assert_eq!(mapper.read_prg(0xfffc), 0x00);
+        assert_eq!(mapper.read_prg(0xfffd), 0x41);
+        // LDA #song - 1, LDX #region, JSR INIT
+        assert_eq!(mapper.read_prg(0x4106), 1);
+        assert_eq!(mapper.read_prg(0x4108), 1);
+        assert_eq!(mapper.read_prg(0x410a), 0x00);
+        assert_eq!(mapper.read_prg(0x410b), 0x80);
+        // JSR PLAY
+        assert_eq!(mapper.read_prg(0x4111), 0x03);
+        assert_eq!(mapper.read_prg(0x4112), 0x80);
+
+        // Dendy tunes get the PAL timing
+        let nsf = Nsf::new(&test_nsf(0x8000, [0; 8], &[0x60])).unwrap();
+        let mut mapper = NsfMapper::new(nsf, 1, Region::Dendy);
+        assert_eq!(mapper.read_prg(0x4108), 1);
+    }
+
+    #[test]
+    fn test_play_rate() {
+        let nsf = Nsf::new(&test_nsf(0x8000, [0; 8], &[0x60])).unwrap();
+        let mut mapper = NsfMapper::new(nsf, 1, Region::Ntsc);
+
+        let mut calls = 0;
+        for _ in 0..Region::Ntsc.cpu_clock_rate() as usize {
+            mapper.cpu_tick();
+            if mapper.irq_pending() {
+                calls += 1;
+                mapper.read_prg(0xfffe);
+            }
+        }
+        // 16639 microseconds is the usual 60.1 Hz
+        assert_eq!(calls, 60);
+    }
+
+    #[test]
+    fn test_vrc6_expansion() {
+        let nsf = Nsf::new(&test_nsf(0x8000, [0; 8], &[0x60])).unwrap();
+        let mut mapper = NsfMapper::new(nsf, 1, Region::Ntsc);
+        mapper.write_prg(0x9000, 0b1000_1111);
+        mapper.write_prg(0x9002, 0b1000_0000);
+        mapper.cpu_tick();
+        assert!(mapper.audio_output() > 0.0);
+    }
+
+    #[test]
+    fn test_cpu_runs_init_and_play() {
+        // INIT: LDA #$10, STA $01, RTS; PLAY: INC $00, RTS
+        let code = [0xa9, 0x10, 0x85, 0x01, 0x60, 0xe6, 0x00, 0x60];
+        let mut raw = test_nsf(0x8000, [0; 8], &code);
+        raw[0x0c..0x0e].copy_from_slice(&0x8005u16.to_le_bytes());
+
+        let mapper = NsfMapper::new(Nsf::new(&raw).unwrap(), 1, Region::Ntsc);
+        let mut cpu = CPU::new(Bus::with_cartridge(Cartridge::with_mapper(Box::new(
+            mapper,
+        ))));
+        cpu.reset();
+        cpu.run_with_callback(|cpu| {
+            if cpu.bus.frame_count() >= 60 {
+                cpu.halt();
+            }
+        });
+
+        assert_eq!(cpu.mem_read(0x01), 0x10);
+        assert!((59..=60).contains(&cpu.mem_read(0x00)));
+    }
+}
@@ -0,0 +1,380 @@
+//! Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25). Both chips are nearly identical, VRC4 adds a PRG swap mode,
+//! single-screen mirroring and an IRQ counter. The annoying part is that every board wires different CPU address
+//! lines to the register select pins of the chip, so the register at "0x8001" might be at 0x8002, 0x8004, 0x8008,
+//! 0x8040 or 0x8080 depending on the board. NES 2.0 submappers tell them apart, for old headers both possible
+//! wirings of a mapper number are decoded at once which works for almost every game.
+//! https://www.nesdev.org/wiki/VRC2_and_VRC4
+//!
+//! Registers (with the select pins normalized to A0 and A1):
+//! ```
+//! 0x8000 - 0x8003: PRG bank at 0x8000 (or 0xC000 in VRC4 swap mode)
+//! 0x9000 - 0x9001: mirroring (VRC2: 0 vertical, 1 horizontal; VRC4: additionally 2/3 single screen)
+//! 0x9002 - 0x9003: VRC4 PRG swap mode in bit 1
+//! 0xA000 - 0xA003: PRG bank at 0xA000
+//! 0xB000 - 0xE003: CHR banks, two registers (low and high bits) per 1 KiB bank
+//! 0xF000 - 0xF003: VRC4 IRQ latch low, latch high, control and acknowledge
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{chr_or_ram, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// The CPU address lines connected to the register select pins A0 and A1 of the chip.
+/// Several lines may be set at once for headers without submapper.
+pub(super) struct RegisterLines {
+    a0: u16,
+    a1: u16,
+}
+
+impl RegisterLines {
+    pub(super) const fn new(a0: u16, a1: u16) -> Self {
+        RegisterLines { a0, a1 }
+    }
+
+    /// Returns the register address as documented, e.g. 0x8001 for a write to 0x8004 on a VRC4e.
+    pub(super) fn normalize(&self, addr: u16) -> u16 {
+        let a0 = (addr & self.a0 != 0) as u16;
+        let a1 = (addr & self.a1 != 0) as u16;
+        addr & 0xf000 | a1 << 1 | a0
+    }
+}
+
+/// The IRQ counter shared by VRC4, VRC6 and VRC7. It either counts CPU cycles or emulates scanlines by using a
+/// prescaler that approximates 113.667 CPU cycles (341 PPU dots divided by 3).
+pub(super) struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pub(super) pending: bool,
+}
+
+impl VrcIrq {
+    pub(super) fn new() -> Self {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub(super) fn set_latch(&mut self, latch: u8) {
+        self.latch = latch;
+    }
+
+    pub(super) fn latch(&self) -> u8 {
+        self.latch
+    }
+
+    /// Control register: bit 0 enable after acknowledge, bit 1 enable, bit 2 cycle mode.
+    pub(super) fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 0b001 != 0;
+        self.enabled = data & 0b010 != 0;
+        self.cycle_mode = data & 0b100 != 0;
+        self.pending = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub(super) fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Called once per CPU cycle.
+    pub(super) fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+pub struct Vrc {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    lines: RegisterLines,
+    vrc2: bool,
+    // VRC2a ignores the lowest bit of the CHR bank numbers
+    chr_shift: u8,
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc {
+    pub fn new(rom: Rom) -> Self {
+        let (lines, vrc2) = match (rom.mapper, rom.submapper) {
+            (21, 1) => (RegisterLines::new(0x02, 0x04), false), // VRC4a
+            (21, 2) => (RegisterLines::new(0x40, 0x80), false), // VRC4c
+            (21, _) => (RegisterLines::new(0x42, 0x84), false),
+            (22, _) => (RegisterLines::new(0x02, 0x01), true), // VRC2a
+            (23, 1) => (RegisterLines::new(0x01, 0x02), false), // VRC4f
+            (23, 2) => (RegisterLines::new(0x04, 0x08), false), // VRC4e
+            (23, 3) => (RegisterLines::new(0x01, 0x02), true), // VRC2b
+            (23, _) => (RegisterLines::new(0x05, 0x0a), false),
+            (25, 1) => (RegisterLines::new(0x02, 0x01), false), // VRC4b
+            (25, 2) => (RegisterLines::new(0x08, 0x04), false), // VRC4d
+            (25, 3) => (RegisterLines::new(0x02, 0x01), true),  // VRC2c
+            (_, _) => (RegisterLines::new(0x0a, 0x05), false),
+        };
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Vrc {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr,
+            chr_is_ram,
+            lines,
+            vrc2,
+            chr_shift: (rom.mapper == 22) as u8,
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::VERTICAL,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn write_chr_bank(&mut self, addr: u16, data: u8) {
+        let index = (((addr >> 12) - 0xb) * 2 + ((addr & 0b10) >> 1)) as usize;
+        let bank = self.chr_banks[index];
+        self.chr_banks[index] = if addr & 1 == 0 {
+            bank & 0x1f0 | (data & 0x0f) as u16
+        } else {
+            bank & 0x00f | ((data & 0x1f) as u16) << 4
+        };
+    }
+}
+
+impl Mapper for Vrc {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match addr {
+            0x6000..=0x7fff => return self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0x9fff if self.prg_swap => bank_count - 2,
+            0x8000..=0x9fff => self.prg_banks[0] as usize,
+            0xa000..=0xbfff => self.prg_banks[1] as usize,
+            0xc000..=0xdfff if self.prg_swap => self.prg_banks[0] as usize,
+            0xc000..=0xdfff => bank_count - 2,
+            0xe000..=0xffff => bank_count - 1,
+            _ => return 0,
+        };
+        self.prg_rom[(bank % bank_count) * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7fff = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+            return;
+        }
+
+        let addr = self.lines.normalize(addr);
+        match addr {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0b1_1111,
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::VERTICAL
+                } else {
+                    Mirroring::HORIZONTAl
+                }
+            }
+            0x9000..=0x9001 => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::VERTICAL,
+                    1 => Mirroring::HORIZONTAl,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0x9002..=0x9003 => self.prg_swap = data & 0b10 != 0,
+            0xa000..=0xa003 => self.prg_banks[1] = data & 0b1_1111,
+            0xb000..=0xe003 => self.write_chr_bank(addr, data),
+            0xf000 if !self.vrc2 => {
+                let latch = self.irq.latch();
+                self.irq.set_latch(latch & 0xf0 | data & 0x0f);
+            }
+            0xf001 if !self.vrc2 => {
+                let latch = self.irq.latch();
+                self.irq.set_latch(latch & 0x0f | data << 4);
+            }
+            0xf002 if !self.vrc2 => self.irq.write_control(data),
+            0xf003 if !self.vrc2 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = (self.chr_banks[addr as usize / CHR_BANK_SIZE] >> self.chr_shift) as usize;
+        let index = bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE;
+        self.chr[index % self.chr.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cartridge::test::{test_mapper_rom, test_submapper_rom},
+        mapper::test::banks,
+    };
+
+    fn new_vrc(mapper: u8, submapper: u8) -> Vrc {
+        Vrc::new(test_submapper_rom(
+            mapper,
+            submapper,
+            banks(PRG_BANK_SIZE, 16),
+            banks(CHR_BANK_SIZE, 256),
+        ))
+    }
+
+    #[test]
+    fn test_register_lines_per_submapper() {
+        // the CHR bank 0 high bits register (0xB001) on every board
+        for (mapper, submapper, register) in [
+            (21, 1, 0xb002),
+            (21, 2, 0xb040),
+            (22, 0, 0xb002),
+            (23, 1, 0xb001),
+            (23, 2, 0xb004),
+            (23, 3, 0xb001),
+            (25, 1, 0xb002),
+            (25, 2, 0xb008),
+            (25, 3, 0xb002),
+        ] {
+            let mut mapper = new_vrc(mapper, submapper);
+            mapper.write_prg(register, 0x01);
+            assert_eq!(mapper.chr_banks[0], 0x10);
+        }
+    }
+
+    #[test]
+    fn test_ines_headers_decode_both_wirings() {
+        let mut mapper = Vrc::new(test_mapper_rom(
+            21,
+            banks(PRG_BANK_SIZE, 16),
+            banks(CHR_BANK_SIZE, 256),
+        ));
+        mapper.write_prg(0xb002, 0x01);
+        assert_eq!(mapper.chr_banks[0], 0x10);
+        mapper.write_prg(0xb080, 0x02);
+        assert_eq!(mapper.chr_banks[1], 0x02);
+    }
+
+    #[test]
+    fn test_prg_banks_and_swap_mode() {
+        let mut mapper = new_vrc(23, 1);
+        mapper.write_prg(0x8000, 3);
+        mapper.write_prg(0xa000, 4);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xa000), 4);
+        assert_eq!(mapper.read_prg(0xc000), 14);
+        assert_eq!(mapper.read_prg(0xe000), 15);
+
+        mapper.write_prg(0x9002, 0b10);
+        assert_eq!(mapper.read_prg(0x8000), 14);
+        assert_eq!(mapper.read_prg(0xc000), 3);
+    }
+
+    #[test]
+    fn test_vrc2a_chr_banks_ignore_lowest_bit() {
+        let mut mapper = new_vrc(22, 0);
+        mapper.write_prg(0xb000, 0x05);
+        assert_eq!(mapper.read_chr(0x0000), 2);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut vrc4 = new_vrc(23, 1);
+        vrc4.write_prg(0x9000, 3);
+        assert_eq!(vrc4.mirroring(), Mirroring::SingleScreenUpper);
+
+        let mut vrc2 = new_vrc(23, 3);
+        vrc2.write_prg(0x9000, 3);
+        assert_eq!(vrc2.mirroring(), Mirroring::HORIZONTAl);
+    }
+
+    #[test]
+    fn test_irq_cycle_mode() {
+        let mut mapper = new_vrc(23, 1);
+        mapper.write_prg(0xf000, 0x0d);
+        mapper.write_prg(0xf001, 0x0f); // latch 0xfd
+        mapper.write_prg(0xf002, 0b110);
+
+        mapper.cpu_tick();
+        mapper.cpu_tick();
+        assert!(!mapper.irq_pending());
+        mapper.cpu_tick();
+        assert!(mapper.irq_pending());
+
+        mapper.write_prg(0xf003, 0);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_irq_scanline_mode() {
+        let mut irq = VrcIrq::new();
+        irq.set_latch(0xff);
+        irq.write_control(0b010);
+
+        // one scanline takes 113 or 114 CPU cycles
+        for _ in 0..113 {
+            irq.tick();
+        }
+        assert!(!irq.pending);
+        irq.tick();
+        assert!(irq.pending);
+    }
+}
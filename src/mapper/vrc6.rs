@@ -0,0 +1,445 @@
+//! Konami VRC6 (mappers 24 and 26). Bank switching is straightforward, the interesting parts are the IRQ counter
+//! (shared with VRC4) and the expansion audio: two pulse channels and a sawtooth channel.
+//! Mapper 26 swaps the register select lines A0 and A1.
+//! https://www.nesdev.org/wiki/VRC6
+//! https://www.nesdev.org/wiki/VRC6_audio
+//!
+//! Registers:
+//! ```
+//! 0x8000 - 0x8003: 16 KiB PRG bank at 0x8000
+//! 0x9000 - 0x9002: pulse 1 (duty and volume, period low, enable and period high)
+//! 0x9003: audio control (halt, frequency scaling)
+//! 0xA000 - 0xA002: pulse 2
+//! 0xB000 - 0xB002: sawtooth (accumulator rate, period low, enable and period high)
+//! 0xB003: mirroring in bits 2 - 3, PRG RAM enable in bit 7
+//! 0xC000 - 0xC003: 8 KiB PRG bank at 0xC000, 0xE000 is fixed to the last bank
+//! 0xD000 - 0xE003: 1 KiB CHR banks
+//! 0xF000 - 0xF002: IRQ latch, control and acknowledge
+//! ```
+
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{
+    chr_or_ram,
+    vrc::{RegisterLines, VrcIrq},
+    Mapper,
+};
+
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// roughly the same volume per step as the APU pulse channels
+const AUDIO_SCALE: f32 = 0.00752;
+
+struct Pulse {
+    duty: u8,
+    volume: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn new() -> Self {
+        Pulse {
+            duty: 0,
+            volume: 0,
+            ignore_duty: false,
+            period: 0,
+            enabled: false,
+            divider: 0,
+            step: 15,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = data & 0b1000_0000 != 0;
+                self.duty = (data >> 4) & 0b111;
+                self.volume = data & 0b1111;
+            }
+            1 => self.period = self.period & 0x0f00 | data as u16,
+            _ => {
+                self.period = self.period & 0x00ff | ((data & 0x0f) as u16) << 8;
+                self.enabled = data & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.divider == 0 {
+            self.divider = self.period >> shift;
+            self.step = if self.step == 0 { 15 } else { self.step - 1 };
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+struct Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn new() -> Self {
+        Sawtooth {
+            rate: 0,
+            period: 0,
+            enabled: false,
+            divider: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0b11_1111,
+            1 => self.period = self.period & 0x0f00 | data as u16,
+            _ => {
+                self.period = self.period & 0x00ff | ((data & 0x0f) as u16) << 8;
+                self.enabled = data & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.period >> shift;
+
+        // the accumulator is only increased on every second clock and reset after the 7th addition
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 1 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// The sound channels, also used by NSF files that use the VRC6.
+pub(super) struct Vrc6Audio {
+    pulses: [Pulse; 2],
+    sawtooth: Sawtooth,
+    halt: bool,
+    frequency_shift: u8,
+}
+
+impl Vrc6Audio {
+    pub(super) fn new() -> Self {
+        Vrc6Audio {
+            pulses: [Pulse::new(), Pulse::new()],
+            sawtooth: Sawtooth::new(),
+            halt: false,
+            frequency_shift: 0,
+        }
+    }
+
+    /// Write to one of the sound registers, 0x9000 - 0x9003, 0xA000 - 0xA002 or 0xB000 - 0xB002.
+    pub(super) fn write(&mut self, addr: u16, data: u8) {
+        let register = addr & 0b11;
+        match addr {
+            0x9003 => {
+                self.halt = data & 0b001 != 0;
+                self.frequency_shift = match data & 0b110 {
+                    0 => 0,
+                    0b010 => 4,
+                    _ => 8,
+                };
+            }
+            0x9000..=0x9002 => self.pulses[0].write(register, data),
+            0xa000..=0xa002 => self.pulses[1].write(register, data),
+            0xb000..=0xb002 => self.sawtooth.write(register, data),
+            _ => {}
+        }
+    }
+
+    pub(super) fn tick(&mut self) {
+        if !self.halt {
+            self.pulses[0].tick(self.frequency_shift);
+            self.pulses[1].tick(self.frequency_shift);
+            self.sawtooth.tick(self.frequency_shift);
+        }
+    }
+
+    pub(super) fn output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        sum as f32 * AUDIO_SCALE
+    }
+}
+
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    prg_ram_enabled: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    lines: RegisterLines,
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(rom: Rom) -> Self {
+        let lines = if rom.mapper == 26 {
+            RegisterLines::new(0x02, 0x01)
+        } else {
+            RegisterLines::new(0x01, 0x02)
+        };
+        let (chr, chr_is_ram) = chr_or_ram(rom.chr_rom);
+
+        Vrc6 {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            prg_ram_enabled: false,
+            chr,
+            chr_is_ram,
+            lines,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::VERTICAL,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    fn read_prg_rom(&self, bank: usize, bank_size: usize, addr: u16) -> u8 {
+        let index = bank * bank_size + addr as usize % bank_size;
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xbfff => self.read_prg_rom(self.prg_bank_16k as usize, 0x4000, addr),
+            0xc000..=0xdfff => self.read_prg_rom(self.prg_bank_8k as usize, 0x2000, addr),
+            0xe000..=0xffff => self.read_prg_rom(self.prg_rom.len() / 0x2000 - 1, 0x2000, addr),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7fff = addr {
+            if self.prg_ram_enabled {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            return;
+        }
+
+        let addr = self.lines.normalize(addr);
+        let register = addr & 0b11;
+        match addr {
+            0x8000..=0x8003 => self.prg_bank_16k = data & 0x0f,
+            0x9000..=0xb002 => self.audio.write(addr, data),
+            0xb003 => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.mirroring = match (data >> 2) & 0b11 {
+                    0 => Mirroring::VERTICAL,
+                    1 => Mirroring::HORIZONTAl,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xc000..=0xc003 => self.prg_bank_8k = data & 0x1f,
+            0xd000..=0xe003 => {
+                self.chr_banks[(((addr >> 12) - 0xd) * 4 + register) as usize] = data
+            }
+            0xf000 => self.irq.set_latch(data),
+            0xf001 => self.irq.write_control(data),
+            0xf002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        let index = bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE;
+        self.chr[index % self.chr.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cartridge::test::test_mapper_rom, mapper::test::banks};
+
+    fn new_vrc6(mapper: u8) -> Vrc6 {
+        Vrc6::new(test_mapper_rom(
+            mapper,
+            banks(0x2000, 16),
+            banks(CHR_BANK_SIZE, 64),
+        ))
+    }
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        let mut mapper = new_vrc6(24);
+        mapper.write_prg(0x8000, 2);
+        mapper.write_prg(0xc000, 7);
+        mapper.write_prg(0xe002, 42);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xa000), 5);
+        assert_eq!(mapper.read_prg(0xc000), 7);
+        assert_eq!(mapper.read_prg(0xe000), 15);
+        assert_eq!(mapper.read_chr(0x1800), 42);
+    }
+
+    #[test]
+    fn test_mapper_26_swaps_register_lines() {
+        let mut mapper = new_vrc6(26);
+        mapper.write_prg(0xb003, 0b1000_0100);
+        assert_eq!(mapper.mirroring(), Mirroring::HORIZONTAl);
+
+        mapper.write_prg(0xd001, 9);
+        assert_eq!(mapper.read_chr(0x0800), 9);
+    }
+
+    #[test]
+    fn test_prg_ram_enable() {
+        let mut mapper = new_vrc6(24);
+        mapper.write_prg(0x6000, 1);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+
+        mapper.write_prg(0xb003, 0b1000_0000);
+        mapper.write_prg(0x6000, 1);
+        assert_eq!(mapper.read_prg(0x6000), 1);
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut mapper = new_vrc6(24);
+        mapper.write_prg(0xf000, 0xfe);
+        mapper.write_prg(0xf001, 0b111);
+        mapper.cpu_tick();
+        assert!(!mapper.irq_pending());
+        mapper.cpu_tick();
+        assert!(mapper.irq_pending());
+
+        mapper.write_prg(0xf002, 0);
+        assert!(!mapper.irq_pending());
+        // enable after acknowledge was set, so the counter keeps running from the latch
+        mapper.cpu_tick();
+        mapper.cpu_tick();
+        assert!(mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_pulse_duty_cycle() {
+        let mut mapper = new_vrc6(24);
+        mapper.write_prg(0x9000, 0b0011_1111); // duty 3 (4/16), volume 15
+        mapper.write_prg(0x9001, 0);
+        mapper.write_prg(0x9002, 0b1000_0000); // period 0, every CPU cycle is a step
+
+        let mut high = 0;
+        for _ in 0..16 {
+            mapper.cpu_tick();
+            if mapper.audio_output() > 0.0 {
+                high += 1;
+            }
+        }
+        assert_eq!(high, 4);
+
+        mapper.write_prg(0x9000, 0b1000_1111); // ignore duty, constant volume
+        mapper.cpu_tick();
+        assert_eq!(mapper.audio_output(), 15.0 * AUDIO_SCALE);
+    }
+
+    #[test]
+    fn test_sawtooth() {
+        let mut mapper = new_vrc6(24);
+        mapper.write_prg(0xb000, 42);
+        mapper.write_prg(0xb001, 0);
+        mapper.write_prg(0xb002, 0b1000_0000);
+
+        let mut outputs = Vec::new();
+        for _ in 0..14 {
+            mapper.cpu_tick();
+            outputs.push(mapper.audio.sawtooth.output());
+        }
+        assert_eq!(
+            outputs,
+            vec![0, 5, 5, 10, 10, 15, 15, 21, 21, 26, 26, 31, 31, 0]
+        );
+    }
+
+    #[test]
+    fn test_halt_stops_audio() {
+        let mut mapper = new_vrc6(24);
+        mapper.write_prg(0xb000, 42);
+        mapper.write_prg(0xb002, 0b1000_0000);
+        mapper.write_prg(0x9003, 1);
+        for _ in 0..10 {
+            mapper.cpu_tick();
+        }
+        assert_eq!(mapper.audio.sawtooth.output(), 0);
+    }
+}
@@ -0,0 +1,222 @@
+//! BPS patches: the target is built from a list of actions that copy data from the source, from the patch or from
+//! the already written target. Unlike IPS it can move data around, which is common in translations.
+//! https://www.romhacking.net/documents/746/
+//!
+//! ```
+//! "BPS1"
+//! source size, target size, metadata size (variable length numbers), metadata
+//! action: (length - 1) << 2 | command (variable length number) and its data
+//!     0 SourceRead: copy from the source at the current target offset
+//!     1 TargetRead: copy the following bytes of the patch
+//!     2 SourceCopy: copy from the source at a relative offset (variable length number, bit 0 is the sign)
+//!     3 TargetCopy: copy from the target at a relative offset, the areas may overlap
+//! source CRC-32, target CRC-32, patch CRC-32
+//! ```
+
+use super::{read_number, verify_checksums, verify_target, verify_target_size};
+
+pub const MAGIC: &[u8] = b"BPS1";
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(MAGIC) {
+        return Err("Patch is not in BPS format".to_string());
+    }
+    let (checksums, end) = verify_checksums(source, patch, "BPS")?;
+    let patch = &patch[..end];
+
+    let mut pos = MAGIC.len();
+    let source_size = read_number(patch, &mut pos)?;
+    let target_size = read_number(patch, &mut pos)?;
+    let metadata_size = read_number(patch, &mut pos)?;
+    pos = pos
+        .checked_add(metadata_size)
+        .filter(|&pos| pos <= patch.len())
+        .ok_or("BPS patch is truncated")?;
+    if source.len() != source_size {
+        return Err(format!(
+            "BPS patch expects a file of {} bytes, got {} bytes",
+            source_size,
+            source.len()
+        ));
+    }
+    verify_target_size(target_size, "BPS")?;
+
+    let out_of_bounds = || "BPS patch reads outside of the data".to_string();
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while pos < patch.len() {
+        let action = read_number(patch, &mut pos)?;
+        let length = (action >> 2) + 1;
+        if length > target_size - target.len() {
+            return Err("BPS patch writes past the end of the target".to_string());
+        }
+
+        match action & 0b11 {
+            0 => {
+                let start = target.len();
+                let data = source
+                    .get(start..start + length)
+                    .ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(data);
+            }
+            1 => {
+                let data = patch.get(pos..pos + length).ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(data);
+                pos += length;
+            }
+            2 => {
+                source_offset = relative_offset(source_offset, read_number(patch, &mut pos)?)
+                    .ok_or_else(out_of_bounds)?;
+                let data = source
+                    .get(source_offset..)
+                    .and_then(|data| data.get(..length))
+                    .ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(data);
+                source_offset += length;
+            }
+            _ => {
+                target_offset = relative_offset(target_offset, read_number(patch, &mut pos)?)
+                    .ok_or_else(out_of_bounds)?;
+                if target_offset >= target.len() {
+                    return Err(out_of_bounds());
+                }
+                // byte by byte, the copy may read what it has just written to repeat a pattern
+                for _ in 0..length {
+                    target.push(target[target_offset]);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(format!(
+            "BPS patch produced {} bytes instead of {}",
+            target.len(),
+            target_size
+        ));
+    }
+    verify_target(&target, &checksums, "BPS")?;
+    Ok(target)
+}
+
+/// Bit 0 is the sign of the relative offset, the other bits are its magnitude.
+fn relative_offset(offset: usize, data: usize) -> Option<usize> {
+    if data & 1 != 0 {
+        offset.checked_sub(data >> 1)
+    } else {
+        offset.checked_add(data >> 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::patch::test::{encode_number, with_checksums};
+
+    enum Action<'a> {
+        SourceRead(usize),
+        TargetRead(&'a [u8]),
+        SourceCopy(usize, isize),
+        TargetCopy(usize, isize),
+    }
+
+    fn create_patch(source: &[u8], target: &[u8], actions: &[Action]) -> Vec<u8> {
+        let relative = |offset: isize| (offset.unsigned_abs() << 1) | (offset < 0) as usize;
+
+        let mut patch = MAGIC.to_vec();
+        patch.extend(encode_number(source.len()));
+        patch.extend(encode_number(target.len()));
+        patch.extend(encode_number(4));
+        patch.extend(b"meta");
+        for action in actions {
+            match *action {
+                Action::SourceRead(length) => patch.extend(encode_number((length - 1) << 2)),
+                Action::TargetRead(data) => {
+                    patch.extend(encode_number((data.len() - 1) << 2 | 1));
+                    patch.extend(data);
+                }
+                Action::SourceCopy(length, offset) => {
+                    patch.extend(encode_number((length - 1) << 2 | 2));
+                    patch.extend(encode_number(relative(offset)));
+                }
+                Action::TargetCopy(length, offset) => {
+                    patch.extend(encode_number((length - 1) << 2 | 3));
+                    patch.extend(encode_number(relative(offset)));
+                }
+            }
+        }
+        with_checksums(patch, source, target)
+    }
+
+    #[test]
+    fn test_apply() {
+        let source = [1, 2, 3, 4, 5, 6];
+        let target = [1, 2, 0xaa, 5, 6, 3, 4, 3, 4, 3, 4, 3];
+        let patch = create_patch(
+            &source,
+            &target,
+            &[
+                Action::SourceRead(2),
+                Action::TargetRead(&[0xaa]),
+                Action::SourceCopy(2, 4),
+                Action::SourceCopy(2, -4),
+                // starts two bytes before the end, so "3, 4" is repeated
+                Action::TargetCopy(5, 5),
+            ],
+        );
+
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn test_invalid_patches() {
+        let source = [1, 2, 3];
+        let target = [1, 2, 3, 3];
+        let patch = create_patch(
+            &source,
+            &target,
+            &[Action::SourceRead(3), Action::SourceCopy(1, 2)],
+        );
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+
+        assert!(apply(&[1, 2, 4], &patch)
+            .unwrap_err()
+            .contains("made for a different file"));
+
+        let outside = create_patch(&source, &target, &[Action::SourceCopy(4, 1)]);
+        assert_eq!(
+            apply(&source, &outside),
+            Err("BPS patch reads outside of the data".to_string())
+        );
+
+        let short = create_patch(&source, &target, &[Action::SourceRead(3)]);
+        assert!(apply(&source, &short).is_err());
+    }
+
+    #[test]
+    fn test_sizes_are_validated() {
+        let source = [1, 2, 3];
+        // the copy would fill all memory if it ran before checking the target size
+        let copy = create_patch(
+            &source,
+            &source,
+            &[
+                Action::SourceRead(1),
+                Action::TargetCopy(usize::MAX >> 3, -1),
+            ],
+        );
+        assert_eq!(
+            apply(&source, &copy),
+            Err("BPS patch writes past the end of the target".to_string())
+        );
+
+        let mut huge = MAGIC.to_vec();
+        huge.extend(encode_number(source.len()));
+        huge.extend(encode_number(usize::MAX >> 1));
+        huge.extend(encode_number(0));
+        let huge = with_checksums(huge, &source, &source);
+        assert!(apply(&source, &huge).unwrap_err().contains("is too large"));
+    }
+}
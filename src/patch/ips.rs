@@ -0,0 +1,127 @@
+//! IPS patches: a list of records that overwrite the data at an offset, either with literal data or a run of a
+//! single byte (RLE). Offsets are 24 bit, so only the first 16 MiB of a file can be patched.
+//! https://zerosoft.zophar.net/ips.php
+//!
+//! ```
+//! "PATCH"
+//! record: offset (3 bytes, big endian), size (2 bytes), data
+//! RLE record: offset, size 0, run length (2 bytes), value
+//! "EOF"
+//! ```
+
+pub const MAGIC: &[u8] = b"PATCH";
+const EOF: &[u8] = b"EOF";
+// "EOF" read as an offset, records can't start there
+const EOF_OFFSET: usize = 0x454f46;
+const MAX_RECORD_SIZE: usize = 0xffff;
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(MAGIC) {
+        return Err("Patch is not in IPS format".to_string());
+    }
+
+    let mut target = source.to_vec();
+    let mut pos = MAGIC.len();
+    let read = |pos: usize, len: usize| {
+        patch
+            .get(pos..pos + len)
+            .ok_or_else(|| "IPS patch is truncated".to_string())
+    };
+
+    loop {
+        let offset = read(pos, 3)?;
+        if offset == EOF {
+            break;
+        }
+        let offset = (offset[0] as usize) << 16 | (offset[1] as usize) << 8 | offset[2] as usize;
+        let size = read(pos + 3, 2)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+        pos += 5;
+
+        let data = if size == 0 {
+            let rle = read(pos, 3)?;
+            pos += 3;
+            vec![rle[2]; (rle[0] as usize) << 8 | rle[1] as usize]
+        } else {
+            let data = read(pos, size)?.to_vec();
+            pos += size;
+            data
+        };
+
+        if target.len() < offset + data.len() {
+            target.resize(offset + data.len(), 0);
+        }
+        target[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    Ok(target)
+}
+
+/// Creates a patch that turns `source` into `target`. Both have to be of the same size.
+pub fn create(source: &[u8], target: &[u8]) -> Vec<u8> {
+    assert_eq!(source.len(), target.len());
+
+    let mut patch = MAGIC.to_vec();
+    let mut pos = 0;
+    while pos < target.len() {
+        if source[pos] == target[pos] {
+            pos += 1;
+            continue;
+        }
+
+        // a record can't start at the offset that reads as "EOF"
+        let start = if pos == EOF_OFFSET { pos - 1 } else { pos };
+        let mut end = pos;
+        while end < target.len() && end - start < MAX_RECORD_SIZE && source[end] != target[end] {
+            end += 1;
+        }
+
+        patch.extend(&(start as u32).to_be_bytes()[1..]);
+        patch.extend(&((end - start) as u16).to_be_bytes());
+        patch.extend(&target[start..end]);
+        pos = end;
+    }
+    patch.extend(EOF);
+
+    patch
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let patch = [
+            b"PATCH".as_slice(),
+            &[0x00, 0x00, 0x01, 0x00, 0x02, 0xaa, 0xbb],
+            &[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0xcc],
+            b"EOF",
+        ]
+        .concat();
+
+        assert_eq!(
+            apply(&[0; 6], &patch),
+            Ok(vec![0x00, 0xaa, 0xbb, 0x00, 0x00, 0xcc, 0xcc, 0xcc])
+        );
+    }
+
+    #[test]
+    fn test_invalid_patches() {
+        assert!(apply(&[0; 4], b"NOPE").is_err());
+        assert!(apply(&[0; 4], b"PATCH\x00\x00\x01\x00\x05\x01EOF").is_err());
+    }
+
+    #[test]
+    fn test_create_round_trip() {
+        let source = vec![0; 0x500000];
+        let mut target = source.clone();
+        target[3] = 1;
+        target[4] = 2;
+        target[EOF_OFFSET] = 3;
+        target[0x480000..0x490100].fill(4);
+
+        let patch = create(&source, &target);
+        assert_eq!(apply(&source, &patch), Ok(target));
+    }
+}
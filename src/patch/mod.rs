@@ -0,0 +1,183 @@
+//! Patch formats used for soft patching, the data is never written back to the original file.
+
+use std::path::Path;
+
+use crate::cartridge::hash::crc32;
+
+pub mod bps;
+pub mod ips;
+pub mod ups;
+
+/// Extensions of the patch formats, in the order they are looked for next to a game.
+const EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// Way bigger than any NES game, so a broken patch can't make us allocate whatever size it claims.
+const MAX_TARGET_SIZE: usize = 64 * 1024 * 1024;
+
+/// Applies a patch of any supported format, the format is detected by the magic at the start of the patch.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(ips::MAGIC) {
+        ips::apply(source, patch)
+    } else if patch.starts_with(ups::MAGIC) {
+        ups::apply(source, patch)
+    } else if patch.starts_with(bps::MAGIC) {
+        bps::apply(source, patch)
+    } else {
+        Err("Patch is neither in IPS, UPS nor BPS format".to_string())
+    }
+}
+
+/// Looks for a patch with the same name as the game, e.g. `game.ips` for `game.nes`.
+pub fn find_patch(game: &str) -> Option<String> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| Path::new(game).with_extension(extension))
+        .find(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// UPS and BPS store numbers as a variable length encoding: 7 bits per byte, the highest bit marks the last byte.
+/// Every byte but the last one is also incremented, so each number has exactly one encoding.
+fn read_number(patch: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let mut number = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = *patch.get(*pos).ok_or("Patch is truncated")?;
+        *pos += 1;
+        number = shift
+            .checked_mul((byte & 0x7f) as usize)
+            .and_then(|value| number.checked_add(value))
+            .ok_or("Patch contains an invalid number")?;
+        if byte & 0x80 != 0 {
+            return Ok(number);
+        }
+        shift = shift
+            .checked_mul(0x80)
+            .ok_or("Patch contains an invalid number")?;
+        number = number
+            .checked_add(shift)
+            .ok_or("Patch contains an invalid number")?;
+    }
+}
+
+/// UPS and BPS store the size of the target up front, it's checked before anything is allocated.
+fn verify_target_size(target_size: usize, format: &str) -> Result<(), String> {
+    if target_size > MAX_TARGET_SIZE {
+        return Err(format!(
+            "{} patch target of {} bytes is too large",
+            format, target_size
+        ));
+    }
+    Ok(())
+}
+
+/// UPS and BPS end with the CRC-32 of the source, of the target and of the patch itself (little endian).
+struct Checksums {
+    source: u32,
+    target: u32,
+}
+
+/// Verifies the checksum of the patch and the source data, returns the checksums and the end of the patch data.
+fn verify_checksums(
+    source: &[u8],
+    patch: &[u8],
+    format: &str,
+) -> Result<(Checksums, usize), String> {
+    if patch.len() < 4 + 12 {
+        return Err(format!("{} patch is truncated", format));
+    }
+    let end = patch.len() - 12;
+    let read = |pos: usize| {
+        u32::from_le_bytes([patch[pos], patch[pos + 1], patch[pos + 2], patch[pos + 3]])
+    };
+    let checksums = Checksums {
+        source: read(end),
+        target: read(end + 4),
+    };
+
+    if crc32(&patch[..end + 8]) != read(end + 8) {
+        return Err(format!(
+            "{} patch is corrupted, its checksum doesn't match",
+            format
+        ));
+    }
+    if crc32(source) != checksums.source {
+        return Err(format!(
+            "{} patch was made for a different file, the checksum {:08X} doesn't match the expected {:08X}",
+            format,
+            crc32(source),
+            checksums.source
+        ));
+    }
+    Ok((checksums, end))
+}
+
+/// Compares the patched data against the checksum stored in the patch.
+fn verify_target(target: &[u8], checksums: &Checksums, format: &str) -> Result<(), String> {
+    if crc32(target) != checksums.target {
+        return Err(format!(
+            "Result of the {} patch has the checksum {:08X} instead of {:08X}",
+            format,
+            crc32(target),
+            checksums.target
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    pub fn encode_number(mut number: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (number & 0x7f) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(byte | 0x80);
+                return bytes;
+            }
+            bytes.push(byte);
+            number -= 1;
+        }
+    }
+
+    /// Appends the source, target and patch checksums.
+    pub fn with_checksums(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_number_encoding() {
+        for number in [0, 1, 0x7f, 0x80, 0x407f, 0x4080, 0x12345678] {
+            let encoded = encode_number(number);
+            let mut pos = 0;
+            assert_eq!(read_number(&encoded, &mut pos), Ok(number));
+            assert_eq!(pos, encoded.len());
+        }
+        assert!(read_number(&[0x00, 0x01], &mut 0).is_err());
+        assert_eq!(
+            read_number(&[0x7f; 12], &mut 0),
+            Err("Patch contains an invalid number".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_is_detected() {
+        let ips = [b"PATCH".as_slice(), &[0, 0, 1, 0, 1, 0xaa], b"EOF"].concat();
+        assert_eq!(apply(&[0; 2], &ips), Ok(vec![0, 0xaa]));
+
+        let ups = with_checksums(
+            [b"UPS1".as_slice(), &[0x82, 0x82, 0x81, 0xaa, 0x00]].concat(),
+            &[0; 2],
+            &[0, 0xaa],
+        );
+        assert_eq!(apply(&[0; 2], &ups), Ok(vec![0, 0xaa]));
+
+        assert!(apply(&[0; 2], b"NOPE").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! UPS patches: the target is the source XOR a list of hunks, which keeps patches small and lets them be applied
+//! in both directions. The CRC-32 checksums at the end make sure the patch fits the file.
+//! https://www.romhacking.net/documents/392/
+//!
+//! ```
+//! "UPS1"
+//! source size, target size (variable length numbers)
+//! hunk: bytes to skip (variable length number), XOR data terminated by 0x00
+//! source CRC-32, target CRC-32, patch CRC-32
+//! ```
+
+use super::{read_number, verify_checksums, verify_target, verify_target_size};
+
+pub const MAGIC: &[u8] = b"UPS1";
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(MAGIC) {
+        return Err("Patch is not in UPS format".to_string());
+    }
+    let (checksums, end) = verify_checksums(source, patch, "UPS")?;
+    let patch = &patch[..end];
+
+    let mut pos = MAGIC.len();
+    let source_size = read_number(patch, &mut pos)?;
+    let target_size = read_number(patch, &mut pos)?;
+    if source.len() != source_size {
+        return Err(format!(
+            "UPS patch expects a file of {} bytes, got {} bytes",
+            source_size,
+            source.len()
+        ));
+    }
+    verify_target_size(target_size, "UPS")?;
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+    let mut offset = 0usize;
+    while pos < patch.len() {
+        offset = offset
+            .checked_add(read_number(patch, &mut pos)?)
+            .filter(|&offset| offset <= target_size)
+            .ok_or("UPS patch writes past the end of the target")?;
+        loop {
+            let xor = *patch.get(pos).ok_or("UPS patch is truncated")?;
+            pos += 1;
+            if xor == 0 {
+                break;
+            }
+            if let Some(byte) = target.get_mut(offset) {
+                *byte ^= xor;
+            }
+            offset += 1;
+        }
+        // the terminating zero stands for an unchanged byte
+        offset += 1;
+    }
+
+    verify_target(&target, &checksums, "UPS")?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::patch::test::{encode_number, with_checksums};
+
+    fn create_patch(source: &[u8], target: &[u8], hunks: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        patch.extend(encode_number(source.len()));
+        patch.extend(encode_number(target.len()));
+        for (skip, data) in hunks {
+            patch.extend(encode_number(*skip));
+            patch.extend(*data);
+            patch.push(0);
+        }
+        with_checksums(patch, source, target)
+    }
+
+    #[test]
+    fn test_apply() {
+        let source = [1, 2, 3, 4, 5];
+        let target = [1, 0x12, 0x13, 4, 5, 6, 7];
+        // 0x12 ^ 2 = 0x10, 0x13 ^ 3 = 0x10; the appended bytes are XORed with 0
+        let patch = create_patch(&source, &target, &[(1, &[0x10, 0x10]), (1, &[6, 7])]);
+
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn test_checksums_are_validated() {
+        let source = [1, 2, 3];
+        let target = [1, 2, 4];
+        let patch = create_patch(&source, &target, &[(2, &[7])]);
+
+        assert!(apply(&[1, 2, 4], &patch)
+            .unwrap_err()
+            .contains("made for a different file"));
+
+        let mut corrupted = patch.clone();
+        corrupted[7] ^= 1;
+        assert!(apply(&source, &corrupted)
+            .unwrap_err()
+            .contains("patch is corrupted"));
+
+        // a consistent patch whose hunks don't produce the promised result
+        let wrong = create_patch(&source, &target, &[(2, &[1])]);
+        assert!(apply(&source, &wrong)
+            .unwrap_err()
+            .starts_with("Result of the UPS patch"));
+    }
+
+    #[test]
+    fn test_sizes_are_validated() {
+        let source = [1, 2, 3];
+        let mut huge = MAGIC.to_vec();
+        huge.extend(encode_number(source.len()));
+        huge.extend(encode_number(usize::MAX >> 1));
+        let huge = with_checksums(huge, &source, &source);
+        assert!(apply(&source, &huge).unwrap_err().contains("is too large"));
+
+        let past_the_end = create_patch(&source, &source, &[(usize::MAX, &[1])]);
+        assert_eq!(
+            apply(&source, &past_the_end),
+            Err("UPS patch writes past the end of the target".to_string())
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! The background half of the rendering pipeline. Every 8 dots the PPU fetches the nametable byte, the attribute
+//! byte and the two pattern bytes of a tile into latches, which are then loaded into the low half of 16 bit shift
+//! registers. Those shift one bit per dot and the pixel is picked from their high half by the fine X scroll.
+//! https://www.nesdev.org/wiki/PPU_rendering
+
+pub struct BackgroundShifter {
+    pub next_tile: u8,
+    /// Palette of the next tile (0 - 3), already picked out of the attribute byte.
+    pub next_palette: u8,
+    pub next_pattern_low: u8,
+    pub next_pattern_high: u8,
+    pattern_low: u16,
+    pattern_high: u16,
+    // the palette bits are expanded to 8 bits per tile so they can be shifted along with the pattern
+    palette_low: u16,
+    palette_high: u16,
+}
+
+impl BackgroundShifter {
+    pub fn new() -> Self {
+        BackgroundShifter {
+            next_tile: 0,
+            next_palette: 0,
+            next_pattern_low: 0,
+            next_pattern_high: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+            palette_low: 0,
+            palette_high: 0,
+        }
+    }
+
+    /// Moves the latches into the low half of the shift registers.
+    pub fn load(&mut self) {
+        let expand = |bit: u8| if bit != 0 { 0xff } else { 0x00 };
+
+        self.pattern_low = (self.pattern_low & 0xff00) | self.next_pattern_low as u16;
+        self.pattern_high = (self.pattern_high & 0xff00) | self.next_pattern_high as u16;
+        self.palette_low = (self.palette_low & 0xff00) | expand(self.next_palette & 0b01);
+        self.palette_high = (self.palette_high & 0xff00) | expand(self.next_palette & 0b10);
+    }
+
+    pub fn shift(&mut self) {
+        self.pattern_low <<= 1;
+        self.pattern_high <<= 1;
+        self.palette_low <<= 1;
+        self.palette_high <<= 1;
+    }
+
+    /// Palette (0 - 3) and color index (0 - 3) of the current pixel.
+    pub fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let bit = 15 - fine_x;
+        let value = (self.pattern_high >> bit & 1) << 1 | (self.pattern_low >> bit & 1);
+        let palette = (self.palette_high >> bit & 1) << 1 | (self.palette_low >> bit & 1);
+        (palette as u8, value as u8)
+    }
+}
+
+/// Palette of a tile out of its attribute byte. Every byte of the attribute table covers 4x4 tiles and picks one of
+/// the four background palettes for each 2x2 tile quarter of that area:
+/// ```
+/// 7654 3210
+/// |||| ||++- top left
+/// |||| ++--- top right
+/// ||++------ bottom left
+/// ++-------- bottom right
+/// ```
+/// `scroll` is the scroll position of the tile, see [`super::registers::loopy`].
+pub fn attribute_palette(attribute: u8, scroll: u16) -> u8 {
+    let coarse_x = scroll & 0x1f;
+    let coarse_y = scroll >> 5 & 0x1f;
+    let shift = (coarse_y & 0b10) << 1 | (coarse_x & 0b10);
+    attribute >> shift & 0b11
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::*;
+    use crate::{cartridge::Mirroring, render::palette::SYSTEM_PALETE};
+
+    #[test]
+    fn test_background_colors_from_attribute_table() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        // tile 1: the left half uses color 1, the right half color 3
+        write_ppu(&mut ppu, 0x0010, &[[0xff; 8], [0x0f; 8]].concat());
+        // the tile at (2, 0) is in the top right quarter, (0, 2) in the bottom left one
+        write_ppu(&mut ppu, 0x2002, &[1]);
+        write_ppu(&mut ppu, 0x2040, &[1]);
+        write_ppu(&mut ppu, 0x23c0, &[0b00_10_01_00]);
+        write_ppu(
+            &mut ppu,
+            0x3f00,
+            &[
+                0x0f, 0x01, 0x02, 0x03, 0x00, 0x11, 0x12, 0x13, 0x00, 0x21, 0x22, 0x23,
+            ],
+        );
+
+        render_frame(&mut ppu, 0, 0, 0);
+
+        // palette 1 for the top right quarter
+        assert_eq!(pixel(&ppu, 16, 0), SYSTEM_PALETE[0x11]);
+        assert_eq!(pixel(&ppu, 23, 7), SYSTEM_PALETE[0x13]);
+        // palette 2 for the bottom left quarter
+        assert_eq!(pixel(&ppu, 0, 16), SYSTEM_PALETE[0x21]);
+        assert_eq!(pixel(&ppu, 7, 16), SYSTEM_PALETE[0x23]);
+        // color 0 is the universal background color no matter the palette
+        assert_eq!(pixel(&ppu, 8, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 255, 239), SYSTEM_PALETE[0x0f]);
+    }
+
+    #[test]
+    fn test_base_nametable_is_rendered() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x2800, &[1]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f, 0x30]);
+
+        // horizontal mirroring, 0x2000 and 0x2400 show the first nametable, 0x2800 and 0x2C00 the second one
+        for (ctrl, color) in [(0b00, 0x0f), (0b01, 0x0f), (0b10, 0x30), (0b11, 0x30)] {
+            render_frame(&mut ppu, ctrl, 0, 0);
+            assert_eq!(pixel(&ppu, 0, 0), SYSTEM_PALETE[color]);
+        }
+    }
+
+    #[test]
+    fn test_horizontal_scroll_into_next_nametable() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::VERTICAL);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x2400, &[1]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f, 0x30]);
+
+        // 250 is 31 tiles and 2 pixels, the first tile of the right nametable starts 6 pixels into the screen
+        render_frame(&mut ppu, 0, 250, 0);
+        assert_eq!(pixel(&ppu, 5, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 6, 0), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 13, 7), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 14, 0), SYSTEM_PALETE[0x0f]);
+
+        // starting in the right nametable wraps around to the left one
+        render_frame(&mut ppu, 1, 0, 0);
+        assert_eq!(pixel(&ppu, 0, 0), SYSTEM_PALETE[0x30]);
+        render_frame(&mut ppu, 1, 8, 0);
+        assert_eq!(pixel(&ppu, 0, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 255, 0), SYSTEM_PALETE[0x0f]);
+    }
+
+    #[test]
+    fn test_vertical_scroll_into_next_nametable() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x2020, &[1]);
+        write_ppu(&mut ppu, 0x2800, &[1]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f, 0x30]);
+
+        render_frame(&mut ppu, 0, 0, 12);
+        // row 1 of the top nametable moved up by 12 lines, so only its last 4 lines are visible
+        assert_eq!(pixel(&ppu, 0, 0), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 0, 3), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 0, 4), SYSTEM_PALETE[0x0f]);
+        // the bottom nametable follows after the 30 rows of tiles
+        assert_eq!(pixel(&ppu, 0, 227), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 0, 228), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 0, 235), SYSTEM_PALETE[0x30]);
+    }
+
+    #[test]
+    fn test_scroll_split_mid_frame() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        // the second column of the nametable is filled with tile 1
+        for row in 0..30 {
+            write_ppu(&mut ppu, 0x2001 + row * 32, &[1]);
+        }
+        write_ppu(&mut ppu, 0x3f00, &[0x0f, 0x30]);
+        render_frame(&mut ppu, 0, 0, 0);
+
+        // change the scroll position during line 100, before the horizontal position is reloaded for the next line,
+        // the Y scroll only takes effect in the next frame
+        for _ in 0..100 * 11 {
+            ppu.tick(31);
+        }
+        ppu.tick(200);
+        ppu.write_to_scroll_register(8);
+        ppu.write_to_scroll_register(100);
+        run_frame(&mut ppu);
+
+        assert_eq!(pixel(&ppu, 0, 100), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 8, 100), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 0, 101), SYSTEM_PALETE[0x30]);
+        assert_eq!(pixel(&ppu, 0, 239), SYSTEM_PALETE[0x30]);
+    }
+}
@@ -10,15 +10,35 @@
 //! are mapped to 0x2000 - 0x2007 in the CPU memory map and then mirrored every 8 bytes from
 //! 0x2008 - 0x3FFF.
 
+mod background;
 pub mod registers;
+mod sprites;
 
-use crate::cartridge::Mirroring;
+use std::{cell::RefCell, rc::Rc};
 
+use crate::{
+    cartridge::{Cartridge, Region},
+    render::{
+        frame::Frame,
+        palette::{emphasis_factors, emphasize, SYSTEM_PALETE},
+    },
+};
+
+use self::background::BackgroundShifter;
 use self::registers::{
-    address::AddrRegister, control::ControlRegister, data::DataRegister, mask::MaskRegister,
-    oam_address::OamAddressRegister, oam_data::OamDataRegister, scroll::ScrollRegister,
+    control::ControlRegister,
+    data::DataRegister,
+    loopy::LoopyRegisters,
+    mask::{Color, MaskRegister},
+    oam_address::OamAddressRegister,
+    oam_data::OamDataRegister,
     status::StatusRegister,
 };
+use self::sprites::{LineSprite, SPRITES_PER_LINE};
+
+const DOTS_PER_SCANLINE: u16 = 341;
+const VISIBLE_SCANLINES: u16 = 240;
+const VISIBLE_DOTS: u16 = 256;
 
 /// Note: a few registers are marked with `pub` visiblity.
 /// This is because the emulator needs to intercept the program execution in order to properly draw the screen.
@@ -29,61 +49,308 @@ pub struct NesPPU {
     status: StatusRegister,       // register at 0x2002, read-only
     oam_addr: OamAddressRegister, // register at 0x2003, write-only
     oam_data: OamDataRegister,    // register at 0x2004, read and write
-    scroll: ScrollRegister,       // register at 0x2005, write-only => write called twice (16-bit)
-    addr: AddrRegister,           // register at 0x2006, write-only => write called twice (16-bit)
-    pub data: DataRegister,       // register at 0x2007, read and write
+    // 0x2005 (write-only) and 0x2006 (write-only) are both written twice and only update these internal registers
+    loopy: LoopyRegisters,
+    pub data: DataRegister, // register at 0x2007, read and write
+    cartridge: Rc<RefCell<Cartridge>>,
     scanline: u16,
-    cycles: usize,
+    dot: u16, // PPU cycle within the scanline
+    odd_frame: bool,
     nmi_interrupt: Option<u8>,
+    background: BackgroundShifter,
+    sprites: Vec<LineSprite>,        // the sprites on the current scanline
+    sprite_slots: Vec<(usize, u16)>, // the sprites found for the next scanline and their rows
+    sprite_limit: bool,
+    region: Region,
+    emphasis: [f32; 3], // the emphasis factors of the mask for the region, see `update_emphasis`
+    frame: Frame,
 }
 
 impl NesPPU {
-    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>) -> Self {
         NesPPU {
             ctrl: ControlRegister::new(),
             mask: MaskRegister::new(),
             status: StatusRegister::new(),
             oam_addr: OamAddressRegister::new(),
             oam_data: OamDataRegister::new(),
-            scroll: ScrollRegister::new(),
-            addr: AddrRegister::new(),
-            data: DataRegister::new(chr_rom, mirroring),
+            loopy: LoopyRegisters::new(),
+            data: DataRegister::new(Rc::clone(&cartridge)),
+            cartridge,
             scanline: 0,
-            cycles: 0,
+            dot: 0,
+            odd_frame: false,
             nmi_interrupt: None,
+            background: BackgroundShifter::new(),
+            sprites: Vec::new(),
+            sprite_slots: Vec::new(),
+            sprite_limit: true,
+            region: Region::Ntsc,
+            emphasis: [1.0; 3],
+            frame: Frame::new(),
         }
     }
 
+    /// Runs the PPU for the given number of cycles, returns whether a frame finished in that time.
     pub fn tick(&mut self, cycles: u8) -> bool {
-        self.cycles += cycles as usize;
-
-        // 341 PPU cycles are needed for 1 scanline to finish
-        if self.cycles >= 341 {
-            self.cycles -= 341;
-            self.scanline += 1;
-
-            // 241st scanline is not visible anymore ans is called vertical overscan
-            if self.scanline == 241 {
-                self.status.set_vblank_started();
-                self.status.remove_sprite_zero_hit();
-                if self.ctrl.generate_vblank_nmi() {
-                    self.nmi_interrupt = Some(1);
+        let mut frame_finished = false;
+        for _ in 0..cycles {
+            frame_finished |= self.step();
+        }
+
+        frame_finished
+    }
+
+    /// Turning the limit of 8 sprites per scanline off gets rid of the flickering games use to work around it.
+    /// The sprite overflow flag still behaves like on the real hardware.
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    /// The region decides the number of scanlines and when vblank starts.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.update_emphasis();
+    }
+
+    /// The picture of the last frame, the lines above the current scanline are already from the next one.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Emulates a single dot (PPU cycle), returns whether it was the last one of the frame.
+    fn step(&mut self) -> bool {
+        let rendering_line =
+            self.scanline < VISIBLE_SCANLINES || self.scanline == self.pre_render_scanline();
+        if rendering_line && self.mask.is_rendering_enabled() {
+            self.fetch();
+        }
+
+        if self.scanline < VISIBLE_SCANLINES && (1..=VISIBLE_DOTS).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        // 241st scanline is not visible anymore ans is called vertical overscan
+        if self.scanline == self.region.vblank_scanline() && self.dot == 1 {
+            self.status.set_vblank_started();
+            if self.ctrl.generate_vblank_nmi() {
+                self.nmi_interrupt = Some(1);
+            }
+        }
+
+        if self.scanline == self.pre_render_scanline() && self.dot == 1 {
+            self.nmi_interrupt = None;
+            self.status.remove_sprite_zero_hit();
+            self.status.remove_sprite_overflow();
+            self.status.clear_vblank_started();
+        }
+
+        self.next_dot()
+    }
+
+    fn next_dot(&mut self) -> bool {
+        self.dot += 1;
+        // with rendering enabled the pre-render line of every odd frame is one dot shorter
+        if self.scanline == self.pre_render_scanline()
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && self.mask.is_rendering_enabled()
+            && self.region.skips_odd_frame_dot()
+        {
+            self.dot += 1;
+        }
+        if self.dot < DOTS_PER_SCANLINE {
+            return false;
+        }
+
+        self.dot = 0;
+        self.scanline += 1;
+        self.cartridge
+            .borrow_mut()
+            .notify_scanline(self.scanline % self.region.scanlines_per_frame());
+
+        // per frame 262 scanlines are rendered, 312 on PAL and Dendy consoles
+        if self.scanline >= self.region.scanlines_per_frame() {
+            self.scanline = 0;
+            self.odd_frame = !self.odd_frame;
+            return true; // frame finished rendering
+        }
+
+        false
+    }
+
+    /// The last line of the frame, it does the same memory accesses as a visible one.
+    fn pre_render_scanline(&self) -> u16 {
+        self.region.scanlines_per_frame() - 1
+    }
+
+    /// The memory accesses and scroll updates of the visible and the pre-render scanlines.
+    /// https://www.nesdev.org/w/images/default/4/4f/Ppu.svg
+    fn fetch(&mut self) {
+        let dot = self.dot;
+
+        // dots 1 - 256 fetch the tiles of the current line (the first two were fetched on the previous line),
+        // dots 321 - 336 the first two tiles of the next line
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.background.shift();
+
+            match (dot - 1) % 8 {
+                0 => {
+                    self.background.load();
+                    self.background.next_tile =
+                        self.data.fetch_nametable(self.loopy.get_tile_addr());
+                }
+                2 => {
+                    let attribute = self.data.fetch_nametable(self.loopy.get_attribute_addr());
+                    self.background.next_palette =
+                        background::attribute_palette(attribute, self.loopy.get_scroll());
                 }
+                4 => self.background.next_pattern_low = self.fetch_background_pattern(0),
+                6 => self.background.next_pattern_high = self.fetch_background_pattern(8),
+                7 => self.loopy.increment_x(),
+                _ => {}
+            }
+        }
+
+        if dot == VISIBLE_DOTS {
+            self.loopy.increment_y();
+        }
+        if dot == VISIBLE_DOTS + 1 {
+            self.loopy.copy_horizontal();
+            self.evaluate_sprites();
+        }
+        if (VISIBLE_DOTS + 1..=320).contains(&dot) {
+            self.fetch_sprites(dot - (VISIBLE_DOTS + 1));
+        }
+        if self.scanline == self.pre_render_scanline() && (280..=304).contains(&dot) {
+            self.loopy.copy_vertical();
+        }
+    }
+
+    /// `plane` is 0 for the low and 8 for the high byte of the row.
+    fn fetch_background_pattern(&mut self, plane: u16) -> u8 {
+        let addr = self.ctrl.get_background_pattern_addr()
+            + self.background.next_tile as u16 * 16
+            + self.loopy.get_fine_y()
+            + plane;
+        self.data.fetch_chr(addr)
+    }
+
+    /// Finds the sprites of the next line, their pattern data is fetched afterwards (see [`Self::fetch_sprites`]).
+    fn evaluate_sprites(&mut self) {
+        self.sprites.clear();
+        self.sprite_slots.clear();
+        if self.scanline >= VISIBLE_SCANLINES {
+            return;
+        }
+
+        let height = self.ctrl.get_sprite_height() as u16;
+        let (found, overflow) =
+            sprites::evaluate(&self.oam_data, self.scanline, height, self.sprite_limit);
+        if overflow {
+            self.status.set_sprite_overflow();
+        }
+        self.sprite_slots = found;
+    }
+
+    /// Dots 257 - 320 fetch the patterns of 8 sprites, 8 dots per sprite. The fetches happen even if there are
+    /// fewer sprites on the line (with tile 0xFF), mappers like the MMC5 count them to tell the background and
+    /// sprite fetches apart. Sprites above the limit of 8 are all fetched in the last slot.
+    fn fetch_sprites(&mut self, cycle: u16) {
+        if cycle % 8 != 4 {
+            return;
+        }
 
-                return false;
+        let slot = (cycle / 8) as usize;
+        let slots = if slot == SPRITES_PER_LINE - 1 {
+            slot..self.sprite_slots.len().max(SPRITES_PER_LINE)
+        } else {
+            slot..slot + 1
+        };
+        let height = self.ctrl.get_sprite_height() as u16;
+        for slot in slots {
+            let (index, row) = self.sprite_slots.get(slot).copied().unzip();
+            let sprite = index.map_or([0xff; 4], |index| self.oam_data.get_sprite(index));
+            let addr = sprites::pattern_addr(
+                sprite,
+                row.unwrap_or(0),
+                height,
+                self.ctrl.get_sprite_pattern_addr(),
+            );
+            let pattern = (self.data.fetch_chr(addr), self.data.fetch_chr(addr + 8));
+            if let Some(index) = index {
+                self.sprites.push(LineSprite::new(index, sprite, pattern));
             }
+        }
+    }
 
-            // per frame 262 scanlines are rendered
-            if self.scanline >= 262 {
-                self.scanline = 0;
-                self.nmi_interrupt = None;
-                self.status.remove_sprite_zero_hit();
-                self.status.clear_vblank_started();
-                return true; // frame finished rendering
+    /// Sprite 0 hits can't happen at the last pixel of a line, nor in the leftmost 8 pixels while either layer is
+    /// hidden there. The hit ignores sprite priority, so a sprite behind the background counts as well.
+    fn is_sprite_zero_hit_possible(&self, x: usize) -> bool {
+        let left_column_shown =
+            self.mask.show_leftmost_8pxl_background() && self.mask.show_leftmost_8pxl_sprites();
+        x != VISIBLE_DOTS as usize - 1 && (x >= 8 || left_column_shown)
+    }
+
+    /// Combines the background and the sprite pixel at the current dot and writes the color into the frame.
+    fn render_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+
+        let left_column = x < 8;
+
+        let (background_palette, background) = if self.mask.show_background()
+            && (!left_column || self.mask.show_leftmost_8pxl_background())
+        {
+            self.background.pixel(self.loopy.get_fine_x())
+        } else {
+            (0, 0)
+        };
+        let sprite = if self.mask.show_sprites()
+            && (!left_column || self.mask.show_leftmost_8pxl_sprites())
+        {
+            sprites::pixel(&self.sprites, x)
+        } else {
+            None
+        };
+
+        if let Some(sprite) = &sprite {
+            if sprite.index == 0 && background != 0 && self.is_sprite_zero_hit_possible(x) {
+                self.status.set_sprite_zero_hit();
             }
         }
 
-        false
+        let palette_index = match sprite {
+            Some(sprite) if background == 0 || !sprite.behind_background => {
+                0x10 + sprite.palette * 4 + sprite.value
+            }
+            // color 0 of every palette is the universal background color at 0x3F00
+            _ if background == 0 => 0,
+            _ => background_palette * 4 + background,
+        };
+
+        let mut color = self.data.fetch_palette(palette_index) & 0x3f;
+        if self.mask.is_greyscale() {
+            // only keeps the brightness, the first column of the palette is grey
+            color &= 0x30;
+        }
+        let rgb = emphasize(SYSTEM_PALETE[color as usize], &self.emphasis);
+        self.frame.set_pixel(x, self.scanline as usize, rgb);
+    }
+
+    /// PAL and Dendy consoles have the bits for red and green emphasis the other way around.
+    fn update_emphasis(&mut self) {
+        let swapped = matches!(self.region, Region::Pal | Region::Dendy);
+        let emphasized: Vec<_> = self
+            .mask
+            .get_emphasized_color()
+            .into_iter()
+            .map(|color| match color {
+                Color::Red if swapped => Color::Green,
+                Color::Green if swapped => Color::Red,
+                color => color,
+            })
+            .collect();
+        self.emphasis = emphasis_factors(&emphasized);
     }
 
     pub fn take_nmi_interrupt(&mut self) -> Option<u8> {
@@ -93,6 +360,7 @@ impl NesPPU {
     pub fn write_to_ctrl_register(&mut self, bits: u8) {
         let before_nmi_status = self.ctrl.generate_vblank_nmi();
         self.ctrl.update(bits);
+        self.loopy.write_ctrl(bits);
         // trigger NMI if GENERATE_NMI bit in control register changes from 0 to 1 and the PPU is in VBLANK_STARTED state
         if !before_nmi_status && self.ctrl.generate_vblank_nmi() && self.status.is_in_vblank() {
             self.nmi_interrupt = Some(1);
@@ -101,13 +369,13 @@ impl NesPPU {
 
     pub fn write_to_mask_register(&mut self, bits: u8) {
         self.mask.update(bits);
+        self.update_emphasis();
     }
 
     pub fn read_status_register(&mut self) -> u8 {
         let snapshot = self.status.get();
         self.status.clear_vblank_started();
-        self.scroll.reset_latch();
-        self.addr.reset_latch();
+        self.loopy.reset_latch();
         snapshot
     }
 
@@ -127,35 +395,34 @@ impl NesPPU {
     }
 
     pub fn write_to_scroll_register(&mut self, value: u8) {
-        self.scroll.write(value);
+        self.loopy.write_scroll(value);
     }
 
     pub fn write_to_addr_register(&mut self, value: u8) {
-        self.addr.update(value);
+        self.loopy.write_addr(value);
     }
 
     pub fn read_data_register(&mut self) -> u8 {
-        let addr = self.addr.get();
+        let addr = self.loopy.get_addr();
         self.increment_vram_addr();
         self.data.read_data(addr)
     }
 
     pub fn write_to_data_register(&mut self, data: u8) {
-        let addr = self.addr.get();
+        let addr = self.loopy.get_addr();
         self.data.write_data(addr, data);
         self.increment_vram_addr();
     }
 
     fn increment_vram_addr(&mut self) {
-        self.addr
-            .increment(self.ctrl.get_vram_addr_increment_value());
+        self.loopy
+            .increment_addr(self.ctrl.get_vram_addr_increment_value());
     }
 
     /// This function is a bit of a cheat, it's usually part of the register at 0x4014 called OAM Direct Memory Access Register.
     /// The actual OAM Data Register doesn't seem to be used by most games properly and they rather use this way to write data into memory.
     /// Preferably I would like to extract this into its own file and struct but I need the [`OamDataRegister`] internal memory.
-    /// I'll keep it as TODO for now.
-    /// Currently only used for tests, should probably fix at some point.
+    /// The bus reads the CPU page written to 0x4014 and hands it over here.
     pub fn write_to_oam_dma_register(&mut self, data: &[u8; 256]) {
         for value in data.iter() {
             let addr = self.oam_addr.get();
@@ -168,6 +435,10 @@ impl NesPPU {
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::cartridge::{
+        test::{test_mapper_rom, test_rom},
+        Mirroring,
+    };
 
     impl DataRegister {
         pub fn set_vram_at_address(&mut self, addr: u16, data: u8) {
@@ -179,8 +450,68 @@ pub mod test {
         }
     }
 
+    fn new_ppu_with_mirroring(mirroring: Mirroring) -> NesPPU {
+        let mut rom = test_rom(None);
+        rom.screen_mirroring = mirroring;
+        NesPPU::new(Rc::new(RefCell::new(Cartridge::new(rom))))
+    }
+
     fn new_empty_rom() -> NesPPU {
-        NesPPU::new(vec![0; 2048], Mirroring::HORIZONTAl)
+        new_ppu_with_mirroring(Mirroring::HORIZONTAl)
+    }
+
+    /// A PPU with CHR RAM, so the tests can write their own tiles.
+    pub fn new_chr_ram_ppu(mirroring: Mirroring) -> NesPPU {
+        let mut rom = test_mapper_rom(0, vec![0; 0x4000], vec![]);
+        rom.screen_mirroring = mirroring;
+        NesPPU::new(Rc::new(RefCell::new(Cartridge::new(rom))))
+    }
+
+    pub fn write_ppu(ppu: &mut NesPPU, addr: u16, data: &[u8]) {
+        ppu.write_to_addr_register((addr >> 8) as u8);
+        ppu.write_to_addr_register(addr as u8);
+        for &value in data {
+            ppu.write_to_data_register(value);
+        }
+    }
+
+    /// Places sprites at the start of OAM, the rest is moved off screen.
+    pub fn write_oam(ppu: &mut NesPPU, sprites: &[[u8; 4]]) {
+        let mut oam = [0xff; 256];
+        for (index, sprite) in sprites.iter().enumerate() {
+            oam[index * 4..index * 4 + 4].copy_from_slice(sprite);
+        }
+        ppu.write_to_oam_addr_register(0);
+        ppu.write_to_oam_dma_register(&oam);
+    }
+
+    pub fn run_dots(ppu: &mut NesPPU, dots: usize) {
+        for _ in 0..dots {
+            ppu.tick(1);
+        }
+    }
+
+    /// Runs the PPU until the current frame is done.
+    pub fn run_frame(ppu: &mut NesPPU) {
+        while !ppu.tick(1) {}
+    }
+
+    /// Sets up the scroll position like a game does after writing to VRAM and renders a whole frame with it.
+    pub fn render_frame(ppu: &mut NesPPU, ctrl: u8, scroll_x: u8, scroll_y: u8) {
+        ppu.write_to_ctrl_register(ctrl);
+        ppu.read_status_register();
+        ppu.write_to_scroll_register(scroll_x);
+        ppu.write_to_scroll_register(scroll_y);
+        ppu.write_to_mask_register(0b0001_1110);
+        // the scroll position is only picked up at the end of a frame
+        run_frame(ppu);
+        run_frame(ppu);
+    }
+
+    pub fn pixel(ppu: &NesPPU, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * 256 + x) * 3;
+        let data = &ppu.frame().data;
+        (data[base], data[base + 1], data[base + 2])
     }
 
     #[test]
@@ -203,7 +534,7 @@ pub mod test {
         ppu.write_to_addr_register(0x05);
 
         ppu.read_data_register(); //load_into_buffer
-        assert_eq!(ppu.addr.get(), 0x2306);
+        assert_eq!(ppu.loopy.get_addr(), 0x2306);
         assert_eq!(ppu.read_data_register(), 0x66);
     }
 
@@ -273,7 +604,7 @@ pub mod test {
     //   [0x2800 a ] [0x2C00 b ]
     #[test]
     fn test_vram_vertical_mirror() {
-        let mut ppu = NesPPU::new(vec![0; 2048], Mirroring::VERTICAL);
+        let mut ppu = new_ppu_with_mirroring(Mirroring::VERTICAL);
 
         ppu.write_to_addr_register(0x20);
         ppu.write_to_addr_register(0x05);
@@ -298,6 +629,52 @@ pub mod test {
         assert_eq!(ppu.read_data_register(), 0x77); //read from B
     }
 
+    // Single screen: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 a ]
+    //   [0x2800 a ] [0x2C00 a ]
+    #[test]
+    fn test_vram_single_screen_mirror() {
+        let mut ppu = new_ppu_with_mirroring(Mirroring::SingleScreenUpper);
+
+        ppu.write_to_addr_register(0x2C);
+        ppu.write_to_addr_register(0x05);
+
+        ppu.write_to_data_register(0x66); //write to a
+
+        assert_eq!(ppu.data.get_vram_at_address(0x0405), 0x66);
+
+        ppu.write_to_addr_register(0x20);
+        ppu.write_to_addr_register(0x05);
+
+        ppu.read_data_register(); //load into buffer
+        assert_eq!(ppu.read_data_register(), 0x66); //read from A
+    }
+
+    // Four screen: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 B ]
+    //   [0x2800 C ] [0x2C00 D ]
+    #[test]
+    fn test_vram_four_screen() {
+        let mut ppu = new_ppu_with_mirroring(Mirroring::FOURSCREEN);
+
+        for (hi, value) in [(0x20, 0x11), (0x24, 0x22), (0x28, 0x33), (0x2C, 0x44)] {
+            ppu.write_to_addr_register(hi);
+            ppu.write_to_addr_register(0x05);
+            ppu.write_to_data_register(value);
+        }
+
+        // C and D live on the cartridge and must not overwrite A and B
+        assert_eq!(ppu.data.get_vram_at_address(0x0005), 0x11);
+        assert_eq!(ppu.data.get_vram_at_address(0x0405), 0x22);
+
+        for (hi, value) in [(0x20, 0x11), (0x24, 0x22), (0x28, 0x33), (0x2C, 0x44)] {
+            ppu.write_to_addr_register(hi);
+            ppu.write_to_addr_register(0x05);
+            ppu.read_data_register(); //load into buffer
+            assert_eq!(ppu.read_data_register(), value);
+        }
+    }
+
     #[test]
     fn test_read_status_resets_latch() {
         let mut ppu = new_empty_rom();
@@ -377,4 +754,180 @@ pub mod test {
         ppu.write_to_oam_addr_register(0x11);
         assert_eq!(ppu.read_oam_data_register(), 0x66);
     }
+
+    /// Tile 1 (filled with color 1) in the top left corner of the background and as a sprite on line 51.
+    fn new_mask_test_ppu() -> NesPPU {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x2000, &[1, 1]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f, 0x16]);
+        write_ppu(&mut ppu, 0x3f10, &[0x0f, 0x2a]);
+        write_oam(&mut ppu, &[[50, 1, 0, 0]]);
+        render_frame(&mut ppu, 0, 0, 0);
+        ppu
+    }
+
+    fn render_with_mask(ppu: &mut NesPPU, mask: u8) {
+        ppu.write_to_mask_register(mask);
+        run_frame(ppu);
+        run_frame(ppu);
+    }
+
+    #[test]
+    fn test_mask_left_column() {
+        let mut ppu = new_mask_test_ppu();
+        assert_eq!(pixel(&ppu, 0, 0), SYSTEM_PALETE[0x16]);
+        assert_eq!(pixel(&ppu, 0, 51), SYSTEM_PALETE[0x2a]);
+
+        render_with_mask(&mut ppu, 0b0001_1000);
+        assert_eq!(pixel(&ppu, 7, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 8, 0), SYSTEM_PALETE[0x16]);
+        assert_eq!(pixel(&ppu, 7, 51), SYSTEM_PALETE[0x0f]);
+
+        render_with_mask(&mut ppu, 0b0001_1100);
+        assert_eq!(pixel(&ppu, 7, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 7, 51), SYSTEM_PALETE[0x2a]);
+
+        render_with_mask(&mut ppu, 0b0001_1010);
+        assert_eq!(pixel(&ppu, 7, 0), SYSTEM_PALETE[0x16]);
+        assert_eq!(pixel(&ppu, 7, 51), SYSTEM_PALETE[0x0f]);
+    }
+
+    #[test]
+    fn test_mask_layers() {
+        let mut ppu = new_mask_test_ppu();
+        render_with_mask(&mut ppu, 0b0001_0110);
+        assert_eq!(pixel(&ppu, 8, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 0, 51), SYSTEM_PALETE[0x2a]);
+
+        render_with_mask(&mut ppu, 0b0000_1110);
+        assert_eq!(pixel(&ppu, 8, 0), SYSTEM_PALETE[0x16]);
+        assert_eq!(pixel(&ppu, 0, 51), SYSTEM_PALETE[0x0f]);
+
+        // with rendering off the whole screen shows the universal background color
+        render_with_mask(&mut ppu, 0);
+        assert_eq!(pixel(&ppu, 8, 0), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 0, 51), SYSTEM_PALETE[0x0f]);
+    }
+
+    #[test]
+    fn test_mask_greyscale() {
+        let mut ppu = new_mask_test_ppu();
+        render_with_mask(&mut ppu, 0b0001_1111);
+        assert_eq!(pixel(&ppu, 0, 0), SYSTEM_PALETE[0x10]);
+        assert_eq!(pixel(&ppu, 0, 51), SYSTEM_PALETE[0x20]);
+        assert_eq!(pixel(&ppu, 100, 100), SYSTEM_PALETE[0x00]);
+    }
+
+    #[test]
+    fn test_mask_emphasis() {
+        let mut ppu = new_mask_test_ppu();
+        let (red, green, blue) = SYSTEM_PALETE[0x16];
+        let darken = |value: u8| (value as f32 * 0.75) as u8;
+
+        render_with_mask(&mut ppu, 0b0011_1110);
+        assert_eq!(pixel(&ppu, 0, 0), (red, darken(green), darken(blue)));
+
+        render_with_mask(&mut ppu, 0b1111_1110);
+        assert_eq!(pixel(&ppu, 0, 0), (red, green, blue));
+
+        // the red bit emphasizes green on PAL
+        ppu.set_region(Region::Pal);
+        render_with_mask(&mut ppu, 0b0011_1110);
+        assert_eq!(pixel(&ppu, 0, 0), (darken(red), green, darken(blue)));
+        render_with_mask(&mut ppu, 0b1001_1110);
+        assert_eq!(pixel(&ppu, 0, 0), (darken(red), darken(green), blue));
+    }
+
+    #[test]
+    fn test_palette_mirroring() {
+        let mut ppu = new_empty_rom();
+        for (addr, mirror) in [
+            (0x3f10, 0x3f00),
+            (0x3f04, 0x3f14),
+            (0x3f1c, 0x3f0c),
+            (0x3f25, 0x3f05),
+        ] {
+            ppu.write_to_addr_register((addr >> 8) as u8);
+            ppu.write_to_addr_register(addr as u8);
+            ppu.write_to_data_register(addr as u8 & 0x3f);
+
+            ppu.write_to_addr_register((mirror >> 8) as u8);
+            ppu.write_to_addr_register(mirror as u8);
+            assert_eq!(ppu.read_data_register(), addr as u8 & 0x3f);
+        }
+
+        // the other sprite colors have their own entries
+        ppu.write_to_addr_register(0x3f);
+        ppu.write_to_addr_register(0x11);
+        ppu.write_to_data_register(0x30);
+        ppu.write_to_addr_register(0x3f);
+        ppu.write_to_addr_register(0x01);
+        assert_ne!(ppu.read_data_register(), 0x30);
+        ppu.write_to_addr_register(0xff);
+        ppu.write_to_addr_register(0xf1);
+        assert_eq!(ppu.read_data_register(), 0x30);
+    }
+
+    #[test]
+    fn test_palette_reads() {
+        let mut ppu = new_empty_rom();
+        ppu.write_to_ctrl_register(0);
+        // 0x2f05 is in the second nametable with horizontal mirroring
+        ppu.data.set_vram_at_address(0x0705, 0x66);
+        ppu.write_to_addr_register(0x3f);
+        ppu.write_to_addr_register(0x05);
+        ppu.write_to_data_register(0xff);
+
+        ppu.write_to_addr_register(0x3f);
+        ppu.write_to_addr_register(0x05);
+        // no dummy read needed, the value only has 6 bits
+        assert_eq!(ppu.read_data_register(), 0x3f);
+
+        // the buffer holds the nametable byte below the palette address
+        ppu.write_to_addr_register(0x00);
+        ppu.write_to_addr_register(0x00);
+        assert_eq!(ppu.read_data_register(), 0x66);
+    }
+
+    #[test]
+    fn test_nametable_mirror_above_0x3000() {
+        let mut ppu = new_ppu_with_mirroring(Mirroring::VERTICAL);
+        ppu.write_to_addr_register(0x34);
+        ppu.write_to_addr_register(0x05);
+        ppu.write_to_data_register(0x66);
+        assert_eq!(ppu.data.get_vram_at_address(0x0405), 0x66);
+
+        // the second nametable with vertical mirroring
+        ppu.data.set_vram_at_address(0x06fe, 0x77);
+        ppu.write_to_addr_register(0x3e);
+        ppu.write_to_addr_register(0xfe);
+        ppu.read_data_register(); //load_into_buffer
+        assert_eq!(ppu.read_data_register(), 0x77);
+    }
+
+    /// Runs a frame and returns the number of dots it took and the scanline vblank started on.
+    fn frame_timing(region: Region) -> (usize, u16) {
+        let mut ppu = new_empty_rom();
+        ppu.set_region(region);
+        let mut dots = 0;
+        let mut vblank_scanline = None;
+        loop {
+            dots += 1;
+            let finished = ppu.tick(1);
+            if vblank_scanline.is_none() && ppu.status.get() >> 7 == 1 {
+                vblank_scanline = Some(ppu.scanline);
+            }
+            if finished {
+                return (dots, vblank_scanline.unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_region_frame_timing() {
+        assert_eq!(frame_timing(Region::Ntsc), (341 * 262, 241));
+        assert_eq!(frame_timing(Region::Pal), (341 * 312, 241));
+        assert_eq!(frame_timing(Region::Dendy), (341 * 312, 291));
+    }
 }
@@ -66,4 +66,24 @@ impl ControlRegister {
             0x1000
         }
     }
+
+    /// Pattern table of 8x8 sprites, 8x16 sprites pick it per sprite.
+    pub fn get_sprite_pattern_addr(&self) -> u16 {
+        if !self
+            .status
+            .contains(ControlRegisterFlags::SPRITE_PATTERN_ADDR)
+        {
+            0
+        } else {
+            0x1000
+        }
+    }
+
+    pub fn get_sprite_height(&self) -> usize {
+        if !self.status.contains(ControlRegisterFlags::SPRITE_SIZE) {
+            8
+        } else {
+            16
+        }
+    }
 }
@@ -1,29 +1,41 @@
-use crate::cartridge::Mirroring;
+use std::{cell::RefCell, rc::Rc};
+
+use crate::cartridge::{Cartridge, NametableAddr};
 
 pub struct DataRegister {
     // I don't want this to be pub but I need it for tests right now, TODO
-    pub vram: [u8; 2048],    // internal memory, keeps palette tables
-    chr_rom: Vec<u8>,        // visuals of the game stored on the cartridge
-    palette_table: [u8; 32], // internal memory, keeps palette tables
-    mirroring: Mirroring,
+    pub vram: [u8; 2048],              // internal memory, keeps the name tables
+    cartridge: Rc<RefCell<Cartridge>>, // visuals of the game (CHR) and the mirroring are provided by the cartridge
+    palette_table: [u8; 32],           // internal memory, keeps palette tables
     internal_data_buf: u8,
 }
 
 impl DataRegister {
-    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>) -> Self {
         DataRegister {
             vram: [0; 2048],
-            chr_rom,
+            cartridge,
             palette_table: [0; 32],
-            mirroring,
             internal_data_buf: 0,
         }
     }
 
-    /// Returns a slice of the `chr_rom` from index `start` to `end` inclusively.
-    /// This function is mainly used a way for the emulator to read the actual ROM to draw the screen.
-    pub fn return_chr_rom_slice(&self, start: usize, end: usize) -> &[u8] {
-        &self.chr_rom[start..=end]
+    /// Fetches a single byte of the pattern tables for rendering.
+    /// The fetch goes through the mapper since some of them switch CHR banks depending on what the PPU fetches.
+    pub fn fetch_chr(&self, addr: u16) -> u8 {
+        self.cartridge.borrow_mut().fetch_chr(addr)
+    }
+
+    /// Reads a nametable byte (0x2000 - 0x3EFF) for rendering, without touching the read buffer.
+    pub fn fetch_nametable(&self, addr: u16) -> u8 {
+        let mut cartridge = self.cartridge.borrow_mut();
+        let mapped = cartridge.fetch_nametable(addr);
+        self.read_mapped_nametable(&mut cartridge, mapped)
+    }
+
+    /// Reads one of the 32 palette entries for rendering, `index` is the offset from 0x3F00.
+    pub fn fetch_palette(&self, index: u8) -> u8 {
+        self.palette_table[palette_index(index as u16)]
     }
 
     pub fn read_data(&mut self, addr: u16) -> u8 {
@@ -31,70 +43,65 @@ impl DataRegister {
             // pattern tables => chr rom access
             0..=0x1fff => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.chr_rom[addr as usize];
+                self.internal_data_buf = self.cartridge.borrow_mut().read_chr(addr);
                 result
             }
-            // name tables => vram tables
-            0x2000..=0x2fff => {
+            // name tables => vram tables, 0x3000 - 0x3EFF mirrors 0x2000 - 0x2EFF
+            0x2000..=0x3eff => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.vram[self.mirror_vram_addr(addr) as usize];
+                self.internal_data_buf = self.read_nametable(addr);
                 result
             }
-            0x3000..=0x3eff => panic!(
-                "addr space 0x3000..0x3eff is not expected to be used, requested = {} ",
-                addr
-            ),
-            // palette tables
-            0x3f00..=0x3fff => self.palette_table[(addr - 0x3f00) as usize],
+            // palette tables aren't buffered, but the nametable byte "underneath" them still ends up in the buffer
+            0x3f00..=0x3fff => {
+                self.internal_data_buf = self.read_nametable(addr - 0x1000);
+                self.palette_table[palette_index(addr)]
+            }
             _ => panic!("Unexpected read access to mirrored space {}", addr),
         }
     }
 
     pub fn write_data(&mut self, addr: u16, data: u8) {
         match addr {
-            // TODO: guide uses println!, maybe this is actually called occasionally?
-            0..=0x1fff => panic!("Attempt to write to chr rom space: {}", addr),
-            0x2000..=0x2fff => self.vram[self.mirror_vram_addr(addr) as usize] = data,
-            0x3000..=0x3eff => panic!(
-                "addr space 0x3000..0x3eff is not expected to be used, requested = {} ",
-                addr
-            ),
-            // I understand that these addresses mirror each other but I don't understand why it should be important for write specifically
-            // This reference here: https://bytes.vokal.io/7-nes-ppu/ mentions that mirroring but I'll just keep it like that for now
-            // Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
-            // 0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-            //     let add_mirror = addr - 0x10;
-            //     self.palette_table[(add_mirror - 0x3f00) as usize] = data;
-            // }
-            0x3f00..=0x3fff => self.palette_table[(addr - 0x3f00) as usize] = data,
+            // boards with CHR RAM are written this way, the mapper ignores writes to CHR ROM
+            0..=0x1fff => self.cartridge.borrow_mut().write_chr(addr, data),
+            0x2000..=0x3eff => self.write_nametable(addr, data),
+            // palette entries only have 6 bits
+            0x3f00..=0x3fff => self.palette_table[palette_index(addr)] = data & 0x3f,
             _ => panic!("Unexpected write access to mirrored space {}", addr),
         }
     }
 
-    /// NES uses 1 KiB of VRAM to represent the state of a single screen. Since it has 2 KiB it can store 2 screen states.
-    /// Range 0x2000-0x3f00 on the PPU memory map is reserved for nametables (screen states). Two additional screens have
-    /// to be mapped to the existing ones on the PPU memory map. The mapping depends on the mirroring type.
-    ///
-    /// Horizontal:
-    /// [0x2000-0x2400] and [0x2400-0x2800] should be mapped to the first 1 KiB of VRAM.
-    /// [0x2800-0x2C00] and [0x2C00-0x3F00] should be mapped to the second 1 KiB of VRAM.
-    ///  [ A ] [ a ]
-    ///  [ B ] [ b ]
-    ///
-    /// Vertical:
-    ///  [ A ] [ B ]
-    ///  [ a ] [ b ]
-    fn mirror_vram_addr(&self, addr: u16) -> u16 {
-        let mirrored_vram = addr & 0b0010_1111_1111_1111; // mirror down 0x3000-0x3eff to 0x2000 - 0x2eff
-        let vram_index = mirrored_vram - 0x2000; // to vram vector
-        let name_table = vram_index / 0x400; // to name table index
+    fn read_nametable(&self, addr: u16) -> u8 {
+        let mut cartridge = self.cartridge.borrow_mut();
+        let mapped = cartridge.map_nametable(addr);
+        self.read_mapped_nametable(&mut cartridge, mapped)
+    }
 
-        match (&self.mirroring, name_table) {
-            (Mirroring::VERTICAL, 2) | (Mirroring::VERTICAL, 3) => vram_index - 0x800,
-            (Mirroring::HORIZONTAl, 2) => vram_index - 0x400,
-            (Mirroring::HORIZONTAl, 1) => vram_index - 0x400,
-            (Mirroring::HORIZONTAl, 3) => vram_index - 0x800,
-            _ => vram_index,
+    fn read_mapped_nametable(&self, cartridge: &mut Cartridge, mapped: NametableAddr) -> u8 {
+        match mapped {
+            NametableAddr::Vram(index) => self.vram[index as usize],
+            NametableAddr::Cartridge(offset) => cartridge.read_nametable(offset),
         }
     }
+
+    fn write_nametable(&mut self, addr: u16, data: u8) {
+        let mut cartridge = self.cartridge.borrow_mut();
+        match cartridge.map_nametable(addr) {
+            NametableAddr::Vram(index) => self.vram[index as usize] = data,
+            NametableAddr::Cartridge(offset) => cartridge.write_nametable(offset, data),
+        }
+    }
+}
+
+/// Index into the palette table for an address in 0x3F00 - 0x3FFF, the 32 entries are mirrored all the way up.
+/// Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C: the first color of every sprite
+/// palette is transparent, so those entries are shared with the background palettes.
+fn palette_index(addr: u16) -> usize {
+    let index = addr & 0x1f;
+    if index & 0x13 == 0x10 {
+        (index & 0x0f) as usize
+    } else {
+        index as usize
+    }
 }
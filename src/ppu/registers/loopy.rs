@@ -0,0 +1,222 @@
+//! The internal registers of the PPU that 0x2000, 0x2005 and 0x2006 all write into, named after the person who
+//! first documented them. https://www.nesdev.org/wiki/PPU_scrolling
+//!
+//! `v` is the current VRAM address and `t` the temporary one, the address of the top left pixel on screen.
+//! While rendering both of them are used as a scroll position:
+//! ```
+//! yyy NN YYYYY XXXXX
+//! ||| || ||||| +++++- coarse X scroll
+//! ||| || +++++------- coarse Y scroll
+//! ||| ++------------- nametable select
+//! +++---------------- fine Y scroll
+//! ```
+//! `x` is the fine X scroll (3 bits) and `w` the write toggle shared between 0x2005 and 0x2006.
+
+// grouped by the fields of the scroll position instead of nibbles
+#![allow(clippy::unusual_byte_groupings)]
+
+const COARSE_X: u16 = 0b000_00_00000_11111;
+const COARSE_Y: u16 = 0b000_00_11111_00000;
+const NAMETABLE: u16 = 0b000_11_00000_00000;
+const NAMETABLE_X: u16 = 0b000_01_00000_00000;
+const NAMETABLE_Y: u16 = 0b000_10_00000_00000;
+const FINE_Y: u16 = 0b111_00_00000_00000;
+const HORIZONTAL: u16 = COARSE_X | NAMETABLE_X;
+const VERTICAL: u16 = COARSE_Y | NAMETABLE_Y | FINE_Y;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopyRegisters {
+    v: u16,
+    t: u16,
+    x: u8,
+    // since 0x2005 and 0x2006 expect two writes each this acts as the latch to write the correct value
+    // a latch is a set of D-Flip-Flops used to temporarily store a value
+    w: bool,
+}
+
+impl LoopyRegisters {
+    pub fn new() -> Self {
+        LoopyRegisters {
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+        }
+    }
+
+    /// 0x2000 write, the nametable bits end up in `t`.
+    pub fn write_ctrl(&mut self, data: u8) {
+        self.t = (self.t & !NAMETABLE) | ((data as u16 & 0b11) << 10);
+    }
+
+    /// 0x2005 write, first X and then Y scroll.
+    pub fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !COARSE_X) | (data as u16 >> 3);
+            self.x = data & 0b111;
+        } else {
+            self.t = (self.t & !(COARSE_Y | FINE_Y))
+                | ((data as u16 >> 3) << 5)
+                | ((data as u16 & 0b111) << 12);
+        }
+        self.w = !self.w;
+    }
+
+    /// 0x2006 write, first the high and then the low byte. The address only takes effect after the second write.
+    pub fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            // the high byte only has 6 bits, the highest bit of `t` is cleared
+            self.t = (self.t & 0x00ff) | ((data as u16 & 0x3f) << 8);
+        } else {
+            self.t = (self.t & 0xff00) | data as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    /// Reading 0x2002 resets the write toggle.
+    pub fn reset_latch(&mut self) {
+        self.w = false;
+    }
+
+    /// The address used by 0x2007, mirrored down to the 14 bit address space of the PPU.
+    pub fn get_addr(&self) -> u16 {
+        self.v & 0x3fff
+    }
+
+    /// Increment after a 0x2007 access.
+    pub fn increment_addr(&mut self, inc: u8) {
+        self.v = self.v.wrapping_add(inc as u16) & 0x7fff;
+    }
+
+    pub fn get_fine_x(&self) -> u8 {
+        self.x
+    }
+
+    /// The current scroll position: coarse X, coarse Y, nametable and fine Y.
+    pub fn get_scroll(&self) -> u16 {
+        self.v
+    }
+
+    /// Nametable byte of the tile at the current scroll position.
+    pub fn get_tile_addr(&self) -> u16 {
+        0x2000 | (self.v & 0x0fff)
+    }
+
+    /// Attribute byte of the tile at the current scroll position, the last 64 bytes of every nametable.
+    pub fn get_attribute_addr(&self) -> u16 {
+        0x23c0 | (self.v & NAMETABLE) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07)
+    }
+
+    /// Row within the tile at the current scroll position.
+    pub fn get_fine_y(&self) -> u16 {
+        (self.v & FINE_Y) >> 12
+    }
+
+    /// Moves `v` one tile to the right, wrapping into the horizontally adjacent nametable.
+    pub fn increment_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            self.v &= !COARSE_X;
+            self.v ^= NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// Moves `v` one line down, wrapping into the vertically adjacent nametable after the 30 rows of tiles.
+    /// Coarse Y set to 30 or 31 by a write points into the attribute table and wraps without switching nametables.
+    pub fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 1 << 12;
+            return;
+        }
+
+        self.v &= !FINE_Y;
+        let coarse_y = match (self.v & COARSE_Y) >> 5 {
+            29 => {
+                self.v ^= NAMETABLE_Y;
+                0
+            }
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    /// At the end of every line the horizontal position is reset to the one in `t`.
+    pub fn copy_horizontal(&mut self) {
+        self.v = (self.v & !HORIZONTAL) | (self.t & HORIZONTAL);
+    }
+
+    /// Before the first line the vertical position is reset to the one in `t`.
+    pub fn copy_vertical(&mut self) {
+        self.v = (self.v & !VERTICAL) | (self.t & VERTICAL);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scroll_writes() {
+        // the example from the nesdev wiki
+        let mut loopy = LoopyRegisters::new();
+        loopy.write_ctrl(0b00);
+        loopy.write_scroll(0b0111_1101);
+        assert_eq!(
+            (loopy.t, loopy.x, loopy.w),
+            (0b000_00_00000_01111, 0b101, true)
+        );
+        loopy.write_scroll(0b0101_1110);
+        assert_eq!(loopy.t, 0b110_00_01011_01111);
+        assert!(!loopy.w);
+
+        loopy.write_addr(0b0011_1101);
+        assert_eq!(loopy.t, 0b011_11_01011_01111);
+        loopy.write_addr(0b1111_0000);
+        assert_eq!(loopy.t, 0b011_11_01111_10000);
+        assert_eq!(loopy.v, loopy.t);
+        assert_eq!(loopy.x, 0b101);
+    }
+
+    #[test]
+    fn test_increments_wrap_into_next_nametable() {
+        let mut loopy = LoopyRegisters::new();
+        loopy.v = 31;
+        loopy.increment_x();
+        assert_eq!(loopy.v, NAMETABLE_X);
+
+        loopy.v = FINE_Y | (29 << 5);
+        loopy.increment_y();
+        assert_eq!(loopy.v, NAMETABLE_Y);
+
+        // the attribute table rows wrap to the top of the same nametable
+        loopy.v = FINE_Y | (31 << 5);
+        loopy.increment_y();
+        assert_eq!(loopy.v, 0);
+    }
+
+    #[test]
+    fn test_fetch_addresses() {
+        let mut loopy = LoopyRegisters::new();
+        // bottom right tile of the bottom right nametable
+        loopy.v = 0b101_11_11101_11111;
+        assert_eq!(loopy.get_tile_addr(), 0x2fbf);
+        assert_eq!(loopy.get_attribute_addr(), 0x2fff);
+        assert_eq!(loopy.get_fine_y(), 0b101);
+    }
+
+    #[test]
+    fn test_copy_position_from_t() {
+        let mut loopy = LoopyRegisters::new();
+        loopy.write_ctrl(0b11);
+        loopy.write_scroll(0xff);
+        loopy.write_scroll(0xff);
+
+        loopy.copy_horizontal();
+        assert_eq!(loopy.v, HORIZONTAL);
+        loopy.copy_vertical();
+        assert_eq!(loopy.v, 0x7fff);
+    }
+}
@@ -25,11 +25,12 @@ bitflags! {
     }
 }
 
-// pub enum Color {
-//     Red,
-//     Green,
-//     Blue,
-// }
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Color {
+    Red,
+    Green,
+    Blue,
+}
 
 pub struct MaskRegister {
     status: MaskRegisterFlags,
@@ -42,43 +43,49 @@ impl MaskRegister {
         }
     }
 
-    // pub fn is_greyscale(&self) -> bool {
-    //     self.status.contains(MaskRegisterFlags::GREYSCALE)
-    // }
+    pub fn is_greyscale(&self) -> bool {
+        self.status.contains(MaskRegisterFlags::GREYSCALE)
+    }
+
+    pub fn show_leftmost_8pxl_background(&self) -> bool {
+        self.status.contains(MaskRegisterFlags::LEFTMOST_BACKGROUND)
+    }
 
-    // pub fn show_leftmost_8pxl_background(&self) -> bool {
-    //     self.status.contains(MaskRegisterFlags::LEFTMOST_BACKGROUND)
-    // }
+    pub fn show_leftmost_8pxl_sprites(&self) -> bool {
+        self.status.contains(MaskRegisterFlags::LEFTMOST_SPRITE)
+    }
 
-    // pub fn show_leftmost_8pxl_sprites(&self) -> bool {
-    //     self.status.contains(MaskRegisterFlags::LEFTMOST_SPRITE)
-    // }
+    pub fn show_background(&self) -> bool {
+        self.status.contains(MaskRegisterFlags::SHOW_BACKGROUND)
+    }
 
-    // pub fn show_background(&self) -> bool {
-    //     self.status.contains(MaskRegisterFlags::SHOW_BACKGROUND)
-    // }
+    pub fn show_sprites(&self) -> bool {
+        self.status.contains(MaskRegisterFlags::SHOW_SPRITES)
+    }
 
-    // pub fn show_sprites(&self) -> bool {
-    //     self.status.contains(MaskRegisterFlags::SHOW_SPRITES)
-    // }
+    /// The PPU only accesses memory and updates its scroll position while either layer is shown.
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.show_background() || self.show_sprites()
+    }
 
-    // pub fn get_emphasized_color(&self) -> Vec<Color> {
-    //     let mut result: Vec<Color> = vec![];
+    /// The colors as they are named for NTSC, PAL and Dendy swap red and green.
+    pub fn get_emphasized_color(&self) -> Vec<Color> {
+        let mut result: Vec<Color> = vec![];
 
-    //     if self.status.contains(MaskRegisterFlags::EMPHASIZE_RED) {
-    //         result.push(Color::Red);
-    //     }
+        if self.status.contains(MaskRegisterFlags::EMPHASIZE_RED) {
+            result.push(Color::Red);
+        }
 
-    //     if self.status.contains(MaskRegisterFlags::EMPHASIZE_GREEN) {
-    //         result.push(Color::Green);
-    //     }
+        if self.status.contains(MaskRegisterFlags::EMPHASIZE_GREEN) {
+            result.push(Color::Green);
+        }
 
-    //     if self.status.contains(MaskRegisterFlags::EMPHASIZE_BLUE) {
-    //         result.push(Color::Blue);
-    //     }
+        if self.status.contains(MaskRegisterFlags::EMPHASIZE_BLUE) {
+            result.push(Color::Blue);
+        }
 
-    //     result
-    // }
+        result
+    }
 
     pub fn update(&mut self, data: u8) {
         self.status = MaskRegisterFlags::from_bits_truncate(data);
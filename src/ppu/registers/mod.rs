@@ -1,8 +1,7 @@
-pub mod address;
 pub mod control;
 pub mod data;
+pub mod loopy;
 pub mod mask;
 pub mod oam_address;
 pub mod oam_data;
-pub mod scroll;
 pub mod status;
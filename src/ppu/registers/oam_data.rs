@@ -1,3 +1,16 @@
+/// Every sprite takes up 4 bytes of OAM:
+/// ```
+/// 0: Y position of the top of the sprite minus 1
+/// 1: tile index, for 8x16 sprites bit 0 selects the pattern table and the rest the top tile
+/// 2: attributes
+///    76543210
+///    ||||||++- palette (4 to 7)
+///    |||+++--- unused
+///    ||+------ priority (0: in front of background; 1: behind background)
+///    |+------- flip sprite horizontally
+///    +-------- flip sprite vertically
+/// 3: X position of the left side of the sprite
+/// ```
 pub struct OamDataRegister {
     oam_data: [u8; 256], // called Object Attribute Memory, keeps sprite state
 }
@@ -11,6 +24,17 @@ impl OamDataRegister {
         self.oam_data[addr as usize]
     }
 
+    /// The 4 bytes of the sprite with the given index (0 - 63).
+    pub fn get_sprite(&self, index: usize) -> [u8; 4] {
+        let start = index * 4;
+        [
+            self.oam_data[start],
+            self.oam_data[start + 1],
+            self.oam_data[start + 2],
+            self.oam_data[start + 3],
+        ]
+    }
+
     pub fn write_data(&mut self, addr: u8, data: u8) {
         self.oam_data[addr as usize] = data;
     }
@@ -51,6 +51,18 @@ impl StatusRegister {
         self.status.remove(StatusRegisterFlags::VBLANK_STARTED);
     }
 
+    pub fn set_sprite_overflow(&mut self) {
+        self.status.insert(StatusRegisterFlags::SPRITE_OVERFLOW);
+    }
+
+    pub fn remove_sprite_overflow(&mut self) {
+        self.status.remove(StatusRegisterFlags::SPRITE_OVERFLOW);
+    }
+
+    pub fn set_sprite_zero_hit(&mut self) {
+        self.status.insert(StatusRegisterFlags::SPRITE_0_HIT);
+    }
+
     pub fn remove_sprite_zero_hit(&mut self) {
         self.status.remove(StatusRegisterFlags::SPRITE_0_HIT);
     }
@@ -0,0 +1,412 @@
+//! Sprites of a single scanline. At the end of every visible line the PPU looks through OAM for the first 8 sprites
+//! that are on the next line and fetches their pattern data, those are the only sprites drawn on that line.
+//! Games flicker their sprites to work around that limit, which emulators can lift (see [`evaluate`]).
+//! https://www.nesdev.org/wiki/PPU_sprite_evaluation
+
+use super::registers::oam_data::OamDataRegister;
+
+pub const SPRITE_COUNT: usize = 64;
+pub const SPRITES_PER_LINE: usize = 8;
+
+const PRIORITY: u8 = 0b0010_0000;
+const FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const FLIP_VERTICAL: u8 = 0b1000_0000;
+
+/// A sprite on the current scanline, with the row of its pattern that is on the line already flipped.
+pub struct LineSprite {
+    pub index: usize,
+    x: u8,
+    attributes: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+}
+
+/// The visible pixel of the sprite with the lowest index at some position.
+pub struct SpritePixel {
+    pub index: usize,
+    /// Color index (1 - 3), transparent pixels are never returned.
+    pub value: u8,
+    /// Sprite palette (0 - 3).
+    pub palette: u8,
+    pub behind_background: bool,
+}
+
+/// Indices of the sprites on the line after `scanline` together with the row of the sprite that is on it, and
+/// whether the sprite overflow flag gets set. Only the first [`SPRITES_PER_LINE`] sprites are returned unless
+/// `sprite_limit` is turned off, the flag behaves the same either way.
+pub fn evaluate(
+    oam: &OamDataRegister,
+    scanline: u16,
+    height: u16,
+    sprite_limit: bool,
+) -> (Vec<(usize, u16)>, bool) {
+    // OAM holds the line before the top of the sprite, which is why this matches the next line
+    let in_range = |y: u8| {
+        let row = scanline.wrapping_sub(y as u16);
+        (row < height).then_some(row)
+    };
+
+    let mut found = Vec::with_capacity(SPRITES_PER_LINE);
+    let mut index = 0;
+    while index < SPRITE_COUNT && found.len() < SPRITES_PER_LINE {
+        if let Some(row) = in_range(oam.get_sprite(index)[0]) {
+            found.push((index, row));
+        }
+        index += 1;
+    }
+
+    // once 8 sprites are found the PPU keeps looking for another one to set the overflow flag, but due to a hardware
+    // bug it also moves to the next byte of a sprite every time it moves to the next sprite. So it treats tile
+    // indices, attributes and X positions as Y positions, which leads to false positives as well as false negatives.
+    let mut overflow = false;
+    let mut byte = 0;
+    for overflow_index in index..SPRITE_COUNT {
+        if in_range(oam.read_data((overflow_index * 4 + byte) as u8)).is_some() {
+            overflow = true;
+            break;
+        }
+        byte = (byte + 1) % 4;
+    }
+
+    if !sprite_limit {
+        found.extend(
+            (index..SPRITE_COUNT)
+                .filter_map(|index| in_range(oam.get_sprite(index)[0]).map(|row| (index, row))),
+        );
+    }
+
+    (found, overflow)
+}
+
+impl LineSprite {
+    /// `pattern` is the low and high byte of the row of the sprite that is on the line, see [`pattern_addr`].
+    pub fn new(index: usize, sprite: [u8; 4], pattern: (u8, u8)) -> Self {
+        let [_, _, attributes, x] = sprite;
+        let (mut pattern_low, mut pattern_high) = pattern;
+        if attributes & FLIP_HORIZONTAL != 0 {
+            pattern_low = pattern_low.reverse_bits();
+            pattern_high = pattern_high.reverse_bits();
+        }
+
+        LineSprite {
+            index,
+            x,
+            attributes,
+            pattern_low,
+            pattern_high,
+        }
+    }
+
+    fn pixel(&self, x: usize) -> Option<SpritePixel> {
+        let column = x
+            .checked_sub(self.x as usize)
+            .filter(|&column| column < 8)?;
+        let bit = 7 - column;
+        let value = (self.pattern_high >> bit & 1) << 1 | (self.pattern_low >> bit & 1);
+        if value == 0 {
+            return None;
+        }
+
+        Some(SpritePixel {
+            index: self.index,
+            value,
+            palette: self.attributes & 0b11,
+            behind_background: self.attributes & PRIORITY != 0,
+        })
+    }
+}
+
+/// Address of the pattern row of a sprite for the given row within the sprite, for 8x16 sprites bit 0 of the tile
+/// selects the pattern table and the tile below the top one is used for rows 8 - 15.
+pub fn pattern_addr(sprite: [u8; 4], row: u16, height: u16, pattern_table: u16) -> u16 {
+    let [_, tile, attributes, _] = sprite;
+    let row = if attributes & FLIP_VERTICAL != 0 {
+        height - 1 - row
+    } else {
+        row
+    };
+
+    if height == 16 {
+        let bank = (tile as u16 & 1) * 0x1000;
+        let tile = (tile as u16 & 0xfe) + row / 8;
+        bank + tile * 16 + row % 8
+    } else {
+        pattern_table + tile as u16 * 16 + row
+    }
+}
+
+/// The first opaque sprite pixel at `x`. Where sprites overlap the one with the lower index wins, even if it is
+/// behind the background and the other one isn't, which games use to mask sprites with the background.
+pub fn pixel(sprites: &[LineSprite], x: usize) -> Option<SpritePixel> {
+    sprites.iter().find_map(|sprite| sprite.pixel(x))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{test::*, NesPPU};
+    use super::{evaluate, OamDataRegister};
+    use crate::{cartridge::Mirroring, render::palette::SYSTEM_PALETE};
+
+    #[test]
+    fn test_sprite_flipping_and_palette() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        // tile 2 only has its top left pixel set, to color 3
+        write_ppu(&mut ppu, 0x0020, &[0x80, 0, 0, 0, 0, 0, 0, 0, 0x80]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f]);
+        write_ppu(&mut ppu, 0x3f14, &[0x00, 0x11, 0x12, 0x13]);
+        write_oam(
+            &mut ppu,
+            &[
+                [19, 2, 0b0000_0001, 10],
+                [39, 2, 0b0100_0001, 10],
+                [59, 2, 0b1100_0001, 10],
+            ],
+        );
+
+        render_frame(&mut ppu, 0, 0, 0);
+        assert_eq!(pixel(&ppu, 10, 20), SYSTEM_PALETE[0x13]);
+        assert_eq!(pixel(&ppu, 11, 20), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 17, 40), SYSTEM_PALETE[0x13]);
+        assert_eq!(pixel(&ppu, 10, 40), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 17, 67), SYSTEM_PALETE[0x13]);
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        // tile 1 is filled with color 1
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        // the left half of the background is opaque
+        write_ppu(&mut ppu, 0x2000, &[1; 16]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f, 0x30]);
+        write_ppu(&mut ppu, 0x3f10, &[0x0f, 0x11, 0x00, 0x00, 0x00, 0x21]);
+        write_oam(
+            &mut ppu,
+            &[
+                // behind the background, but still hides sprite 1 as it has the lower index
+                [0, 1, 0b0010_0000, 0],
+                [0, 1, 0b0000_0001, 4],
+                // shows up where the background is transparent
+                [0, 1, 0b0010_0000, 200],
+                // in front of the background
+                [50, 1, 0b0000_0001, 0],
+            ],
+        );
+
+        render_frame(&mut ppu, 0, 0, 0);
+        assert_eq!(pixel(&ppu, 4, 1), SYSTEM_PALETE[0x30]);
+        // the part of sprite 1 that doesn't overlap sprite 0
+        assert_eq!(pixel(&ppu, 8, 1), SYSTEM_PALETE[0x21]);
+        assert_eq!(pixel(&ppu, 200, 1), SYSTEM_PALETE[0x11]);
+        assert_eq!(pixel(&ppu, 0, 51), SYSTEM_PALETE[0x21]);
+    }
+
+    #[test]
+    fn test_8x16_sprites() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        // tile 2 and 3 of the right pattern table, the top one filled with color 1, the bottom one with color 2
+        write_ppu(&mut ppu, 0x1020, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x1038, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f]);
+        write_ppu(&mut ppu, 0x3f10, &[0x0f, 0x11, 0x12]);
+        write_oam(&mut ppu, &[[9, 0x03, 0, 0], [9, 0x03, 0b1000_0000, 8]]);
+
+        render_frame(&mut ppu, 0b0010_0000, 0, 0);
+        assert_eq!(pixel(&ppu, 0, 10), SYSTEM_PALETE[0x11]);
+        assert_eq!(pixel(&ppu, 0, 25), SYSTEM_PALETE[0x12]);
+        assert_eq!(pixel(&ppu, 0, 26), SYSTEM_PALETE[0x0f]);
+        // flipped vertically across both tiles
+        assert_eq!(pixel(&ppu, 8, 10), SYSTEM_PALETE[0x12]);
+        assert_eq!(pixel(&ppu, 8, 25), SYSTEM_PALETE[0x11]);
+    }
+
+    #[test]
+    fn test_only_8_sprites_per_line() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f]);
+        write_ppu(&mut ppu, 0x3f10, &[0x0f, 0x11]);
+        // 9 sprites next to each other, the last one sits a line lower than the others
+        let sprites: Vec<[u8; 4]> = (0..9).map(|n| [n / 8 * 4, 1, 0, n * 8]).collect();
+        write_oam(&mut ppu, &sprites);
+
+        render_frame(&mut ppu, 0, 0, 0);
+        assert_eq!(pixel(&ppu, 56, 1), SYSTEM_PALETE[0x11]);
+        // the ninth sprite only shows up on the lines the others don't cover
+        assert_eq!(pixel(&ppu, 64, 8), SYSTEM_PALETE[0x0f]);
+        assert_eq!(pixel(&ppu, 64, 9), SYSTEM_PALETE[0x11]);
+        assert_eq!(pixel(&ppu, 64, 12), SYSTEM_PALETE[0x11]);
+    }
+
+    fn sprite_zero_hit(ppu: &NesPPU) -> bool {
+        ppu.status.get() & 0b0100_0000 != 0
+    }
+
+    /// Background made of tile 1 (completely opaque) and tile 2 (only the top left pixel is opaque),
+    /// with sprite 0 using tile 2 as well.
+    fn new_sprite_zero_ppu(sprite: [u8; 4]) -> NesPPU {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x0020, &[0x80]);
+        write_ppu(&mut ppu, 0x2000, &[1; 0x3c0]);
+        write_oam(&mut ppu, &[sprite]);
+        ppu
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_at_exact_dot() {
+        let mut ppu = new_sprite_zero_ppu([49, 2, 0, 20]);
+        render_frame(&mut ppu, 0, 0, 0);
+        assert!(!sprite_zero_hit(&ppu));
+
+        // the pixel at x = 20 of line 50 is rendered at dot 21
+        run_dots(&mut ppu, 50 * 341 + 21);
+        assert!(!sprite_zero_hit(&ppu));
+        run_dots(&mut ppu, 1);
+        assert!(sprite_zero_hit(&ppu));
+
+        // stays set until the pre-render line
+        run_dots(&mut ppu, (261 - 50) * 341 - 21);
+        assert!(sprite_zero_hit(&ppu));
+        run_dots(&mut ppu, 2);
+        assert!(!sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_needs_opaque_pixels() {
+        // behind the background still counts
+        let mut ppu = new_sprite_zero_ppu([49, 2, 0b0010_0000, 20]);
+        render_frame(&mut ppu, 0, 0, 0);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(sprite_zero_hit(&ppu));
+
+        // only the top left background pixel of tile 2 is opaque, and the sprite pixel is one line below it
+        let mut ppu = new_sprite_zero_ppu([0, 2, 0, 0]);
+        write_ppu(&mut ppu, 0x2000, &[2]);
+        render_frame(&mut ppu, 0, 0, 0);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(!sprite_zero_hit(&ppu));
+
+        // sprite 1 doesn't count
+        let mut ppu = new_sprite_zero_ppu([0xff, 2, 0, 0]);
+        write_oam(&mut ppu, &[[0xff, 2, 0, 0], [49, 2, 0, 20]]);
+        render_frame(&mut ppu, 0, 0, 0);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(!sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_no_sprite_zero_hit_at_x_255() {
+        let mut ppu = new_sprite_zero_ppu([49, 2, 0b0100_0000, 248]);
+        render_frame(&mut ppu, 0, 0, 0);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(!sprite_zero_hit(&ppu));
+
+        let mut ppu = new_sprite_zero_ppu([49, 2, 0b0100_0000, 247]);
+        render_frame(&mut ppu, 0, 0, 0);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_no_sprite_zero_hit_in_clipped_left_column() {
+        let mut ppu = new_sprite_zero_ppu([49, 2, 0, 7]);
+        render_frame(&mut ppu, 0, 0, 0);
+        ppu.write_to_mask_register(0b0001_1100);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(!sprite_zero_hit(&ppu));
+
+        ppu.write_to_mask_register(0b0001_1010);
+        run_frame(&mut ppu);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(!sprite_zero_hit(&ppu));
+
+        ppu.write_to_mask_register(0b0001_1110);
+        run_frame(&mut ppu);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(sprite_zero_hit(&ppu));
+
+        // right next to the left column it doesn't matter
+        let mut ppu = new_sprite_zero_ppu([49, 2, 0, 8]);
+        render_frame(&mut ppu, 0, 0, 0);
+        ppu.write_to_mask_register(0b0001_1000);
+        run_dots(&mut ppu, 240 * 341);
+        assert!(sprite_zero_hit(&ppu));
+    }
+
+    /// OAM with the given sprites at the start, the rest below the screen.
+    fn oam_with(sprites: &[[u8; 4]]) -> OamDataRegister {
+        let mut oam = OamDataRegister::new();
+        for addr in 0..=255 {
+            oam.write_data(addr, 0xff);
+        }
+        for (index, sprite) in sprites.iter().enumerate() {
+            for (byte, &value) in sprite.iter().enumerate() {
+                oam.write_data((index * 4 + byte) as u8, value);
+            }
+        }
+        oam
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let oam = oam_with(&[[0, 0, 0, 0]; 9]);
+        let (found, overflow) = evaluate(&oam, 0, 8, true);
+        assert_eq!(found.len(), 8);
+        assert!(overflow);
+
+        let (found, overflow) = evaluate(&oam, 0, 8, false);
+        assert_eq!(found.len(), 9);
+        assert!(overflow);
+
+        let oam = oam_with(&[[0, 0, 0, 0]; 8]);
+        assert!(!evaluate(&oam, 0, 8, true).1);
+    }
+
+    #[test]
+    fn test_sprite_overflow_hardware_bug() {
+        // the tile index of sprite 9 is read as the Y position, which puts it on the line
+        let mut sprites = vec![[0, 0, 0, 0]; 8];
+        sprites.extend([[0xff, 0xff, 0xff, 0xff], [0xff, 0x00, 0xff, 0xff]]);
+        assert!(evaluate(&oam_with(&sprites), 0, 8, true).1);
+
+        // the actual Y position of sprite 9 is skipped, so a ninth sprite on the line goes unnoticed
+        let mut sprites = vec![[0, 0, 0, 0]; 8];
+        sprites.extend([[0xff, 0xff, 0xff, 0xff], [0x00, 0xff, 0xff, 0xff]]);
+        let (found, overflow) = evaluate(&oam_with(&sprites), 0, 8, false);
+        assert_eq!(found.len(), 9);
+        assert!(!overflow);
+    }
+
+    fn sprite_overflow(ppu: &NesPPU) -> bool {
+        ppu.status.get() & 0b0010_0000 != 0
+    }
+
+    #[test]
+    fn test_sprite_overflow_flag() {
+        let mut ppu = new_chr_ram_ppu(Mirroring::HORIZONTAl);
+        write_ppu(&mut ppu, 0x0010, &[0xff; 8]);
+        write_ppu(&mut ppu, 0x3f00, &[0x0f]);
+        write_ppu(&mut ppu, 0x3f10, &[0x0f, 0x11]);
+        let sprites: Vec<[u8; 4]> = (0..9).map(|n| [99, 1, 0, n * 8]).collect();
+        write_oam(&mut ppu, &sprites);
+        render_frame(&mut ppu, 0, 0, 0);
+
+        // the sprites are found at the end of line 99
+        run_dots(&mut ppu, 99 * 341 + 256);
+        assert!(!sprite_overflow(&ppu));
+        run_dots(&mut ppu, 2);
+        assert!(sprite_overflow(&ppu));
+        run_dots(&mut ppu, (261 - 99) * 341 - 256);
+        assert!(!sprite_overflow(&ppu));
+        assert_eq!(pixel(&ppu, 64, 100), SYSTEM_PALETE[0x0f]);
+
+        // without the limit all sprites show up, the game still sees the overflow
+        ppu.set_sprite_limit(false);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 64, 100), SYSTEM_PALETE[0x11]);
+        run_dots(&mut ppu, 100 * 341);
+        assert!(sprite_overflow(&ppu));
+    }
+}
@@ -0,0 +1,252 @@
+//! Minimal BMP support, enough to exchange images with any image editor without pulling in an image library.
+//! Images are written as 8 bit indexed bitmaps, reading supports the usual uncompressed formats
+//! (1/4/8 bit indexed, 24/32 bit true color).
+//! https://en.wikipedia.org/wiki/BMP_file_format
+//!
+//! ```
+//! 0x00: "BM", file size, 4 reserved bytes, offset of the pixel data
+//! 0x0E: BITMAPINFOHEADER (40 bytes): header size, width, height (negative for top-down rows), planes,
+//!       bits per pixel, compression, image size, resolution (2x), colors used, important colors
+//! 0x36: color table (blue, green, red, 0) for indexed images
+//!       pixel rows, each padded to a multiple of 4 bytes, bottom row first
+//! ```
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+// 72 DPI in pixels per meter
+const RESOLUTION: u32 = 2835;
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// RGB colors, row by row starting at the top left.
+    pub pixels: Vec<(u8, u8, u8)>,
+}
+
+fn row_size(width: usize, bits_per_pixel: usize) -> usize {
+    (width * bits_per_pixel).div_ceil(32) * 4
+}
+
+/// Writes an 8 bit indexed bitmap, `indices` point into `palette` and are stored row by row from the top left.
+pub fn encode_indexed(
+    width: usize,
+    height: usize,
+    indices: &[u8],
+    palette: &[(u8, u8, u8)],
+) -> Vec<u8> {
+    assert_eq!(indices.len(), width * height);
+    assert!(palette.len() <= 256);
+
+    let row_size = row_size(width, 8);
+    let mut bmp = headers(width, height, 8, palette.len());
+    for &(red, green, blue) in palette {
+        bmp.extend([blue, green, red, 0]);
+    }
+    for row in indices.chunks(width).rev() {
+        bmp.extend(row);
+        bmp.resize(bmp.len() + row_size - width, 0);
+    }
+
+    bmp
+}
+
+/// Writes a 24 bit true color bitmap, `pixels` are stored row by row from the top left.
+pub fn encode_rgb(width: usize, height: usize, pixels: &[(u8, u8, u8)]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height);
+
+    let row_size = row_size(width, 24);
+    let mut bmp = headers(width, height, 24, 0);
+    for row in pixels.chunks(width).rev() {
+        for &(red, green, blue) in row {
+            bmp.extend([blue, green, red]);
+        }
+        bmp.resize(bmp.len() + row_size - width * 3, 0);
+    }
+
+    bmp
+}
+
+/// The file and info header of a bottom-up bitmap with `colors` entries in the color table.
+fn headers(width: usize, height: usize, bits_per_pixel: usize, colors: usize) -> Vec<u8> {
+    let image_size = row_size(width, bits_per_pixel) * height;
+    let pixel_offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE + colors * 4;
+    let file_size = pixel_offset + image_size;
+
+    let mut bmp = b"BM".to_vec();
+    bmp.extend((file_size as u32).to_le_bytes());
+    bmp.extend([0; 4]);
+    bmp.extend((pixel_offset as u32).to_le_bytes());
+
+    bmp.extend((INFO_HEADER_SIZE as u32).to_le_bytes());
+    bmp.extend((width as i32).to_le_bytes());
+    bmp.extend((height as i32).to_le_bytes());
+    bmp.extend(1u16.to_le_bytes());
+    bmp.extend((bits_per_pixel as u16).to_le_bytes());
+    bmp.extend(BI_RGB.to_le_bytes());
+    bmp.extend((image_size as u32).to_le_bytes());
+    bmp.extend(RESOLUTION.to_le_bytes());
+    bmp.extend(RESOLUTION.to_le_bytes());
+    bmp.extend((colors as u32).to_le_bytes());
+    bmp.extend((colors as u32).to_le_bytes());
+
+    bmp
+}
+
+pub fn decode(bmp: &[u8]) -> Result<Image, String> {
+    if bmp.len() < FILE_HEADER_SIZE + INFO_HEADER_SIZE || !bmp.starts_with(b"BM") {
+        return Err("Image is not a BMP file".to_string());
+    }
+    let u16_at = |pos: usize| u16::from_le_bytes([bmp[pos], bmp[pos + 1]]) as usize;
+    let u32_at =
+        |pos: usize| u32::from_le_bytes([bmp[pos], bmp[pos + 1], bmp[pos + 2], bmp[pos + 3]]);
+
+    let pixel_offset = u32_at(10) as usize;
+    let header_size = u32_at(14) as usize;
+    if header_size < INFO_HEADER_SIZE {
+        return Err("BMP files with an OS/2 header are not supported".to_string());
+    }
+    let width = u32_at(18) as i32;
+    let height = u32_at(22) as i32;
+    let bits_per_pixel = u16_at(28);
+    let compression = u32_at(30);
+    if width <= 0 || height == 0 {
+        return Err("BMP file has an invalid size".to_string());
+    }
+    if compression != BI_RGB && !(compression == BI_BITFIELDS && bits_per_pixel == 32) {
+        return Err("Compressed BMP files are not supported".to_string());
+    }
+
+    let color_table = match bits_per_pixel {
+        1 | 4 | 8 => {
+            let count = match u32_at(46) as usize {
+                0 => 1 << bits_per_pixel,
+                count => count,
+            };
+            let start = FILE_HEADER_SIZE + header_size;
+            let table = bmp
+                .get(start..start + count * 4)
+                .ok_or("BMP color table is truncated")?;
+            table
+                .chunks(4)
+                .map(|color| (color[2], color[1], color[0]))
+                .collect()
+        }
+        24 | 32 => vec![],
+        bits => {
+            return Err(format!(
+                "BMP files with {} bits per pixel are not supported",
+                bits
+            ))
+        }
+    };
+
+    let width = width as usize;
+    let top_down = height < 0;
+    let height = height.unsigned_abs() as usize;
+    let row_size = row_size(width, bits_per_pixel);
+    let data = bmp
+        .get(pixel_offset..pixel_offset + row_size * height)
+        .ok_or("BMP pixel data is truncated")?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row_index = if top_down { y } else { height - 1 - y };
+        let row = &data[row_index * row_size..(row_index + 1) * row_size];
+        for x in 0..width {
+            let pixel = match bits_per_pixel {
+                24 | 32 => {
+                    let pos = x * bits_per_pixel / 8;
+                    (row[pos + 2], row[pos + 1], row[pos])
+                }
+                bits => {
+                    let bit = x * bits;
+                    let index = (row[bit / 8] >> (8 - bits - bit % 8)) & ((1 << bits) - 1) as u8;
+                    *color_table
+                        .get(index as usize)
+                        .ok_or("BMP pixel points outside of the color table")?
+                }
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PALETTE: [(u8, u8, u8); 3] = [(0, 0, 0), (0xff, 0, 0), (0, 0x80, 0xff)];
+
+    #[test]
+    fn test_indexed_round_trip() {
+        // 3 pixels per row need a byte of padding
+        let indices = [0, 1, 2, 2, 1, 0];
+        let bmp = encode_indexed(3, 2, &indices, &PALETTE);
+        assert_eq!(bmp.len(), 14 + 40 + 3 * 4 + 2 * 4);
+
+        let image = decode(&bmp).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(
+            image.pixels,
+            indices
+                .iter()
+                .map(|&index| PALETTE[index as usize])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_rgb_round_trip() {
+        // 3 pixels per row take 9 bytes and need 3 bytes of padding
+        let pixels = [PALETTE, [PALETTE[2], PALETTE[0], PALETTE[1]]].concat();
+        let bmp = encode_rgb(3, 2, &pixels);
+        assert_eq!(bmp.len(), 14 + 40 + 2 * 12);
+
+        let image = decode(&bmp).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn test_decode_true_color_top_down() {
+        let mut bmp = encode_indexed(2, 2, &[0; 4], &[]);
+        bmp[22..26].copy_from_slice(&(-2i32).to_le_bytes());
+        bmp[28] = 24;
+        bmp.truncate(14 + 40);
+        // rows of 6 bytes padded to 8, blue green red
+        bmp.extend([0, 0, 0xff, 0xff, 0, 0, 0, 0]);
+        bmp.extend([0, 0xff, 0, 0xff, 0xff, 0xff, 0, 0]);
+
+        let image = decode(&bmp).unwrap();
+        assert_eq!(
+            image.pixels,
+            vec![(0xff, 0, 0), (0, 0, 0xff), (0, 0xff, 0), (0xff, 0xff, 0xff)]
+        );
+    }
+
+    #[test]
+    fn test_decode_4_bit() {
+        let mut bmp = encode_indexed(3, 1, &[0; 3], &PALETTE);
+        bmp[28] = 4;
+        let start = 14 + 40 + 3 * 4;
+        bmp[start..start + 2].copy_from_slice(&[0x12, 0x00]);
+
+        let image = decode(&bmp).unwrap();
+        assert_eq!(image.pixels, vec![PALETTE[1], PALETTE[2], PALETTE[0]]);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(decode(b"PNG").is_err());
+        let bmp = encode_indexed(2, 2, &[0; 4], &PALETTE);
+        assert!(decode(&bmp[..bmp.len() - 1]).is_err());
+    }
+}
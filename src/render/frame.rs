@@ -1,3 +1,5 @@
+use super::bmp;
+
 pub struct Frame {
     pub data: Vec<u8>,
 }
@@ -21,4 +23,14 @@ impl Frame {
             self.data[base + 2] = blue;
         }
     }
+
+    /// Encodes the picture as a 24 bit BMP file.
+    pub fn to_bmp(&self) -> Vec<u8> {
+        let pixels: Vec<_> = self
+            .data
+            .chunks(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+        bmp::encode_rgb(Frame::WIDTH, Frame::HEIGHT, &pixels)
+    }
 }
@@ -1,3 +1,8 @@
+use crate::ppu::registers::mask::Color;
+
+// emphasized colors keep their intensity while the other ones are darkened, roughly to 3/4
+const EMPHASIS_ATTENUATION: f32 = 0.75;
+
 // NES RGB value table
 pub static SYSTEM_PALETE: [(u8, u8, u8); 64] = [
     (0x80, 0x80, 0x80),
@@ -65,3 +70,29 @@ pub static SYSTEM_PALETE: [(u8, u8, u8); 64] = [
     (0x11, 0x11, 0x11),
     (0x11, 0x11, 0x11),
 ];
+
+/// The factor for each channel (red, green, blue) of a color under the emphasis of PPUMASK.
+pub fn emphasis_factors(emphasized: &[Color]) -> [f32; 3] {
+    if emphasized.is_empty() {
+        return [1.0; 3];
+    }
+
+    [Color::Red, Color::Green, Color::Blue].map(|color| {
+        if emphasized.contains(&color) {
+            1.0
+        } else {
+            EMPHASIS_ATTENUATION
+        }
+    })
+}
+
+/// Applies the [`emphasis_factors`] to a color of the [`SYSTEM_PALETE`].
+pub fn emphasize(rgb: (u8, u8, u8), factors: &[f32; 3]) -> (u8, u8, u8) {
+    let (red, green, blue) = rgb;
+    let [red_factor, green_factor, blue_factor] = *factors;
+    (
+        (red as f32 * red_factor) as u8,
+        (green as f32 * green_factor) as u8,
+        (blue as f32 * blue_factor) as u8,
+    )
+}
@@ -0,0 +1,449 @@
+//! Command line tools that work on ROM files instead of running them.
+
+use std::path::Path;
+
+use crate::{
+    cartridge::{hash, DatabaseMatch, GameDatabase, Mirroring, Region, Rom, RomFormat},
+    mapper, parse_number,
+    render::{bmp, palette::SYSTEM_PALETE},
+    tile_viewer::{self, TileSheet},
+    USAGE,
+};
+
+/// Black, two greys and white, so tiles can be told apart no matter which palette a game uses for them.
+const DEFAULT_SHEET_PALETTE: [u8; 4] = [0x0f, 0x00, 0x10, 0x30];
+
+enum Value {
+    Text(String),
+    Number(usize),
+    /// A size in bytes.
+    Size(usize),
+    Flag(bool),
+    List(Vec<String>),
+    Missing,
+}
+
+impl Value {
+    fn to_text(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Number(number) => number.to_string(),
+            Value::Size(0) => "none".to_string(),
+            Value::Size(size) if size & 0x3ff == 0 => format!("{} KiB", size / 1024),
+            Value::Size(size) => format!("{} bytes", size),
+            Value::Flag(true) => "yes".to_string(),
+            Value::Flag(false) => "no".to_string(),
+            Value::List(items) if items.is_empty() => "none".to_string(),
+            Value::List(items) => items.join(", "),
+            Value::Missing => "-".to_string(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Value::Text(text) => json_string(text),
+            Value::Number(number) | Value::Size(number) => number.to_string(),
+            Value::Flag(flag) => flag.to_string(),
+            Value::List(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| json_string(item))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Missing => "null".to_string(),
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Everything known about a ROM as (label, JSON key, value).
+fn describe(
+    path: &str,
+    rom: &Rom,
+    database: Option<&DatabaseMatch>,
+) -> Vec<(&'static str, &'static str, Value)> {
+    let mapper_name = mapper::name(rom.mapper, rom.submapper);
+
+    vec![
+        ("File", "file", Value::Text(path.to_string())),
+        (
+            "Format",
+            "format",
+            Value::Text(rom.format.name().to_string()),
+        ),
+        ("Mapper", "mapper", Value::Number(rom.mapper as usize)),
+        (
+            "Submapper",
+            "submapper",
+            Value::Number(rom.submapper as usize),
+        ),
+        (
+            "Board",
+            "board",
+            mapper_name.map_or(Value::Missing, |name| Value::Text(name.to_string())),
+        ),
+        (
+            "Supported",
+            "supported",
+            Value::Flag(mapper::is_supported(rom.mapper)),
+        ),
+        ("PRG ROM", "prg_rom_size", Value::Size(rom.prg_rom.len())),
+        ("CHR ROM", "chr_rom_size", Value::Size(rom.chr_rom.len())),
+        ("PRG RAM", "prg_ram_size", Value::Size(rom.prg_ram_size)),
+        (
+            "PRG NVRAM",
+            "prg_nvram_size",
+            Value::Size(rom.prg_nvram_size),
+        ),
+        ("CHR RAM", "chr_ram_size", Value::Size(rom.chr_ram_size)),
+        (
+            "CHR NVRAM",
+            "chr_nvram_size",
+            Value::Size(rom.chr_nvram_size),
+        ),
+        (
+            "Mirroring",
+            "mirroring",
+            Value::Text(rom.screen_mirroring.name().to_string()),
+        ),
+        ("Battery", "battery", Value::Flag(rom.battery)),
+        ("Trainer", "trainer", Value::Flag(rom.trainer.is_some())),
+        (
+            "Region",
+            "region",
+            Value::Text(rom.region.name().to_string()),
+        ),
+        (
+            "CRC-32",
+            "crc32",
+            Value::Text(format!("{:08X}", rom.crc32())),
+        ),
+        ("SHA-1", "sha1", Value::Text(hash::to_hex(&rom.sha1()))),
+        (
+            "Database",
+            "database_match",
+            database.map_or(Value::Missing, |game| Value::Text(game.name.clone())),
+        ),
+        (
+            "Corrections",
+            "database_corrections",
+            Value::List(database.map_or(vec![], |game| game.corrections.clone())),
+        ),
+    ]
+}
+
+fn to_text(fields: &[(&str, &str, Value)]) -> String {
+    fields
+        .iter()
+        .map(|(label, _, value)| format!("{:<13}{}\n", format!("{}:", label), value.to_text()))
+        .collect()
+}
+
+fn to_json(fields: &[(&str, &str, Value)]) -> String {
+    let fields = fields
+        .iter()
+        .map(|(_, key, value)| format!("  {}: {}", json_string(key), value.to_json()))
+        .collect::<Vec<_>>();
+    format!("{{\n{}\n}}\n", fields.join(",\n"))
+}
+
+/// Prints the header and cartridge details of a ROM, after the corrections of the game database passed with
+/// `--gamedb` (only the bundled entries without it).
+pub fn info(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut json = false;
+    let mut database = GameDatabase::bundled();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--gamedb" => database = GameDatabase::with_file(args.next().ok_or(USAGE)?)?,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let mut rom = Rom::new(&bytes)?;
+    let database = rom.apply_database(&database);
+    let fields = describe(path, &rom, database.as_ref());
+
+    if json {
+        print!("{}", to_json(&fields));
+    } else {
+        print!("{}", to_text(&fields));
+    }
+    Ok(())
+}
+
+/// Parses four comma separated NES color indices in hex, e.g. "0f,00,10,30".
+fn parse_palette(arg: Option<&String>) -> Result<[(u8, u8, u8); 4], String> {
+    let colors = arg
+        .ok_or(USAGE)?
+        .split(',')
+        .map(|color| {
+            u8::from_str_radix(color.trim(), 16)
+                .ok()
+                .filter(|&color| color < 0x40)
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or("Palette colors have to be hex numbers from 00 to 3f")?;
+    let colors: [u8; 4] = colors
+        .try_into()
+        .map_err(|_| "The palette needs exactly four colors".to_string())?;
+    Ok(colors.map(|color| SYSTEM_PALETE[color as usize]))
+}
+
+fn default_sheet_palette() -> [(u8, u8, u8); 4] {
+    DEFAULT_SHEET_PALETTE.map(|color| SYSTEM_PALETE[color as usize])
+}
+
+/// Index of the palette color closest to `color`, editors may change colors slightly when saving.
+fn nearest_color(palette: &[(u8, u8, u8); 4], color: (u8, u8, u8)) -> u8 {
+    let distance = |other: &(u8, u8, u8)| {
+        let delta = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        delta(color.0, other.0) + delta(color.1, other.1) + delta(color.2, other.2)
+    };
+    (0..4)
+        .min_by_key(|&index| distance(&palette[index]))
+        .unwrap() as u8
+}
+
+/// Writes all CHR ROM banks as one tile sheet, an indexed BMP with the given palette.
+pub fn export_chr(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut out = None;
+    let mut palette = default_sheet_palette();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = Some(args.next().ok_or(USAGE)?.clone()),
+            "--palette" => palette = parse_palette(args.next())?,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let out = out.unwrap_or_else(|| format!("{}.bmp", path));
+
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let rom = Rom::new(&bytes)?;
+    if rom.chr_rom.is_empty() {
+        return Err(format!(
+            "{} has no CHR ROM, its graphics are in PRG ROM",
+            path
+        ));
+    }
+
+    let sheet = tile_viewer::chr_to_sheet(&rom.chr_rom);
+    let image = bmp::encode_indexed(sheet.width, sheet.height, &sheet.pixels, &palette);
+    std::fs::write(&out, image).map_err(|e| format!("Can't write {}: {}", out, e))?;
+    println!("Wrote {} tiles to {}", rom.chr_rom.len() / 16, out);
+    Ok(())
+}
+
+/// Replaces the CHR ROM of a game with an edited tile sheet and writes the result as a new file.
+/// The colors of the sheet are mapped to the closest color of the palette used for the export.
+pub fn import_chr(args: &[String]) -> Result<(), String> {
+    let mut paths = vec![];
+    let mut out = None;
+    let mut palette = default_sheet_palette();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = Some(args.next().ok_or(USAGE)?.clone()),
+            "--palette" => palette = parse_palette(args.next())?,
+            _ if paths.len() < 2 => paths.push(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let [path, sheet_path] = paths[..] else {
+        return Err(USAGE.to_string());
+    };
+    let out = out.unwrap_or_else(|| {
+        Path::new(path)
+            .with_extension("chr.nes")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let rom = Rom::new(&bytes)?;
+    if rom.format == RomFormat::Unif {
+        return Err("Only iNES files can be written".to_string());
+    }
+    if rom.chr_rom.is_empty() {
+        return Err(format!("{} has no CHR ROM to replace", path));
+    }
+
+    let image = std::fs::read(sheet_path)
+        .map_err(|e| format!("Can't read {}: {}", sheet_path, e))
+        .and_then(|image| bmp::decode(&image))?;
+    let sheet = TileSheet {
+        width: image.width,
+        height: image.height,
+        pixels: image
+            .pixels
+            .iter()
+            .map(|&color| nearest_color(&palette, color))
+            .collect(),
+    };
+    let chr = tile_viewer::sheet_to_chr(&sheet, rom.chr_rom.len())?;
+
+    // the CHR ROM follows the header, the optional trainer and the PRG ROM
+    let chr_start = 16 + rom.trainer.as_ref().map_or(0, Vec::len) + rom.prg_rom.len();
+    let mut patched = bytes;
+    patched[chr_start..chr_start + chr.len()].copy_from_slice(&chr);
+    std::fs::write(&out, patched).map_err(|e| format!("Can't write {}: {}", out, e))?;
+    println!("Wrote {}", out);
+    Ok(())
+}
+
+/// Changes header fields of a game and writes the result as a new file, the original file isn't touched.
+/// The ROM data stays as it is, so the ROM sizes always match the data.
+pub fn edit_header(args: &[String]) -> Result<(), String> {
+    let mut args = args.iter();
+    let path = args.next().ok_or(USAGE)?;
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let mut rom = Rom::new(&bytes)?;
+    let mut out = None;
+
+    let name = |arg: Option<&String>| arg.cloned().ok_or_else(|| USAGE.to_string());
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mapper" => {
+                let mapper = parse_number(args.next())?;
+                rom.mapper =
+                    u16::try_from(mapper).map_err(|_| format!("Invalid mapper {}", mapper))?;
+            }
+            "--submapper" => {
+                let submapper = parse_number(args.next())?;
+                rom.submapper = u8::try_from(submapper)
+                    .map_err(|_| format!("Invalid submapper {}", submapper))?;
+            }
+            "--mirroring" => {
+                let mirroring = name(args.next())?;
+                rom.screen_mirroring = Mirroring::from_name(&mirroring)
+                    .ok_or(format!("Unknown mirroring {}", mirroring))?;
+            }
+            "--battery" => {
+                rom.battery = match name(args.next())?.as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(USAGE.to_string()),
+                }
+            }
+            "--prg-ram" => rom.prg_ram_size = parse_number(args.next())?,
+            "--prg-nvram" => rom.prg_nvram_size = parse_number(args.next())?,
+            "--chr-ram" => rom.chr_ram_size = parse_number(args.next())?,
+            "--chr-nvram" => rom.chr_nvram_size = parse_number(args.next())?,
+            "--region" => {
+                let region = name(args.next())?;
+                rom.region =
+                    Region::from_name(&region).ok_or(format!("Unknown region {}", region))?;
+            }
+            "--nes2" => rom.format = RomFormat::Nes2,
+            "--out" => out = Some(name(args.next())?),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let out = out.unwrap_or_else(|| {
+        Path::new(path)
+            .with_extension("fixed.nes")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    std::fs::write(&out, rom.to_bytes()?).map_err(|e| format!("Can't write {}: {}", out, e))?;
+    println!("Wrote {}", out);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nestest_info() {
+        let mut rom = Rom::new(&std::fs::read("nestest.nes").unwrap()).unwrap();
+        let database = rom.apply_database(&GameDatabase::bundled());
+        let fields = describe("nestest.nes", &rom, database.as_ref());
+
+        let text = to_text(&fields);
+        assert!(text.starts_with("File:        nestest.nes\nFormat:      iNES\n"));
+        assert!(text.contains("Board:       NROM\n"));
+        assert!(text.contains("PRG ROM:     16 KiB\n"));
+        assert!(text.contains("CHR RAM:     none\n"));
+        assert!(text.contains("CRC-32:      158B0388\n"));
+        assert!(text.contains("Database:    nestest\nCorrections: none\n"));
+
+        let json = to_json(&fields);
+        assert!(json.starts_with("{\n  \"file\": \"nestest.nes\",\n  \"format\": \"iNES\",\n"));
+        assert!(json.contains("  \"prg_rom_size\": 16384,\n"));
+        assert!(json.contains("  \"battery\": false,\n"));
+        assert!(json.ends_with("  \"database_corrections\": []\n}\n"));
+    }
+
+    #[test]
+    fn test_header_values_out_of_range() {
+        let args = |flag: &str, value: &str| {
+            ["nestest.nes", flag, value, "--out", "unused.nes"].map(String::from)
+        };
+        assert_eq!(
+            edit_header(&args("--mapper", "65540")).err(),
+            Some("Invalid mapper 65540".to_string())
+        );
+        assert_eq!(
+            edit_header(&args("--submapper", "256")).err(),
+            Some("Invalid submapper 256".to_string())
+        );
+    }
+
+    #[test]
+    fn test_json_values() {
+        assert_eq!(
+            json_string("a \"b\"\\\n\u{1}"),
+            "\"a \\\"b\\\"\\\\\\n\\u0001\""
+        );
+        assert_eq!(Value::Missing.to_json(), "null");
+        assert_eq!(
+            Value::List(vec!["mapper 1 -> 4".to_string(), "x".to_string()]).to_json(),
+            "[\"mapper 1 -> 4\", \"x\"]"
+        );
+        assert_eq!(Value::Size(0x2200).to_text(), "8704 bytes");
+        assert_eq!(Value::Size(0x2000).to_text(), "8 KiB");
+    }
+
+    #[test]
+    fn test_palette() {
+        let palette = parse_palette(Some(&"0f, 16,27,30".to_string())).unwrap();
+        assert_eq!(palette[1], SYSTEM_PALETE[0x16]);
+        assert!(parse_palette(Some(&"0f,16,27".to_string())).is_err());
+        assert!(parse_palette(Some(&"0f,16,27,40".to_string())).is_err());
+
+        let palette = default_sheet_palette();
+        assert_eq!(nearest_color(&palette, palette[2]), 2);
+        assert_eq!(nearest_color(&palette, (0xfa, 0xfa, 0xfa)), 3);
+        assert_eq!(nearest_color(&palette, (0x10, 0x08, 0x00)), 0);
+    }
+}
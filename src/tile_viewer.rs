@@ -1,40 +1,128 @@
-use crate::render::{frame::Frame, palette::SYSTEM_PALETE};
+//! CHR data laid out as a sheet of tiles so it can be viewed and edited as an image. The sheet is 16 tiles wide,
+//! so each 4 KiB pattern table is a 128x128 pixel block and the pattern tables are stacked on top of each other.
+//! Pixels are the 2 bit color indices of the tiles, the palette is up to whoever displays the sheet.
 
-fn show_tile(chr_rom: &[u8], bank: usize, tile_n: usize) -> Frame {
-    assert!(bank <= 1);
+const TILE_SIZE: usize = 16; // bytes per 8x8 tile
+const SHEET_COLUMNS: usize = 16;
+pub const SHEET_WIDTH: usize = SHEET_COLUMNS * 8;
 
-    let mut frame = Frame::new();
-    let bank = bank * 0x1000; // 0x1000 = 4096 in decimal
+pub struct TileSheet {
+    pub width: usize,
+    pub height: usize,
+    /// Color index (0 - 3) of every pixel, row by row starting at the top left.
+    pub pixels: Vec<u8>,
+}
 
-    let start = bank + tile_n * 16;
-    let tile = &chr_rom[start..=(start + 15)];
+/// Color indices of the 8x8 pixels of a tile.
+pub fn decode_tile(tile: &[u8]) -> [[u8; 8]; 8] {
+    let mut pixels = [[0; 8]; 8];
 
     // a tile is described using 16 bytes and each row is encoded using 2 bytes that stand 8 byte apart
     // to calculate the color index of the top left pixel you read the 7th bit of 0x0000 (left) = 0
     // and the 7th bit of 0x0008 (right) = 1 and then combine them: (right 7th bit)(left 7th bit) = 10
     // then repeat analog for next pixel (i.e. go to the 6th bit)
-    for y in 0..=7 {
-        let mut left = tile[y]; // 0x0000
-        let mut right = tile[y + 8]; // 0x0008
-
-        for x in (0..=7).rev() {
-            // TODO: I have adjusted this to fit with the explanation instead of the guide
-            // the guide has right and left flipped around
-            let value = (1 & right) << 1 | (1 & left);
-            left >>= 1;
-            right >>= 1;
-
-            // TODO: colors are currently just picked by me and not specified using the palette_table
-            let rgb = match value {
-                0 => SYSTEM_PALETE[0x01],
-                1 => SYSTEM_PALETE[0x23],
-                2 => SYSTEM_PALETE[0x27],
-                3 => SYSTEM_PALETE[0x30],
-                _ => panic!("Theoretically unreachable code was reached"),
-            };
-            frame.set_pixel(x, y, rgb);
+    for (y, row) in pixels.iter_mut().enumerate() {
+        let left = tile[y]; // 0x0000
+        let right = tile[y + 8]; // 0x0008
+
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let bit = 7 - x;
+            *pixel = (right >> bit & 1) << 1 | (left >> bit & 1);
+        }
+    }
+
+    pixels
+}
+
+/// The inverse of [`decode_tile`], splits the color indices into the two bit planes.
+pub fn encode_tile(pixels: &[[u8; 8]; 8]) -> [u8; TILE_SIZE] {
+    let mut tile = [0; TILE_SIZE];
+
+    for (y, row) in pixels.iter().enumerate() {
+        for (x, &pixel) in row.iter().enumerate() {
+            let bit = 7 - x;
+            tile[y] |= (pixel & 1) << bit;
+            tile[y + 8] |= (pixel >> 1 & 1) << bit;
+        }
+    }
+
+    tile
+}
+
+pub fn chr_to_sheet(chr: &[u8]) -> TileSheet {
+    let rows = chr.len().div_ceil(TILE_SIZE * SHEET_COLUMNS);
+    let mut sheet = TileSheet {
+        width: SHEET_WIDTH,
+        height: rows * 8,
+        pixels: vec![0; SHEET_WIDTH * rows * 8],
+    };
+
+    for (n, tile) in chr.chunks_exact(TILE_SIZE).enumerate() {
+        let (left, top) = ((n % SHEET_COLUMNS) * 8, (n / SHEET_COLUMNS) * 8);
+        for (y, row) in decode_tile(tile).iter().enumerate() {
+            let start = (top + y) * SHEET_WIDTH + left;
+            sheet.pixels[start..start + 8].copy_from_slice(row);
+        }
+    }
+
+    sheet
+}
+
+/// Encodes the tiles of a sheet back into `size` bytes of CHR data.
+pub fn sheet_to_chr(sheet: &TileSheet, size: usize) -> Result<Vec<u8>, String> {
+    let rows = size.div_ceil(TILE_SIZE * SHEET_COLUMNS);
+    if sheet.width != SHEET_WIDTH || sheet.height != rows * 8 {
+        return Err(format!(
+            "Tile sheet has to be {}x{} pixels, got {}x{}",
+            SHEET_WIDTH,
+            rows * 8,
+            sheet.width,
+            sheet.height
+        ));
+    }
+
+    let mut chr = Vec::with_capacity(size);
+    for n in 0..size / TILE_SIZE {
+        let (left, top) = ((n % SHEET_COLUMNS) * 8, (n / SHEET_COLUMNS) * 8);
+        let mut pixels = [[0; 8]; 8];
+        for (y, row) in pixels.iter_mut().enumerate() {
+            let start = (top + y) * SHEET_WIDTH + left;
+            row.copy_from_slice(&sheet.pixels[start..start + 8]);
         }
+        chr.extend(encode_tile(&pixels));
     }
 
-    frame
+    Ok(chr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_tile() {
+        // the "1/2" example from https://www.nesdev.org/wiki/PPU_pattern_tables
+        let mut tile = [0; 16];
+        tile[0] = 0b0100_0001;
+        tile[8] = 0b1100_0010;
+
+        let pixels = decode_tile(&tile);
+        assert_eq!(pixels[0], [2, 3, 0, 0, 0, 0, 2, 1]);
+        assert_eq!(pixels[1], [0; 8]);
+        assert_eq!(encode_tile(&pixels), tile);
+    }
+
+    #[test]
+    fn test_sheet_round_trip() {
+        let chr: Vec<u8> = (0..0x2000).map(|i| (i * 7 % 251) as u8).collect();
+        let sheet = chr_to_sheet(&chr);
+        assert_eq!((sheet.width, sheet.height), (128, 256));
+
+        // tile 17 starts in the second row of tiles, second column
+        let tile = decode_tile(&chr[17 * 16..18 * 16]);
+        assert_eq!(sheet.pixels[8 * 128 + 8..8 * 128 + 16], tile[0]);
+
+        assert_eq!(sheet_to_chr(&sheet, chr.len()), Ok(chr));
+        assert!(sheet_to_chr(&sheet, 0x4000).is_err());
+    }
 }